@@ -0,0 +1,93 @@
+// Fold-loop Micro-benchmark
+//
+// Times `K_BulletProof::create` and `verification_scalars` on their own,
+// to compare curve backends:
+//
+//     cargo bench --bench fold
+//     cargo +nightly bench --bench fold --features simd
+
+#![allow(non_snake_case)]
+
+extern crate bulletproofs;
+use bulletproofs::prelude::{BulletproofGens, K_BulletProof};
+
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+extern crate curve25519_dalek;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+extern crate merlin;
+use merlin::Transcript;
+
+extern crate rand;
+
+extern crate sha3;
+use sha3::Sha3_512;
+
+const N: usize = 1024;
+const CASES: [(usize, usize); 3] = [(2, 10), (4, 5), (32, 2)];
+
+struct FoldInstance {
+    G: Vec<RistrettoPoint>,
+    H: Vec<RistrettoPoint>,
+    Q: RistrettoPoint,
+    a: Vec<Scalar>,
+    b: Vec<Scalar>,
+}
+
+fn fold_instance() -> FoldInstance {
+    let mut rng = rand::thread_rng();
+    let bp_gens = BulletproofGens::new(N, 1);
+    FoldInstance {
+        G: bp_gens.G_vec[0].clone(),
+        H: bp_gens.H_vec[0].clone(),
+        Q: RistrettoPoint::hash_from_bytes::<Sha3_512>(b"fold bench point"),
+        a: (0..N).map(|_| Scalar::random(&mut rng)).collect(),
+        b: (0..N).map(|_| Scalar::random(&mut rng)).collect(),
+    }
+}
+
+fn create_fold(c: &mut Criterion) {
+    for &(k, num_rounds) in CASES.iter() {
+        let instance = fold_instance();
+        let label = format!("K_BulletProof::create n={} k={}", N, k);
+        c.bench_function(&label, move |b| {
+            b.iter(|| {
+                let mut transcript = Transcript::new(b"FoldBench");
+                K_BulletProof::create(
+                    &mut transcript, k, &instance.G, &instance.H, instance.Q,
+                    &instance.a, &instance.b, num_rounds,
+                )
+            })
+        });
+    }
+}
+
+fn verification_scalars(c: &mut Criterion) {
+    for &(k, num_rounds) in CASES.iter() {
+        let instance = fold_instance();
+        let mut transcript = Transcript::new(b"FoldBench");
+        let proof = K_BulletProof::create(
+            &mut transcript, k, &instance.G, &instance.H, instance.Q,
+            &instance.a, &instance.b, num_rounds,
+        );
+        let label = format!("K_BulletProof::verification_scalars n={} k={}", N, k);
+        c.bench_function(&label, move |b| {
+            b.iter(|| {
+                let mut transcript = Transcript::new(b"FoldBench");
+                proof.verification_scalars(N, &mut transcript).unwrap()
+            })
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = create_fold, verification_scalars,
+}
+
+criterion_main!(benches);
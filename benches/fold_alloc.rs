@@ -0,0 +1,113 @@
+// Fold-loop Allocation Benchmark
+//
+// Counts the heap allocations `K_BulletProof::create` makes per proof and
+// times it under a counting allocator. Most of the count is scratch space
+// `vartime_multiscalar_mul` allocates on every call; the fold loop itself
+// only adds the `U_vecs` entry each round keeps in the proof:
+//
+//     cargo bench --bench fold_alloc
+
+#![allow(non_snake_case)]
+
+extern crate bulletproofs;
+use bulletproofs::prelude::{BulletproofGens, K_BulletProof};
+
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+extern crate curve25519_dalek;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+extern crate merlin;
+use merlin::Transcript;
+
+extern crate rand;
+
+extern crate sha3;
+use sha3::Sha3_512;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Forwards to the system allocator, counting every allocation.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// `(n, k, num_rounds)`; the odd sizes force padding rounds.
+const CASES: [(usize, usize, usize); 4] = [(1024, 2, 10), (1024, 4, 5), (1000, 3, 7), (4096, 4, 6)];
+
+struct FoldInstance {
+    G: Vec<RistrettoPoint>,
+    H: Vec<RistrettoPoint>,
+    Q: RistrettoPoint,
+    a: Vec<Scalar>,
+    b: Vec<Scalar>,
+}
+
+fn fold_instance(n: usize) -> FoldInstance {
+    let mut rng = rand::thread_rng();
+    let bp_gens = BulletproofGens::new(n, 1);
+    FoldInstance {
+        G: bp_gens.G_vec[0].clone(),
+        H: bp_gens.H_vec[0].clone(),
+        Q: RistrettoPoint::hash_from_bytes::<Sha3_512>(b"fold alloc bench point"),
+        a: (0..n).map(|_| Scalar::random(&mut rng)).collect(),
+        b: (0..n).map(|_| Scalar::random(&mut rng)).collect(),
+    }
+}
+
+fn create(instance: &FoldInstance, k: usize, num_rounds: usize) -> K_BulletProof {
+    let mut transcript = Transcript::new(b"FoldAllocBench");
+    K_BulletProof::create(
+        &mut transcript, k, &instance.G, &instance.H, instance.Q,
+        &instance.a, &instance.b, num_rounds,
+    )
+}
+
+fn create_allocations(c: &mut Criterion) {
+    for &(n, k, num_rounds) in CASES.iter() {
+        let instance = fold_instance(n);
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let proof = create(&instance, k, num_rounds);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        drop(proof);
+        println!(
+            "K_BulletProof::create n={} k={} rounds={}: {} allocations",
+            n, k, num_rounds, allocations
+        );
+
+        let label = format!("K_BulletProof::create (counting alloc) n={} k={}", n, k);
+        c.bench_function(&label, move |b| b.iter(|| create(&instance, k, num_rounds)));
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = create_allocations,
+}
+
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+// Precomputed-generators Verification Benchmark
+//
+// Times `ShuffleProof::verify` against `ShuffleProof::verify_precomputed`
+// on the same proof, with the generator tables built once up front:
+//
+//     cargo bench --bench precomputed --features yoloproofs
+
+#![allow(non_snake_case)]
+
+extern crate bulletproofs;
+use bulletproofs::r1cs::{ProofConfig, ShuffleClaim, ShuffleProof, ShuffleStatement, ShuffleWitness};
+use bulletproofs::{BulletproofGens, PedersenGens, PrecomputedGens};
+
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+extern crate curve25519_dalek;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+
+extern crate merlin;
+use merlin::Transcript;
+
+extern crate rand;
+
+const CASES: [(usize, usize, usize); 3] = [(64, 4, 3), (256, 4, 4), (1024, 4, 5)];
+
+/// Proves a reversal of `n` re-randomized ciphertexts.
+fn verify_instance(
+    pc_gens: &PedersenGens,
+    bp_gens: &BulletproofGens,
+    n: usize,
+    k: usize,
+    d: usize,
+) -> ShuffleClaim {
+    let mut rng = rand::thread_rng();
+    let input: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+    let output: Vec<Scalar> = input.iter().rev().cloned().collect();
+    let C1: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+    let C2: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+
+    let mut C1_prime = Vec::with_capacity(n);
+    let mut C2_prime = Vec::with_capacity(n);
+    let mut r_prime = Scalar::zero();
+    for i in (0..n).rev() {
+        let r_i = Scalar::random(&mut rng);
+        C1_prime.push(C1[i] + pc_gens.B * r_i);
+        C2_prime.push(C2[i] + pc_gens.B_blinding * r_i);
+        r_prime -= r_i * input[i];
+    }
+
+    let statement = ShuffleStatement { input, C1, C2, C1_prime, C2_prime };
+    let witness = ShuffleWitness::new(statement, output, r_prime);
+    let mut transcript = Transcript::new(b"PrecomputedBench");
+    let (proof, output_commitment) = ShuffleProof::prove(
+        pc_gens, bp_gens, &mut transcript, &[], &witness, &ProofConfig::new(n, k, d),
+    )
+    .unwrap();
+    ShuffleClaim { proof, statement: witness.statement, nonce: Vec::new(), output_commitment }
+}
+
+fn verify(c: &mut Criterion) {
+    for &(n, k, d) in CASES.iter() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let claim = verify_instance(&pc_gens, &bp_gens, n, k, d);
+        let label = format!("ShuffleProof::verify n={} k={}", n, k);
+        c.bench_function(&label, move |b| {
+            b.iter(|| {
+                let mut transcript = Transcript::new(b"PrecomputedBench");
+                ShuffleProof::verify(&pc_gens, &bp_gens, &mut transcript, &claim).unwrap()
+            })
+        });
+    }
+}
+
+fn verify_precomputed(c: &mut Criterion) {
+    for &(n, k, d) in CASES.iter() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let table = PrecomputedGens::new(&bp_gens, n);
+        let claim = verify_instance(&pc_gens, &bp_gens, n, k, d);
+        let label = format!("ShuffleProof::verify_precomputed n={} k={}", n, k);
+        c.bench_function(&label, move |b| {
+            b.iter(|| {
+                let mut transcript = Transcript::new(b"PrecomputedBench");
+                ShuffleProof::verify_precomputed(&pc_gens, &bp_gens, &table, &mut transcript, &claim)
+                    .unwrap()
+            })
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = verify, verify_precomputed,
+}
+
+criterion_main!(benches);
@@ -12,6 +12,18 @@ pub enum ProofError {
     /// This error occurs when the proof encoding is malformed.
     #[fail(display = "Proof data could not be parsed.")]
     FormatError,
+    /// This error occurs when a folding proof's encoding ends before all
+    /// of the rounds its header declares.
+    #[fail(display = "Proof data is truncated within its folding rounds.")]
+    MissingRounds,
+    /// This error occurs when a folding proof's encoding holds every
+    /// round its header declares, but ends before the final vectors.
+    #[fail(display = "Proof data is truncated within its final vectors.")]
+    MissingFinalVectors,
+    /// This error occurs when the proof encoding only parses once its
+    /// `u64` headers are read as big-endian instead of little-endian.
+    #[fail(display = "Proof headers are big-endian; expected little-endian.")]
+    EndiannessMismatch,
     /// This error occurs during proving if the number of blinding
     /// factors does not match the number of values.
     #[fail(display = "Wrong number of blinding factors supplied.")]
@@ -97,8 +109,11 @@ pub enum MPCError {
 #[cfg(feature = "yoloproofs")]
 #[derive(Fail, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum R1CSError {
-     ///
-    #[fail(display = "Shuffle zize must be more than 1.")]
+    /// Occurs when a shuffle witness has fewer than 2 entries, or its
+    /// length doesn't match the statement.  A single ciphertext is
+    /// shuffled by zero-padding its witness to 2 entries, which
+    /// [`ShuffleParams`](::r1cs::ShuffleParams) does automatically.
+    #[fail(display = "Shuffle witness must have at least 2 entries; zero-pad a single ciphertext's witness.")]
     InputLengthError,
     /// Occurs when there are insufficient generators for the proof.
     #[fail(display = "Invalid generators size, too few generators for proof")]
@@ -108,6 +123,96 @@ pub enum R1CSError {
     #[fail(display = "R1CSProof did not verify correctly.")]
     VerificationError,
 
+    /// Occurs when a proof is malformed or claims sizes beyond the
+    /// limits the verifier was configured with.
+    #[fail(display = "R1CSProof is malformed or exceeds the verifier's size limits.")]
+    FormatError,
+
+    /// Occurs when the shuffle ciphertext vectors differ in length, are
+    /// longer than the witness, or were padded instead of the witness.
+    #[fail(display = "Ciphertext vectors must both have k_original entries and must not be padded.")]
+    InvalidCiphertextLength,
+
+    /// Occurs when the prover's polynomial vectors are shorter than the
+    /// shuffle size they are combined over.
+    #[fail(display = "Polynomial vector lengths do not match the shuffle size.")]
+    PolynomialLengthMismatch,
+
+    /// Occurs when a shuffle permutation repeats an index or points past
+    /// the ciphertexts.
+    #[fail(display = "Permutation is not a bijection on the ciphertext indices.")]
+    InvalidPermutation,
+
+    /// Occurs when a byte payload is not the canonical encoding of a
+    /// scalar, so it cannot be shuffled without being altered.
+    #[fail(display = "Payload is not a canonical scalar encoding.")]
+    NonCanonicalPayload,
+
+    /// Occurs when an original ciphertext is missing a valid inclusion
+    /// proof against the Merkle root the verifier holds.
+    #[fail(display = "Ciphertext inclusion proof does not match the Merkle root.")]
+    InvalidInclusionProof,
+
+    /// Occurs when [`ShuffleParams`](::r1cs::ShuffleParams) are given a
+    /// folding factor below 2, or more folding rounds than it takes to
+    /// fold the witness down to a single element.
+    #[fail(display = "Folding factor and round count are inconsistent with the shuffle size.")]
+    InvalidFoldParameters,
+
+    /// Occurs when a range-checked shuffle asks for a bit width outside
+    /// `1..=64`, or its witness is shorter than
+    /// [`ShuffleProof::range_witness_len`](::r1cs::ShuffleProof::range_witness_len).
+    #[fail(display = "Range bit width must be 1 to 64, with the witness padded to fit the range constraints.")]
+    InvalidRangeParameters,
+
+    /// Occurs when a shuffle challenge supplied from outside the proof's
+    /// transcript equals one of the public inputs, which would zero the
+    /// permutation check's product.
+    #[fail(display = "External shuffle challenge equals a public input.")]
+    InvalidExternalChallenge,
+
+    /// Occurs when the shuffled ciphertexts decrypt to a different
+    /// multiset of plaintexts than the original ciphertexts.
+    #[fail(display = "Shuffled ciphertexts do not decrypt to the original plaintexts.")]
+    DecryptionMismatch,
+
+    /// Occurs when a hop of a [`ShuffleChain`](::r1cs::ShuffleChain)
+    /// does not take the previous hop's shuffled ciphertexts as its
+    /// original ciphertexts.
+    #[fail(display = "Hop {} of the shuffle chain does not shuffle the previous hop's output.", hop)]
+    BrokenChainLink {
+        /// The index of the first hop whose originals don't match.
+        hop: usize,
+    },
+
+    /// Occurs when a proof was made for a different number of high-level
+    /// variables than the verifier committed.
+    #[fail(
+        display = "Verifier committed {} inputs, but the proof is for at most {}.",
+        verifier, proof
+    )]
+    InputCountMismatch {
+        /// The number of high-level variables the verifier committed.
+        verifier: usize,
+        /// The largest number of high-level variables the proof's folded
+        /// vectors can have started from.
+        proof: usize,
+    },
+
+    /// Occurs when the verifier's committed number of high-level
+    /// variables is not the witness length the proof's fold schedule
+    /// implies, so no padded witness could have produced the proof.
+    #[fail(
+        display = "Verifier committed {} inputs, but the proof's fold schedule implies {}.",
+        verifier, implied
+    )]
+    CircuitSizeMismatch {
+        /// The number of high-level variables the verifier committed.
+        verifier: usize,
+        /// The witness length `m * k^d` implied by the proof.
+        implied: usize,
+    },
+
     /// Occurs when trying to use a missing variable assignment.
     /// Used by gadgets that build the constraint system to signal that
     /// a variable assignment is not provided when the prover needs it.
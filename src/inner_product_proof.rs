@@ -2,15 +2,25 @@
 #![doc= include_str!("../docs/inner-product-protocol.md")]
 
 use std::borrow::Borrow;
+#[cfg(test)]
+use std::cell::RefCell;
 use std::iter;
+use std::mem;
 
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 use curve25519_dalek::traits::VartimeMultiscalarMul;
 use merlin::Transcript;
+use rand::thread_rng;
 
 use errors::ProofError;
-use transcript::TranscriptProtocol;
+use transcript::{ProofTranscript, TranscriptProtocol};
+// Both alias `Iterator` in sequential builds, where only one is used.
+#[allow(unused_imports)]
+use util::{MaybeIndexedParallelIterator, MaybeParallelIterator};
+use util;
+use util::checked_batch_invert;
+use clear_on_drop::clear::Clear;
 use std::convert::TryInto;
 
 use curve25519_dalek::traits::IsIdentity;
@@ -32,19 +42,42 @@ fn scalar_pow(base: Scalar, mut exp: u64) -> Scalar {
     result
 }
 
-fn reconstruct_round_lengths(mut n: usize, k: usize, d: usize) -> Vec<usize> {
+/// Returns `prod_r c_r^exp`, computed directly rather than from the suffix
+/// products the verifiers build `s_P` out of, to cross-check them.
+#[cfg(any(test, feature = "debug"))]
+fn challenge_power_product(challenges: &[Scalar], exp: u64) -> Scalar {
+    scalar_pow(challenges.iter().fold(Scalar::one(), |acc, c| acc * c), exp)
+}
+
+/// Returns the length of `n` elements after one round pads them to a
+/// multiple of `k` and folds them by `k`.
+pub(crate) fn folded_len(n: usize, k: usize) -> usize {
+    n.div_ceil(k)
+}
+
+pub(crate) fn reconstruct_round_lengths(mut n: usize, k: usize, d: usize) -> Vec<usize> {
     let mut lengths = Vec::with_capacity(d + 1);
     lengths.push(n);
     for _ in 0..d {
-        let rem = n % k;
-        let pad = if rem == 0 { 0 } else { k - rem };
-        let n_padded = n + pad;
-        n = n_padded / k;
+        n = folded_len(n, k);
         lengths.push(n);
     }
     lengths
 }
 
+/// Returns the number of rounds that fold `n` elements by `k` down to a
+/// single one; any further round would only pad and fold that element.
+fn full_fold_rounds(mut n: usize, k: usize) -> usize {
+    let mut d = 0;
+    while n > 1 {
+        n = folded_len(n, k);
+        d += 1;
+    }
+    d
+}
+
+/// A logarithmic-size proof that `<a, b> = c` for committed `a`, `b`,
+/// folding the vectors in half each round.
 #[derive(Clone, Debug)]
 pub struct InnerProductProof {
     pub(crate) L_vec: Vec<CompressedRistretto>,
@@ -212,6 +245,34 @@ impl InnerProductProof {
         }
     }
 
+    /// Like [`InnerProductProof::create`], but checks the inputs first
+    /// instead of panicking or proving against degenerate generators.
+    ///
+    /// Returns [`ProofError::InvalidGeneratorsLength`] if the vectors
+    /// differ in length, the length is not a power of two, or any
+    /// `Hprime_factors[i]` is zero.  A zero factor turns `H'_i` into the
+    /// identity, so position `i` of `b` would no longer be bound by the
+    /// proof.  The R1CS prover's factors are powers of `y^-1` and never
+    /// zero; this guards callers choosing their own.
+    pub fn try_create(
+        transcript: &mut Transcript,
+        Q: &RistrettoPoint,
+        Hprime_factors: &[Scalar],
+        G_vec: Vec<RistrettoPoint>,
+        H_vec: Vec<RistrettoPoint>,
+        a_vec: Vec<Scalar>,
+        b_vec: Vec<Scalar>,
+    ) -> Result<InnerProductProof, ProofError> {
+        let n = G_vec.len();
+        if H_vec.len() != n || a_vec.len() != n || b_vec.len() != n || Hprime_factors.len() != n {
+            return Err(ProofError::InvalidGeneratorsLength);
+        }
+        if !n.is_power_of_two() || Hprime_factors.iter().any(|f| *f == Scalar::zero()) {
+            return Err(ProofError::InvalidGeneratorsLength);
+        }
+        Ok(InnerProductProof::create(transcript, Q, Hprime_factors, G_vec, H_vec, a_vec, b_vec))
+    }
+
     pub(crate) fn verification_scalars(
         &self,
         n: usize,
@@ -239,7 +300,7 @@ impl InnerProductProof {
         // 2. Compute 1/(u_k...u_1) and 1/u_k, ..., 1/u_1
 
         let mut challenges_inv = challenges.clone();
-        let allinv = Scalar::batch_invert(&mut challenges_inv);
+        let allinv = checked_batch_invert(&mut challenges_inv)?;
 
         // 3. Compute u_i^2 and (1/u_i)^2
 
@@ -328,6 +389,29 @@ impl InnerProductProof {
         }
     }
 
+    /// Like `verify`, but takes the commitment `P` in compressed form.
+    ///
+    /// Returns `ProofError::FormatError` if `P` does not decompress, so that
+    /// a malformed commitment is distinguishable from a proof that fails
+    /// to verify.
+    pub fn verify_compressed_p<I>(
+        &self,
+        n: usize,
+        transcript: &mut Transcript,
+        Hprime_factors: I,
+        P: &CompressedRistretto,
+        Q: &RistrettoPoint,
+        G: &[RistrettoPoint],
+        H: &[RistrettoPoint],
+    ) -> Result<(), ProofError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+    {
+        let P = P.decompress().ok_or(ProofError::FormatError)?;
+        self.verify(n, transcript, Hprime_factors, &P, Q, G, H)
+    }
+
     /// Returns the size in bytes required to serialize the inner
     /// product proof.
     ///
@@ -391,6 +475,40 @@ impl InnerProductProof {
 
         Ok(InnerProductProof { L_vec, R_vec, a, b })
     }
+
+    /// Serializes the proof like [`InnerProductProof::to_bytes`], preceded
+    /// by its element count \(2n+2\) as a little-endian `u32`, so that it
+    /// can be embedded among other data and still be read back.
+    pub fn to_bytes_prefixed(&self) -> Vec<u8> {
+        let num_elements = (self.L_vec.len() * 2 + 2) as u32;
+        let mut buf = Vec::with_capacity(4 + self.serialized_size());
+        buf.extend_from_slice(&num_elements.to_le_bytes());
+        buf.extend_from_slice(&self.to_bytes());
+        buf
+    }
+
+    /// Deserializes a proof written by
+    /// [`InnerProductProof::to_bytes_prefixed`] from the start of `slice`,
+    /// which may continue with other data.
+    ///
+    /// Returns the proof and the number of bytes it took up, prefix
+    /// included.  Fails as [`InnerProductProof::from_bytes`] does, or if
+    /// `slice` is shorter than the prefix says.
+    pub fn from_bytes_prefixed(slice: &[u8]) -> Result<(InnerProductProof, usize), ProofError> {
+        if slice.len() < 4 {
+            return Err(ProofError::FormatError);
+        }
+        let num_elements = u32::from_le_bytes(slice[..4].try_into().unwrap()) as usize;
+        let end = num_elements
+            .checked_mul(32)
+            .and_then(|len| len.checked_add(4))
+            .ok_or(ProofError::FormatError)?;
+        if slice.len() < end {
+            return Err(ProofError::FormatError);
+        }
+        let proof = InnerProductProof::from_bytes(&slice[4..end])?;
+        Ok((proof, end))
+    }
 }
 
 
@@ -398,6 +516,8 @@ impl InnerProductProof {
 //  K_BulletProof (IPA with Iterative Padding)
 // =========================================================================
 
+/// A k-ary inner-product argument: each round pads the vectors to a
+/// multiple of `k` and folds them by `k`, sending `2k - 2` cross terms.
 #[derive(Clone, Debug)]
 pub struct K_BulletProof {
     pub(crate) k: usize,
@@ -407,8 +527,32 @@ pub struct K_BulletProof {
 }
   
 impl K_BulletProof {   
-    pub fn create(
-        transcript: &mut Transcript,
+    /// Creates a proof of `<a_vec, b_vec>` against the bases `g_vec`, `h_vec`
+    /// and `Q_point`, folding by `k` down to single-element final vectors.
+    ///
+    /// This is [`K_BulletProof::create`] with the largest `num_rounds`
+    /// that still folds, which gives the smallest proof.
+    pub fn create_auto<T: ProofTranscript>(
+        transcript: &mut T,
+        k: usize,
+        g_vec: &[RistrettoPoint],
+        h_vec: &[RistrettoPoint],
+        Q_point: RistrettoPoint,
+        a_vec: &[Scalar],
+        b_vec: &[Scalar],
+    ) -> K_BulletProof {
+        assert!(k > 1, "k must be greater than 1");
+        let num_rounds = full_fold_rounds(a_vec.len(), k);
+        K_BulletProof::create(transcript, k, g_vec, h_vec, Q_point, a_vec, b_vec, num_rounds)
+    }
+
+    /// Creates a proof of `<a_vec, b_vec>` against the bases `g_vec`, `h_vec`
+    /// and `Q_point`, folding by `k` for `num_rounds` rounds.
+    ///
+    /// `num_rounds` must not exceed the rounds it takes to fold the
+    /// vectors down to a single element; debug builds assert this.
+    pub fn create<T: ProofTranscript>(
+        transcript: &mut T,
         k: usize, 
         g_vec: &[RistrettoPoint], 
         h_vec: &[RistrettoPoint], 
@@ -422,15 +566,32 @@ impl K_BulletProof {
         assert_eq!(h_vec.len(), n);
         assert_eq!(b_vec.len(), n);
         assert!(k > 1, "k must be greater than 1");
+        debug_assert!(
+            num_rounds <= full_fold_rounds(n, k),
+            "num_rounds folds past a single element"
+        );
 
-        transcript.append_message(b"protocol-name", b"k_bullet_delay");
-        transcript.append_message(b"n", &(n as u64).to_le_bytes());
-        transcript.append_message(b"k", &(k as u64).to_le_bytes());
-
-        let mut g_curr = g_vec.to_vec(); 
-        let mut h_curr = h_vec.to_vec();
-        let mut a_curr = a_vec.to_vec();
-        let mut b_curr = b_vec.to_vec();
+        k_bullet_domain_sep(transcript, n, k);
+
+        // Padding can grow a buffer by at most `k - 1` entries, so every
+        // buffer below is sized once up front and reused across rounds: the
+        // folded vectors are written into the `*_next` buffers and swapped
+        // with the `*_curr` ones. Each round still allocates the `U_vecs`
+        // entry the proof keeps, and the multiscalar multiplications
+        // allocate their own scratch space.
+        let mut a_curr: Vec<Scalar> = Vec::with_capacity(n + k);
+        let mut b_curr: Vec<Scalar> = Vec::with_capacity(n + k);
+        let mut g_curr: Vec<RistrettoPoint> = Vec::with_capacity(n + k);
+        let mut h_curr: Vec<RistrettoPoint> = Vec::with_capacity(n + k);
+        a_curr.extend_from_slice(a_vec);
+        b_curr.extend_from_slice(b_vec);
+        g_curr.extend_from_slice(g_vec);
+        h_curr.extend_from_slice(h_vec);
+
+        let mut a_next: Vec<Scalar> = Vec::with_capacity(n + k);
+        let mut b_next: Vec<Scalar> = Vec::with_capacity(n + k);
+        let mut g_next: Vec<RistrettoPoint> = Vec::with_capacity(n + k);
+        let mut h_next: Vec<RistrettoPoint> = Vec::with_capacity(n + k);
 
         let mut U_vecs: Vec<Vec<CompressedRistretto>> = Vec::with_capacity(num_rounds);
 
@@ -439,31 +600,30 @@ impl K_BulletProof {
         let mut scalars_neg_l: Vec<Scalar> = Vec::with_capacity(2 * n);
         let mut points_neg_l: Vec<RistrettoPoint> = Vec::with_capacity(2 * n);
 
-        let mut g_points_col: Vec<RistrettoPoint> = vec![RistrettoPoint::default(); k];
-        let mut h_points_col: Vec<RistrettoPoint> = vec![RistrettoPoint::default(); k];
+        let mut c_powers_a: Vec<Scalar> = Vec::with_capacity(k);
+        let mut c_powers_b: Vec<Scalar> = Vec::with_capacity(k);
 
         let mut n_j = n; 
 
         for j in 0..num_rounds {
-            let rem = n_j % k;
-            if rem != 0 {
-                let pad = k - rem;
+            let m_j = folded_len(n_j, k);
+            let pad = m_j * k - n_j;
+            if pad != 0 {
                 a_curr.extend(std::iter::repeat(Scalar::zero()).take(pad));
                 b_curr.extend(std::iter::repeat(Scalar::zero()).take(pad));
                 g_curr.extend(std::iter::repeat(RistrettoPoint::default()).take(pad));
                 h_curr.extend(std::iter::repeat(RistrettoPoint::default()).take(pad));
-                n_j += pad;
             }
 
-            let m_j = n_j / k; 
+            // Block `i` of a buffer is `[i * m_j, (i + 1) * m_j)`.
+            let a_split = |i: usize| &a_curr[i * m_j..(i + 1) * m_j];
+            let b_split = |i: usize| &b_curr[i * m_j..(i + 1) * m_j];
+            let g_split = |i: usize| &g_curr[i * m_j..(i + 1) * m_j];
+            let h_split = |i: usize| &h_curr[i * m_j..(i + 1) * m_j];
 
-            let a_splits: Vec<&[Scalar]> = a_curr.chunks(m_j).collect();
-            let b_splits: Vec<&[Scalar]> = b_curr.chunks(m_j).collect();
-            let g_splits: Vec<&[RistrettoPoint]> = g_curr.chunks(m_j).collect();
-            let h_splits: Vec<&[RistrettoPoint]> = h_curr.chunks(m_j).collect();
-
-            let mut U_pos_compressed: Vec<CompressedRistretto> = Vec::with_capacity(k - 1);
-            let mut U_neg_compressed: Vec<CompressedRistretto> = Vec::with_capacity(k - 1);
+            // The `U_+` commitments fill the first `k - 1` slots and the
+            // `U_-` ones the rest.
+            let mut U_vec_round = vec![CompressedRistretto::default(); 2 * (k - 1)];
             
             for l in 1..k { 
                 let mut v_pos_l = Scalar::zero();
@@ -472,84 +632,78 @@ impl K_BulletProof {
                 scalars_neg_l.clear(); points_neg_l.clear();
 
                 for i in 0..(k - l) {
-                    v_pos_l += inner_product(a_splits[i], b_splits[i + l]);
-                    scalars_l.extend_from_slice(a_splits[i]);
-                    points_l.extend_from_slice(g_splits[i+l]);
-                    scalars_l.extend_from_slice(b_splits[i+l]);
-                    points_l.extend_from_slice(h_splits[i]);
-
-                    v_neg_l += inner_product(a_splits[i + l], b_splits[i]);
-                    scalars_neg_l.extend_from_slice(a_splits[i+l]);
-                    points_neg_l.extend_from_slice(g_splits[i]);
-                    scalars_neg_l.extend_from_slice(b_splits[i]);
-                    points_neg_l.extend_from_slice(h_splits[i+l]);
+                    v_pos_l += inner_product(a_split(i), b_split(i + l));
+                    scalars_l.extend_from_slice(a_split(i));
+                    points_l.extend_from_slice(g_split(i + l));
+                    scalars_l.extend_from_slice(b_split(i + l));
+                    points_l.extend_from_slice(h_split(i));
+
+                    v_neg_l += inner_product(a_split(i + l), b_split(i));
+                    scalars_neg_l.extend_from_slice(a_split(i + l));
+                    points_neg_l.extend_from_slice(g_split(i));
+                    scalars_neg_l.extend_from_slice(b_split(i));
+                    points_neg_l.extend_from_slice(h_split(i + l));
                 }
                 
                 let U_l = RistrettoPoint::vartime_multiscalar_mul(scalars_l.iter(), points_l.iter()) 
                         + v_pos_l * Q_point;
-                U_pos_compressed.push(U_l.compress());
+                U_vec_round[l - 1] = U_l.compress();
 
                 let U_neg_l = RistrettoPoint::vartime_multiscalar_mul(scalars_neg_l.iter(), points_neg_l.iter()) 
                             + v_neg_l * Q_point;
-                U_neg_compressed.push(U_neg_l.compress());
+                U_vec_round[k - 1 + l - 1] = U_neg_l.compress();
             }
-
-            let mut U_vec_round = U_pos_compressed;
-            U_vec_round.extend(U_neg_compressed);
             
-            for (idx, point_compressed) in U_vec_round.iter().enumerate() {
-                 transcript.append_message(b"U_round", &(j as u64).to_le_bytes());
-                 transcript.append_message(b"U_index", &(idx as u64).to_le_bytes());
-                 transcript.commit_point(b"U_point", &point_compressed);
-            }
+            let c = round_challenge(transcript, j, &U_vec_round);
             U_vecs.push(U_vec_round);
-
-            transcript.append_message(b"challenge_prefix", b"c_");
-            transcript.append_message(b"challenge_index", &(j as u64).to_le_bytes());
-            let c = transcript.challenge_scalar(b"challenge_separator");
             let c_inv = c.invert();
 
-            let mut a_new = vec![Scalar::zero(); m_j];
-            let mut b_new = vec![Scalar::zero(); m_j];
-            let mut g_new = vec![RistrettoPoint::default(); m_j];
-            let mut h_new = vec![RistrettoPoint::default(); m_j];
-
-            let mut c_powers_a: Vec<Scalar> = Vec::with_capacity(k);
+            c_powers_a.clear();
             let mut c_pow_y = Scalar::one();
             for _ in 0..k { c_powers_a.push(c_pow_y); c_pow_y *= c; }
 
-            let mut c_powers_b: Vec<Scalar> = Vec::with_capacity(k);
+            c_powers_b.clear();
             let mut c_pow_x = Scalar::one(); 
             for _ in 1..k { c_pow_x *= c; }
             for _ in 0..k { c_powers_b.push(c_pow_x); c_pow_x *= c_inv; }
 
+            a_next.clear();
+            b_next.clear();
             for j_item in 0..m_j {
                 let mut a_j_acc = Scalar::zero();
                 let mut b_j_acc = Scalar::zero();
                 for i in 0..k {
-                    a_j_acc += a_splits[i][j_item] * c_powers_a[i];
-                    b_j_acc += b_splits[i][j_item] * c_powers_b[i];
+                    a_j_acc += a_curr[i * m_j + j_item] * c_powers_a[i];
+                    b_j_acc += b_curr[i * m_j + j_item] * c_powers_b[i];
                 }
-                a_new[j_item] = a_j_acc;
-                b_new[j_item] = b_j_acc;
+                a_next.push(a_j_acc);
+                b_next.push(b_j_acc);
             }
 
             let g_scalars = &c_powers_b;
             let h_scalars = &c_powers_a;
-            
-            for j_item in 0..m_j {
-                for i in 0..k {
-                    g_points_col[i] = g_splits[i][j_item];
-                    h_points_col[i] = h_splits[i][j_item];
-                }
-                g_new[j_item] = RistrettoPoint::vartime_multiscalar_mul(g_scalars.iter(), g_points_col.iter());
-                h_new[j_item] = RistrettoPoint::vartime_multiscalar_mul(h_scalars.iter(), h_points_col.iter());
-            }
-
-            a_curr = a_new;
-            b_curr = b_new;
-            g_curr = g_new;
-            h_curr = h_new;
+            let g_prev = &g_curr;
+            let h_prev = &h_curr;
+
+            g_next.resize(m_j, RistrettoPoint::default());
+            h_next.resize(m_j, RistrettoPoint::default());
+            maybe_par_iter_mut!(g_next).enumerate().for_each(|(j_item, g)| {
+                *g = RistrettoPoint::vartime_multiscalar_mul(
+                    g_scalars.iter(),
+                    (0..k).map(|i| g_prev[i * m_j + j_item]),
+                );
+            });
+            maybe_par_iter_mut!(h_next).enumerate().for_each(|(j_item, h)| {
+                *h = RistrettoPoint::vartime_multiscalar_mul(
+                    h_scalars.iter(),
+                    (0..k).map(|i| h_prev[i * m_j + j_item]),
+                );
+            });
+
+            mem::swap(&mut a_curr, &mut a_next);
+            mem::swap(&mut b_curr, &mut b_next);
+            mem::swap(&mut g_curr, &mut g_next);
+            mem::swap(&mut h_curr, &mut h_next);
 
             n_j = m_j;
         }
@@ -562,10 +716,12 @@ impl K_BulletProof {
         }
     }
 
-    pub fn verification_scalars(
+    /// Replays the transcript and returns the verifier's scalars
+    /// `(s_g, s_h, s_Q, s_P, s_U)` for a single multiscalar check.
+    pub fn verification_scalars<T: ProofTranscript>(
         &self,
         n: usize,
-        transcript: &mut Transcript,
+        transcript: &mut T,
     ) -> Result<(
         Vec<Scalar>,    
         Vec<Scalar>,    
@@ -574,104 +730,88 @@ impl K_BulletProof {
         Vec<Scalar>,    
     ), ProofError> {
         
-        let k = self.k;
-        if n == 0 { return Err(ProofError::InvalidGeneratorsLength); }
-        let d = self.U_vecs.len();
-
-        let round_lengths = reconstruct_round_lengths(n, k, d);
-        let m = *round_lengths.last().unwrap(); 
+        let challenges = self.replay_challenges(n, transcript)?;
+        self.scalars_from_challenges(n, &challenges)
+    }
 
-        if self.a_final.len() != m || self.b_final.len() != m {
-             return Err(ProofError::VerificationError);
-        }
+    /// Returns the generators `(g_final, h_final)` that `g_vec` and
+    /// `h_vec` fold down to, so that the `s_g`/`s_h` terms of the
+    /// verification equation equal `a_final[0] * g_final` and
+    /// `b_final[0] * h_final`.
+    ///
+    /// They only depend on the generators and the round challenges, so a
+    /// verifier that sees the same transcript prefix repeatedly can cache
+    /// them.  Each is computed with one multiscalar multiplication.  A
+    /// proof whose schedule does not fold `n` down to a single element
+    /// has no single folded generator, and is rejected with
+    /// `ProofError::FoldingError`.
+    pub fn folded_basis<T: ProofTranscript>(
+        &self,
+        g_vec: &[RistrettoPoint],
+        h_vec: &[RistrettoPoint],
+        transcript: &mut T,
+    ) -> Result<(RistrettoPoint, RistrettoPoint), ProofError> {
+        let n = g_vec.len();
+        if h_vec.len() != n { return Err(ProofError::InvalidGeneratorsLength); }
 
-        transcript.append_message(b"protocol-name", b"k_bullet_delay");
-        transcript.append_message(b"n", &(n as u64).to_le_bytes());
-        transcript.append_message(b"k", &(k as u64).to_le_bytes());
-
-        let mut challenges: Vec<Scalar> = Vec::with_capacity(d);
-        
-        for r in 0..d {
-            for i_list in 0..(2 * k - 2) {
-                transcript.append_message(b"U_round", &(r as u64).to_le_bytes());
-                transcript.append_message(b"U_index", &(i_list as u64).to_le_bytes());
-                transcript.commit_point(b"U_point", &self.U_vecs[r][i_list]);
-            }
-            transcript.append_message(b"challenge_prefix", b"c_");
-            transcript.append_message(b"challenge_index", &(r as u64).to_le_bytes());
-            challenges.push(transcript.challenge_scalar(b"challenge_separator"));
-        }
+        let challenges = self.replay_challenges(n, transcript)?;
+        let round_lengths = reconstruct_round_lengths(n, self.k, challenges.len());
+        if round_lengths[challenges.len()] != 1 { return Err(ProofError::FoldingError); }
 
         let mut challenges_inv = challenges.clone();
-        Scalar::batch_invert(&mut challenges_inv);
-        
-        let mut s_P = Scalar::one();
-        let k_minus_1_exp = (k - 1) as u64;
-        let mut c_k_minus_1_products = vec![Scalar::one(); d]; 
-        let mut product_so_far = Scalar::one();
-        for r in (0..d).rev() {
-            let c_k_minus_1 = scalar_pow(challenges[r], k_minus_1_exp); 
-            c_k_minus_1_products[r] = product_so_far; 
-            product_so_far *= c_k_minus_1;
-        }
-        s_P = product_so_far;
+        checked_batch_invert(&mut challenges_inv)?;
+        let s_P = challenges.iter()
+            .fold(Scalar::one(), |acc, c| acc * scalar_pow(*c, (self.k - 1) as u64));
 
-        let mut s_g_full = self.a_final.clone(); 
-        for r in (0..d).rev() {
-            let c_inv = challenges_inv[r];
-            let mut block = Vec::with_capacity(k);
-            let mut val = Scalar::one();
-            for _ in 0..k { block.push(val); val *= c_inv; }
-
-            let mut next_s = Vec::with_capacity(s_g_full.len() * k);
-            for b in block.iter() {
-                for val in s_g_full.iter() { next_s.push(val * b); }
-            }
-            
-            next_s.truncate(round_lengths[r]);
-            s_g_full = next_s;
-        }
-        for x in s_g_full.iter_mut() { *x *= s_P; }
+        let one = [Scalar::one()];
+        let s_g: Vec<Scalar> = expand_final(&one, self.k, &challenges_inv, &round_lengths)
+            .iter()
+            .map(|s| s * s_P)
+            .collect();
+        let s_h = expand_final(&one, self.k, &challenges, &round_lengths);
+        let g_final = RistrettoPoint::vartime_multiscalar_mul(s_g.iter(), g_vec.iter());
+        let h_final = RistrettoPoint::vartime_multiscalar_mul(s_h.iter(), h_vec.iter());
+        Ok((g_final, h_final))
+    }
 
-        let mut s_h_full = self.b_final.clone(); 
-        for r in (0..d).rev() {
-            let c = challenges[r];
-            let mut block = Vec::with_capacity(k);
-            let mut val = Scalar::one();
-            for _ in 0..k { block.push(val); val *= c; }
+    /// Writes the proof's setup to the transcript and draws every round
+    /// challenge, exactly as `K_BulletProof::create` does.
+    fn replay_challenges<T: ProofTranscript>(&self, n: usize, transcript: &mut T) -> Result<Vec<Scalar>, ProofError> {
+        let k = self.k;
+        if n == 0 { return Err(ProofError::InvalidGeneratorsLength); }
+        let d = self.U_vecs.len();
 
-            let mut next_s = Vec::with_capacity(s_h_full.len() * k);
-            for b in block.iter() {
-                for val in s_h_full.iter() { next_s.push(val * b); }
-            }
+        k_bullet_domain_sep(transcript, n, k);
 
-            next_s.truncate(round_lengths[r]);
-            s_h_full = next_s;
+        let mut challenges: Vec<Scalar> = Vec::with_capacity(d);
+        
+        for r in 0..d {
+            challenges.push(round_challenge(transcript, r, &self.U_vecs[r]));
         }
-    
-        let s_Q_final = inner_product(&self.a_final, &self.b_final); 
+        Ok(challenges)
+    }
 
-        let mut s_U: Vec<Scalar> = Vec::with_capacity(d * (2*k - 2));
-        for r in 0..d { 
-            let c_r = challenges[r];
-            let suffix_prod = c_k_minus_1_products[r];
-            for l in 1..k { 
-                let exp = (k - 1 - l) as u64;
-                s_U.push(scalar_pow(c_r, exp) * suffix_prod); 
-            }
-            for l in 1..k { 
-                let exp = (k - 1 + l) as u64; 
-                s_U.push(scalar_pow(c_r, exp) * suffix_prod); 
-            }
-        }
-        
-        Ok((s_g_full, s_h_full, s_Q_final, s_P, s_U))
+    /// Expands the per-round challenges into the scalars used by the
+    /// verification equation, without touching the transcript.
+    fn scalars_from_challenges(
+        &self,
+        n: usize,
+        challenges: &[Scalar],
+    ) -> Result<(
+        Vec<Scalar>,    
+        Vec<Scalar>,    
+        Scalar,         
+        Scalar,         
+        Vec<Scalar>,    
+    ), ProofError> {
+        k_bullet_scalars(self.k, &self.a_final, &self.b_final, n, challenges)
     }
 
+    /// Verifies the proof against the bases and the commitment `P_point`.
     #[allow(dead_code)]
-    pub fn verify(
+    pub fn verify<T: ProofTranscript>(
         &self,
-        transcript: &mut Transcript,
+        transcript: &mut T,
         g_vec: &Vec<RistrettoPoint>,
         h_vec: &Vec<RistrettoPoint>,
         Q_point: &RistrettoPoint,
@@ -682,35 +822,97 @@ impl K_BulletProof {
 
         let (s_g, s_h, s_Q_final, s_P, s_U) = self.verification_scalars(n, transcript)?;
 
-        let mut U_points_decompressed: Vec<RistrettoPoint> =
-            Vec::with_capacity(self.U_vecs.len() * (2 * self.k - 2));
+        let U_points = decompress_rounds(&self.U_vecs)?;
 
-        for r in 0..self.U_vecs.len() {
-            for i_list in 0..(2 * self.k - 2) {
-                let U_ir_point = self.U_vecs[r][i_list]
-                    .decompress()
-                    .ok_or(ProofError::VerificationError)?;
-                U_points_decompressed.push(U_ir_point);
-            }
+        check_msm((s_g, s_h, s_Q_final, s_P, s_U), &U_points, g_vec, h_vec, Q_point, P_point)
+    }
+
+    /// Verifies several proofs against the same bases with a single
+    /// multiscalar multiplication, replaying `proofs[i]` against the
+    /// commitment it is paired with on `transcripts[i]`.
+    ///
+    /// Each proof's verification equation is scaled by an independent
+    /// random weight before they are summed, so the `g_vec`, `h_vec` and
+    /// `Q_point` terms appear once, and an invalid proof makes the sum
+    /// nonzero except with negligible probability.  The proofs may differ
+    /// in `k` and in their number of rounds, as long as they are over the
+    /// same `n`.  A failure does not say which proof is invalid.
+    pub fn verify_batch<T: ProofTranscript>(
+        proofs: &[(&K_BulletProof, &RistrettoPoint)],
+        transcripts: &mut [T],
+        g_vec: &[RistrettoPoint],
+        h_vec: &[RistrettoPoint],
+        Q_point: &RistrettoPoint,
+    ) -> Result<(), ProofError> {
+        let n = g_vec.len();
+        if h_vec.len() != n { return Err(ProofError::InvalidGeneratorsLength); }
+        if transcripts.len() != proofs.len() { return Err(ProofError::FormatError); }
+
+        let mut rng = thread_rng();
+        let mut g_scalars = vec![Scalar::zero(); n];
+        let mut h_scalars = vec![Scalar::zero(); n];
+        let mut Q_scalar = Scalar::zero();
+        let mut scalars: Vec<Scalar> = Vec::new();
+        let mut points: Vec<RistrettoPoint> = Vec::new();
+
+        for (&(proof, P_point), transcript) in proofs.iter().zip(transcripts.iter_mut()) {
+            let (s_g, s_h, s_Q_final, s_P, s_U) = proof.verification_scalars(n, transcript)?;
+            let weight = Scalar::random(&mut rng);
+
+            for (acc, s) in g_scalars.iter_mut().zip(s_g.iter()) { *acc += weight * s; }
+            for (acc, s) in h_scalars.iter_mut().zip(s_h.iter()) { *acc += weight * s; }
+            Q_scalar += weight * s_Q_final;
+
+            scalars.push(-(weight * s_P));
+            points.push(*P_point);
+            scalars.extend(s_U.iter().map(|s| -(weight * s)));
+            points.extend(decompress_rounds(&proof.U_vecs)?);
         }
 
-        let scalars = s_g.iter().cloned()
-            .chain(s_h.iter().cloned())
-            .chain(iter::once(s_Q_final))
-            .chain(iter::once(-s_P)) 
-            .chain(s_U.iter().map(|s| -s)); 
+        let check = RistrettoPoint::vartime_multiscalar_mul(
+            g_scalars.iter().chain(h_scalars.iter()).chain(iter::once(&Q_scalar)).chain(scalars.iter()),
+            g_vec.iter().chain(h_vec.iter()).chain(iter::once(Q_point)).chain(points.iter()),
+        );
 
-        let points = g_vec.iter()
-            .chain(h_vec.iter())
-            .chain(iter::once(Q_point))
-            .chain(iter::once(P_point))
-            .chain(U_points_decompressed.iter());
+        if check.is_identity() { Ok(()) } else { Err(ProofError::VerificationError) }
+    }
 
-        let check = RistrettoPoint::vartime_multiscalar_mul(scalars, points);
+    /// Verifies a proof created against the generators `H'_i = factors_i * H_i`,
+    /// given only the base generators `H`.
+    ///
+    /// This generalizes the R1CS layer's `H'_i = y^{-i} H_i`: the factors are
+    /// folded into the `s_h` scalars instead of into the points, so no
+    /// per-verification generator vector has to be built.
+    pub fn verify_with_factors<I, T: ProofTranscript>(
+        &self,
+        transcript: &mut T,
+        factors: I,
+        g_vec: &[RistrettoPoint],
+        h_vec: &[RistrettoPoint],
+        Q_point: &RistrettoPoint,
+        P_point: &RistrettoPoint,
+    ) -> Result<(), ProofError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+    {
+        let n = g_vec.len();
+        if h_vec.len() != n { return Err(ProofError::InvalidGeneratorsLength); }
 
-        if check.is_identity() { Ok(()) } else { Err(ProofError::VerificationError) }
+        let factors: Vec<Scalar> = factors.into_iter().map(|f| *f.borrow()).collect();
+        if factors.len() != n { return Err(ProofError::InvalidGeneratorsLength); }
+
+        let (s_g, mut s_h, s_Q_final, s_P, s_U) = self.verification_scalars(n, transcript)?;
+        for (s_h_i, f_i) in s_h.iter_mut().zip(factors.iter()) {
+            *s_h_i *= f_i;
+        }
+
+        let U_points = decompress_rounds(&self.U_vecs)?;
+
+        check_msm((s_g, s_h, s_Q_final, s_P, s_U), &U_points, g_vec, h_vec, Q_point, P_point)
     }
     
+    /// Returns the size in bytes of the proof serialized by [`K_BulletProof::to_bytes`].
     pub fn serialized_size(&self) -> usize {
         let d = self.U_vecs.len();
         let num_points = if d > 0 { d * (2 * self.k - 2) } else { 0 };
@@ -718,6 +920,8 @@ impl K_BulletProof {
         (3 + num_points + 2 * m) * 32
     }
     
+    /// Serializes the proof as `k`, the round count, the final-vector length,
+    /// the cross terms and the final `a`/`b` vectors.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(self.serialized_size());
         let mut temp = [0u8; 32];
@@ -742,21 +946,12 @@ impl K_BulletProof {
         buf
     }
     
+    /// Deserializes a proof produced by [`K_BulletProof::to_bytes`].
     pub fn from_bytes(slice: &[u8]) -> Result<K_BulletProof, ProofError> {
         let b = slice.len();
-        if b < 32 * 3 { return Err(ProofError::FormatError); }
         use util::read32; 
-        let mut pos = 0;
-
-        let k_bytes = read32(&slice[pos..]);
-        let k = u64::from_le_bytes(k_bytes[..8].try_into().unwrap()) as usize;
-        pos += 32;
-        let d_bytes = read32(&slice[pos..]);
-        let d = u64::from_le_bytes(d_bytes[..8].try_into().unwrap()) as usize;
-        pos += 32;
-        let m_bytes = read32(&slice[pos..]);
-        let m = u64::from_le_bytes(m_bytes[..8].try_into().unwrap()) as usize;
-        pos += 32;
+        let (k, d, m) = k_bullet_header(slice)?;
+        let mut pos = 3 * 32;
 
         let points_per_round = 2 * k - 2;
         let mut U_vecs = Vec::with_capacity(d);
@@ -791,10 +986,384 @@ impl K_BulletProof {
 }
 
 
+/// Reads the `(k, d, m)` header of a serialized `K_BulletProof` and checks
+/// that `slice` has exactly the length it implies.
+fn k_bullet_header(slice: &[u8]) -> Result<(usize, usize, usize), ProofError> {
+    if slice.len() < 32 * 3 { return Err(ProofError::FormatError); }
+    let k = read_header(&slice[0..])?;
+    let d = read_header(&slice[32..])?;
+    let m = read_header(&slice[64..])?;
+    check_encoded_len(slice.len(), k, d, m, 1, 2)?;
+    Ok((k, d, m))
+}
+
+/// A `K_BulletProof` read in place from its serialized bytes.
+///
+/// [`K_BulletProof::from_bytes`] copies every cross-term point into the
+/// proof; this view only checks the framing and the final scalars, and
+/// decompresses each `U` point straight from the slice while verifying.
+/// For large proofs it avoids holding a second copy of the points.
+#[derive(Copy, Clone, Debug)]
+pub struct K_BulletProofRef<'a> {
+    k: usize,
+    d: usize,
+    m: usize,
+    U_bytes: &'a [u8],
+    final_bytes: &'a [u8],
+}
+
+impl<'a> K_BulletProofRef<'a> {
+    /// Views a proof serialized by [`K_BulletProof::to_bytes`], with the
+    /// same checks as [`K_BulletProof::from_bytes`].
+    pub fn from_bytes(slice: &'a [u8]) -> Result<K_BulletProofRef<'a>, ProofError> {
+        let (k, d, m) = k_bullet_header(slice)?;
+        let (U_bytes, final_bytes) = slice[3 * 32..].split_at(d * (2 * k - 2) * 32);
+        for chunk in final_bytes.chunks(32) {
+            Scalar::from_canonical_bytes(util::read32(chunk)).ok_or(ProofError::FormatError)?;
+        }
+        Ok(K_BulletProofRef { k, d, m, U_bytes, final_bytes })
+    }
+
+    /// Returns the `i`-th cross term of round `r`.
+    pub fn U_point(&self, r: usize, i: usize) -> CompressedRistretto {
+        let points_per_round = 2 * self.k - 2;
+        assert!(r < self.d && i < points_per_round);
+        let pos = (r * points_per_round + i) * 32;
+        CompressedRistretto(util::read32(&self.U_bytes[pos..]))
+    }
+
+    /// Copies the proof out into an owned [`K_BulletProof`].
+    pub fn to_proof(&self) -> K_BulletProof {
+        let points_per_round = 2 * self.k - 2;
+        let (a_final, b_final) = self.final_vectors();
+        K_BulletProof {
+            k: self.k,
+            U_vecs: (0..self.d)
+                .map(|r| (0..points_per_round).map(|i| self.U_point(r, i)).collect())
+                .collect(),
+            a_final,
+            b_final,
+        }
+    }
+
+    /// Verifies the proof against the bases and the commitment `P_point`,
+    /// exactly as [`K_BulletProof::verify`] does.
+    pub fn verify<T: ProofTranscript>(
+        &self,
+        transcript: &mut T,
+        g_vec: &[RistrettoPoint],
+        h_vec: &[RistrettoPoint],
+        Q_point: &RistrettoPoint,
+        P_point: &RistrettoPoint,
+    ) -> Result<(), ProofError> {
+        let n = g_vec.len();
+        if h_vec.len() != n || n == 0 { return Err(ProofError::InvalidGeneratorsLength); }
+        let points_per_round = 2 * self.k - 2;
+
+        k_bullet_domain_sep(transcript, n, self.k);
+        let mut challenges = Vec::with_capacity(self.d);
+        let mut U_round = Vec::with_capacity(points_per_round);
+        for r in 0..self.d {
+            U_round.clear();
+            U_round.extend((0..points_per_round).map(|i| self.U_point(r, i)));
+            challenges.push(round_challenge(transcript, r, &U_round));
+        }
+
+        let (a_final, b_final) = self.final_vectors();
+        let (s_g, s_h, s_Q_final, s_P, s_U) = k_bullet_scalars(self.k, &a_final, &b_final, n, &challenges)?;
+
+        let scalars = s_g.into_iter()
+            .chain(s_h.into_iter())
+            .chain(iter::once(s_Q_final))
+            .chain(iter::once(-s_P))
+            .chain(s_U.into_iter().map(|s| -s));
+        let points = g_vec.iter().map(|G| Some(*G))
+            .chain(h_vec.iter().map(|H| Some(*H)))
+            .chain(iter::once(Some(*Q_point)))
+            .chain(iter::once(Some(*P_point)))
+            .chain(self.U_bytes.chunks(32).map(|U| CompressedRistretto(util::read32(U)).decompress()));
+
+        match RistrettoPoint::optional_multiscalar_mul(scalars, points) {
+            Some(check) if check.is_identity() => Ok(()),
+            _ => Err(ProofError::VerificationError),
+        }
+    }
+
+    /// Parses the final `a` and `b` vectors, already checked canonical.
+    fn final_vectors(&self) -> (Vec<Scalar>, Vec<Scalar>) {
+        let mut scalars = self.final_bytes
+            .chunks(32)
+            .map(|chunk| Scalar::from_canonical_bytes(util::read32(chunk)).unwrap());
+        let a_final = scalars.by_ref().take(self.m).collect();
+        let b_final = scalars.collect();
+        (a_final, b_final)
+    }
+}
+
 // =========================================================================
 //  batched_eCP (eCP with Iterative Padding)
 // =========================================================================
 
+/// Unfolds a final vector back to the full generator length: each round,
+/// from the last back to the first, the vector is repeated `k` times with
+/// the `i`-th copy scaled by `bases[r]^i`, then trimmed to that round's
+/// unpadded length.
+fn expand_final(final_vec: &[Scalar], k: usize, bases: &[Scalar], round_lengths: &[usize]) -> Vec<Scalar> {
+    let mut s = final_vec.to_vec();
+    for r in (0..bases.len()).rev() {
+        let mut block = Vec::with_capacity(k);
+        let mut val = Scalar::one();
+        for _ in 0..k { block.push(val); val *= bases[r]; }
+
+        let mut next_s = Vec::with_capacity(s.len() * k);
+        for b in block.iter() {
+            for val in s.iter() { next_s.push(val * b); }
+        }
+
+        next_s.truncate(round_lengths[r]);
+        s = next_s;
+    }
+    s
+}
+
+// Test builds log every `K_BulletProof` transcript operation as an
+// `(operation, label)` pair, so the prover's and verifier's sequences
+// can be compared directly.
+#[cfg(test)]
+thread_local! {
+    static TRANSCRIPT_LOG: RefCell<Option<Vec<(&'static str, &'static [u8])>>> = RefCell::new(None);
+}
+
+#[inline]
+fn record(_op: &'static str, _label: &'static [u8]) {
+    #[cfg(test)]
+    TRANSCRIPT_LOG.with(|log| {
+        if let Some(log) = log.borrow_mut().as_mut() {
+            log.push((_op, _label));
+        }
+    });
+}
+
+fn append<T: ProofTranscript>(transcript: &mut T, label: &'static [u8], message: &[u8]) {
+    record("append", label);
+    transcript.append_message(label, message);
+}
+
+/// Writes the `K_BulletProof` setup for `n` generators folded by `k`.
+fn k_bullet_domain_sep<T: ProofTranscript>(transcript: &mut T, n: usize, k: usize) {
+    append(transcript, b"protocol-name", b"k_bullet_delay");
+    append(transcript, b"n", &(n as u64).to_le_bytes());
+    append(transcript, b"k", &(k as u64).to_le_bytes());
+}
+
+/// Commits one round of `U` points to the transcript and draws that
+/// round's challenge, exactly as `K_BulletProof::create` does.
+fn round_challenge<T: ProofTranscript>(
+    transcript: &mut T,
+    r: usize,
+    U_round: &[CompressedRistretto],
+) -> Scalar {
+    for (i_list, U_point) in U_round.iter().enumerate() {
+        append(transcript, b"U_round", &(r as u64).to_le_bytes());
+        append(transcript, b"U_index", &(i_list as u64).to_le_bytes());
+        record("point", b"U_point");
+        transcript.commit_point(b"U_point", U_point);
+    }
+    append(transcript, b"challenge_prefix", b"c_");
+    append(transcript, b"challenge_index", &(r as u64).to_le_bytes());
+    record("challenge", b"challenge_separator");
+    transcript.challenge_scalar(b"challenge_separator")
+}
+
+/// Expands the per-round challenges of a `K_BulletProof` with folding
+/// factor `k` and final vectors `a_final`, `b_final` into the scalars
+/// `(s_g, s_h, s_Q, s_P, s_U)` of the verification equation.
+fn k_bullet_scalars(
+    k: usize,
+    a_final: &[Scalar],
+    b_final: &[Scalar],
+    n: usize,
+    challenges: &[Scalar],
+) -> Result<(Vec<Scalar>, Vec<Scalar>, Scalar, Scalar, Vec<Scalar>), ProofError> {
+    let d = challenges.len();
+    let round_lengths = reconstruct_round_lengths(n, k, d);
+
+    if a_final.len() != round_lengths[d] || b_final.len() != round_lengths[d] {
+         return Err(ProofError::VerificationError);
+    }
+
+    let mut challenges_inv = challenges.to_vec();
+    checked_batch_invert(&mut challenges_inv)?;
+
+    let mut s_P = Scalar::one();
+    let k_minus_1_exp = (k - 1) as u64;
+    let mut c_k_minus_1_products = vec![Scalar::one(); d]; 
+    let mut product_so_far = Scalar::one();
+    for r in (0..d).rev() {
+        let c_k_minus_1 = scalar_pow(challenges[r], k_minus_1_exp); 
+        c_k_minus_1_products[r] = product_so_far; 
+        product_so_far *= c_k_minus_1;
+    }
+    s_P = product_so_far;
+    #[cfg(feature = "debug")]
+    assert_eq!(s_P, challenge_power_product(challenges, k_minus_1_exp), "s_P != prod_r c_r^(k-1)");
+
+    let mut s_g_full = expand_final(a_final, k, &challenges_inv, &round_lengths);
+    for x in s_g_full.iter_mut() { *x *= s_P; }
+
+    let s_h_full = expand_final(b_final, k, challenges, &round_lengths);
+
+    let s_Q_final = inner_product(a_final, b_final);
+
+    let mut s_U: Vec<Scalar> = Vec::with_capacity(d * (2*k - 2));
+    for r in 0..d { 
+        let c_r = challenges[r];
+        let suffix_prod = c_k_minus_1_products[r];
+        for l in 1..k { 
+            let exp = (k - 1 - l) as u64;
+            s_U.push(scalar_pow(c_r, exp) * suffix_prod); 
+        }
+        for l in 1..k { 
+            let exp = (k - 1 + l) as u64; 
+            s_U.push(scalar_pow(c_r, exp) * suffix_prod); 
+        }
+    }
+
+    Ok((s_g_full, s_h_full, s_Q_final, s_P, s_U))
+}
+
+fn decompress_rounds(
+    U_vecs: &[Vec<CompressedRistretto>],
+) -> Result<Vec<RistrettoPoint>, ProofError> {
+    U_vecs
+        .iter()
+        .flat_map(|U_round| U_round.iter())
+        .map(|U_point| U_point.decompress().ok_or(ProofError::VerificationError))
+        .collect()
+}
+
+/// Checks `<s_g, G> + <s_h, H> + s_Q Q - s_P P - <s_U, U> == 0`.
+fn check_msm(
+    scalars: (Vec<Scalar>, Vec<Scalar>, Scalar, Scalar, Vec<Scalar>),
+    U_points: &[RistrettoPoint],
+    g_vec: &[RistrettoPoint],
+    h_vec: &[RistrettoPoint],
+    Q_point: &RistrettoPoint,
+    P_point: &RistrettoPoint,
+) -> Result<(), ProofError> {
+    let (s_g, s_h, s_Q_final, s_P, s_U) = scalars;
+
+    let scalars = s_g.iter().cloned()
+        .chain(s_h.iter().cloned())
+        .chain(iter::once(s_Q_final))
+        .chain(iter::once(-s_P))
+        .chain(s_U.iter().map(|s| -s));
+
+    let points = g_vec.iter()
+        .chain(h_vec.iter())
+        .chain(iter::once(Q_point))
+        .chain(iter::once(P_point))
+        .chain(U_points.iter());
+
+    let check = RistrettoPoint::vartime_multiscalar_mul(scalars, points);
+
+    if check.is_identity() { Ok(()) } else { Err(ProofError::VerificationError) }
+}
+
+/// Verifies a `K_BulletProof` one round at a time.
+///
+/// Each call to `push_round` absorbs that round's `U` points into the
+/// transcript and derives its challenge immediately, so a verifier can
+/// follow a proof as it streams in. The multiscalar check itself is
+/// deferred to `finalize`, once the final scalars and the statement
+/// are known.
+pub struct RoundVerifier<'a, T: 'a = Transcript> {
+    transcript: &'a mut T,
+    n: usize,
+    k: usize,
+    U_vecs: Vec<Vec<CompressedRistretto>>,
+    U_points: Vec<RistrettoPoint>,
+    challenges: Vec<Scalar>,
+}
+
+impl<'a, T: ProofTranscript> RoundVerifier<'a, T> {
+    /// Starts verifying a folding of `n` generators by a factor of `k`.
+    pub fn new(transcript: &'a mut T, n: usize, k: usize) -> Result<Self, ProofError> {
+        if n == 0 { return Err(ProofError::InvalidGeneratorsLength); }
+        if k < 2 { return Err(ProofError::FoldingError); }
+
+        k_bullet_domain_sep(transcript, n, k);
+
+        Ok(RoundVerifier {
+            transcript,
+            n,
+            k,
+            U_vecs: Vec::new(),
+            U_points: Vec::new(),
+            challenges: Vec::new(),
+        })
+    }
+
+    /// Absorbs the next round of `2k-2` points and returns its challenge.
+    pub fn push_round(&mut self, U_round: Vec<CompressedRistretto>) -> Result<Scalar, ProofError> {
+        if U_round.len() != 2 * self.k - 2 {
+            return Err(ProofError::FormatError);
+        }
+        for U_point in U_round.iter() {
+            self.U_points.push(U_point.decompress().ok_or(ProofError::VerificationError)?);
+        }
+
+        let r = self.U_vecs.len();
+        let c = round_challenge(self.transcript, r, &U_round);
+        self.U_vecs.push(U_round);
+        self.challenges.push(c);
+        Ok(c)
+    }
+
+    /// Number of rounds absorbed so far.
+    pub fn rounds(&self) -> usize {
+        self.U_vecs.len()
+    }
+
+    /// Runs the deferred multiscalar check against the final scalars and
+    /// the statement `P = <a, G> + <b, H> + <a, b> Q`.
+    pub fn finalize(
+        self,
+        a_final: Vec<Scalar>,
+        b_final: Vec<Scalar>,
+        g_vec: &[RistrettoPoint],
+        h_vec: &[RistrettoPoint],
+        Q_point: &RistrettoPoint,
+        P_point: &RistrettoPoint,
+    ) -> Result<(), ProofError> {
+        if g_vec.len() != self.n || h_vec.len() != self.n {
+            return Err(ProofError::InvalidGeneratorsLength);
+        }
+
+        let proof = K_BulletProof {
+            k: self.k,
+            U_vecs: self.U_vecs,
+            a_final,
+            b_final,
+        };
+        let scalars = proof.scalars_from_challenges(self.n, &self.challenges)?;
+
+        check_msm(scalars, &self.U_points, g_vec, h_vec, Q_point, P_point)
+    }
+}
+
+/// A batched proof that the ElGamal ciphertexts `C1_vec` are consistently
+/// weighted by the folded witness, using the same k-ary folding as
+/// [`K_BulletProof`].
+///
+/// The proof shows two relations with the same witness `a`,
+/// `P0 = <a, G>` and `P1 = <a, C1>`.  Its only transcript challenges are
+/// the per-round folding challenges replayed by
+/// [`batched_eCP::verification_scalars`]; the verifier then checks both
+/// relations at once, weighted by two scalars of its own choosing that
+/// never enter the transcript.  [`batched_eCP::verify`] draws them from
+/// the verifier's RNG, while the R1CS verifier folds them into its
+/// single check as `r^4` and `r^3`, so both accept the same proofs.
 #[derive(Clone, Debug)]
 pub struct batched_eCP {
     pub(crate) k: usize,
@@ -803,8 +1372,14 @@ pub struct batched_eCP {
 }
 
 impl batched_eCP {
-    pub fn create(
-        transcript: &mut Transcript,
+    /// Creates a consistency proof for `a_vec` against `G_vec` and
+    /// `C1_vec`, folding by `k` for `num_rounds` rounds.
+    ///
+    /// `a_vec` is derived from the prover's witness, so every folded copy
+    /// of it is zeroed once used; only the final vector, which the proof
+    /// reveals, is kept.  The challenge powers are public and left as is.
+    pub fn create<T: ProofTranscript>(
+        transcript: &mut T,
         k: usize, 
         G_vec: &[RistrettoPoint], 
         C1_vec: &[RistrettoPoint], 
@@ -813,7 +1388,10 @@ impl batched_eCP {
     ) -> batched_eCP {
         let n = a_vec.len();
         
-        let mut a_curr = a_vec.to_vec();
+        // Room for the first round's padding, so that extending `a_curr`
+        // never moves it and leaves an uncleared copy behind.
+        let mut a_curr = Vec::with_capacity(n + k);
+        a_curr.extend_from_slice(a_vec);
         let mut G_curr = G_vec.to_vec();
         let mut C1_curr = C1_vec.to_vec();
 
@@ -827,10 +1405,12 @@ impl batched_eCP {
 
         let mut A_vecs: Vec<Vec<[CompressedRistretto; 2]>> = Vec::with_capacity(num_rounds);
 
-        let mut scalars_0: Vec<Scalar> = Vec::with_capacity(n);
-        let mut points_0: Vec<RistrettoPoint> = Vec::with_capacity(n);
-        let mut scalars_1: Vec<Scalar> = Vec::with_capacity(n);
-        let mut points_1: Vec<RistrettoPoint> = Vec::with_capacity(n);
+        // Each cross term takes at most `k - 1` blocks of the first round's
+        // padded length, so `n + k` also keeps the scalar buffers in place.
+        let mut scalars_0: Vec<Scalar> = Vec::with_capacity(n + k);
+        let mut points_0: Vec<RistrettoPoint> = Vec::with_capacity(n + k);
+        let mut scalars_1: Vec<Scalar> = Vec::with_capacity(n + k);
+        let mut points_1: Vec<RistrettoPoint> = Vec::with_capacity(n + k);
         
         let mut n_j = n;
         
@@ -854,8 +1434,8 @@ impl batched_eCP {
             let mut A_points_round: Vec<[RistrettoPoint; 2]> = Vec::with_capacity(2 * k - 2);
 
              for i in 1..k {
-                scalars_0.clear(); points_0.clear();
-                scalars_1.clear(); points_1.clear();
+                clear_witness("scalars", &mut scalars_0); points_0.clear();
+                clear_witness("scalars", &mut scalars_1); points_1.clear();
                 for l in 1..(i + 1) {
                     scalars_0.extend_from_slice(a_splits[l - 1]);
                     points_0.extend_from_slice(G_splits[k - i + l - 1]); 
@@ -868,8 +1448,8 @@ impl batched_eCP {
                 ]);
             }
             for i in 1..k {
-                scalars_0.clear(); points_0.clear();
-                scalars_1.clear(); points_1.clear();
+                clear_witness("scalars", &mut scalars_0); points_0.clear();
+                clear_witness("scalars", &mut scalars_1); points_1.clear();
                 for l in 1..(k - i + 1) {
                     scalars_0.extend_from_slice(a_splits[i + l - 1]);
                     points_0.extend_from_slice(G_splits[l - 1]); 
@@ -897,7 +1477,8 @@ impl batched_eCP {
             transcript.append_message(b"challenge_index", &(round_idx as u64).to_le_bytes());
             let c = transcript.challenge_scalar(b"challenge_separator");
             
-            let mut a_new = vec![Scalar::zero(); m_j];
+            let mut a_new = Vec::with_capacity(m_j + k);
+            a_new.resize(m_j, Scalar::zero());
             let mut G_new = vec![RistrettoPoint::default(); m_j];
             let mut C1_new = vec![RistrettoPoint::default(); m_j];
 
@@ -928,11 +1509,14 @@ impl batched_eCP {
                 C1_new[j_item] = RistrettoPoint::vartime_multiscalar_mul(c_powers_bases.iter(), c1_col.iter());
             }
 
+            clear_witness("a", &mut a_curr);
             a_curr = a_new;
             G_curr = G_new;
             C1_curr = C1_new;
             n_j = m_j;
         }
+        clear_witness("scalars", &mut scalars_0);
+        clear_witness("scalars", &mut scalars_1);
 
         batched_eCP {
             k,
@@ -941,11 +1525,34 @@ impl batched_eCP {
         }
     }
 
-    pub fn verification_scalars(
+    /// Returns the number of `A` point pairs each folding round holds,
+    /// `2k - 2`, or `0` for an invalid `k < 2`.
+    pub fn pairs_per_round(&self) -> usize {
+        if self.k < 2 { 0 } else { 2 * self.k - 2 }
+    }
+
+    /// Rejects a proof whose `k` is below 2 or whose rounds don't each
+    /// hold [`batched_eCP::pairs_per_round`] pairs.  `from_bytes` never
+    /// builds such a proof, but one assembled by hand can.
+    fn check_rounds(&self) -> Result<(), ProofError> {
+        let pairs = self.pairs_per_round();
+        if pairs == 0 || self.A_vecs.iter().any(|A_round| A_round.len() != pairs) {
+            return Err(ProofError::FormatError);
+        }
+        Ok(())
+    }
+
+    /// Replays the transcript and returns the verifier's scalars
+    /// `(z_s, s_P, s_A)` for a single multiscalar check.
+    ///
+    /// Returns [`ProofError::FormatError`] if the rounds are malformed,
+    /// see [`batched_eCP::pairs_per_round`].
+    pub fn verification_scalars<T: ProofTranscript>(
         &self,
         n: usize,
-        transcript: &mut Transcript,
+        transcript: &mut T,
     ) -> Result<(Vec<Scalar>, Scalar, Vec<Scalar>), ProofError> {
+        self.check_rounds()?;
         let k = self.k;
         let d = self.A_vecs.len();
 
@@ -973,7 +1580,7 @@ impl batched_eCP {
         }
 
         let mut challenges_inv = challenges.clone();
-        Scalar::batch_invert(&mut challenges_inv);
+        checked_batch_invert(&mut challenges_inv)?;
 
         let mut s_P = Scalar::one();
         let k_exp = k as u64;
@@ -985,6 +1592,8 @@ impl batched_eCP {
             product_so_far *= c_k;
         }
         s_P = product_so_far; 
+        #[cfg(feature = "debug")]
+        assert_eq!(s_P, challenge_power_product(&challenges, k_exp), "s_P != prod_r c_r^k");
 
         let mut z_s_vec = self.z.clone();
         for r in (0..d).rev() {
@@ -1021,21 +1630,51 @@ impl batched_eCP {
         Ok((z_s_vec, s_P, s_A_vec))
     }
     
-    #[allow(dead_code)]
-    pub fn verify(
+    /// Verifies the proof against the bases and the commitment `(P0, P1)`.
+    ///
+    /// The two relations are weighted by random scalars from the
+    /// verifier's RNG, as described on [`batched_eCP`]; the transcript is
+    /// left exactly as [`batched_eCP::verification_scalars`] leaves it.
+    pub fn verify<T: ProofTranscript>(
         &self,
-        transcript: &mut Transcript,
+        transcript: &mut T,
+        G_vec: &Vec<RistrettoPoint>,
+        C1_vec: &Vec<RistrettoPoint>,
+        P0: &RistrettoPoint,
+        P1: &RistrettoPoint,
+    ) -> Result<(), ProofError> {
+        let mut rng = transcript.witness_rng(&[]);
+        let w_G = Scalar::random(&mut rng);
+        let w_C = Scalar::random(&mut rng);
+        self.verify_with_weights(transcript, G_vec, C1_vec, P0, P1, w_G, w_C)
+    }
+
+    /// Like [`batched_eCP::verify`], with the `P0 = <a, G>` relation
+    /// weighted by `w_G` and the `P1 = <a, C1>` relation by `w_C`.
+    ///
+    /// The weights must be nonzero and unpredictable to the prover; the
+    /// R1CS verifier's fused check corresponds to `w_G = r^4` and
+    /// `w_C = r^3`.
+    pub fn verify_with_weights<T: ProofTranscript>(
+        &self,
+        transcript: &mut T,
         G_vec: &Vec<RistrettoPoint>,
         C1_vec: &Vec<RistrettoPoint>,
         P0: &RistrettoPoint,
         P1: &RistrettoPoint,
+        w_G: Scalar,
+        w_C: Scalar,
     ) -> Result<(), ProofError> {
         let n = G_vec.len();
+        // `create` pads a short `C1_vec` with identity points, which
+        // contribute nothing to the check.
+        if C1_vec.len() > n {
+            return Err(ProofError::InvalidGeneratorsLength);
+        }
+        self.check_rounds()?;
         let (z_s_vec, s_P, s_A_vec) =
             self.verification_scalars(n, transcript).map_err(|_| ProofError::VerificationError)?;
 
-        let r1 = transcript.challenge_scalar(b"r1");
-
         let mut A_points_combined: Vec<RistrettoPoint> =
             Vec::with_capacity(self.A_vecs.len() * (2 * self.k - 2));
 
@@ -1043,15 +1682,14 @@ impl batched_eCP {
             for i_list in 0..(2 * self.k - 2) {
                 let A_0 = self.A_vecs[r][i_list][0].decompress().ok_or(ProofError::VerificationError)?;
                 let A_1 = self.A_vecs[r][i_list][1].decompress().ok_or(ProofError::VerificationError)?;
-                A_points_combined.push(A_0 + r1 * A_1);
+                A_points_combined.push(w_G * A_0 + w_C * A_1);
             }
         }
-        
-        let z_s_r1: Vec<Scalar> = z_s_vec.iter().map(|s| s * r1).collect();
-        let P_comb = P0 + r1 * P1;
 
-        let scalars = z_s_vec.iter().cloned()
-            .chain(z_s_r1.into_iter())
+        let P_comb = w_G * P0 + w_C * P1;
+
+        let scalars = z_s_vec.iter().map(|s| s * w_G)
+            .chain(z_s_vec[..C1_vec.len()].iter().map(|s| s * w_C))
             .chain(iter::once(-s_P))
             .chain(s_A_vec.iter().map(|s| -s));
 
@@ -1065,6 +1703,7 @@ impl batched_eCP {
         if check.is_identity() { Ok(()) } else { Err(ProofError::VerificationError) }
     }
 
+    /// Returns the size in bytes of the proof serialized by [`batched_eCP::to_bytes`].
     pub fn serialized_size(&self) -> usize {
         let d = self.A_vecs.len();
         let mut num_points = 0;
@@ -1075,6 +1714,8 @@ impl batched_eCP {
         (3 + num_points + m) * 32
     }
     
+    /// Serializes the proof as `k`, the round count, the final-vector length,
+    /// the cross-term pairs and the final `z` vector.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(self.serialized_size());
         let mut temp = [0u8; 32];
@@ -1101,20 +1742,16 @@ impl batched_eCP {
         buf
     }
 
+    /// Deserializes a proof produced by [`batched_eCP::to_bytes`].
     pub fn from_bytes(slice: &[u8]) -> Result<batched_eCP, ProofError> {
          let b = slice.len();
          if b < 32 * 3 { return Err(ProofError::FormatError); }
          use util::read32; 
-         let mut pos = 0;
-         let k_bytes = read32(&slice[pos..]);
-         let k = u64::from_le_bytes(k_bytes[..8].try_into().unwrap()) as usize;
-         pos += 32;
-         let d_bytes = read32(&slice[pos..]);
-         let d = u64::from_le_bytes(d_bytes[..8].try_into().unwrap()) as usize;
-         pos += 32;
-         let m_bytes = read32(&slice[pos..]);
-         let m = u64::from_le_bytes(m_bytes[..8].try_into().unwrap()) as usize;
-         pos += 32;
+         let k = read_header(&slice[0..])?;
+         let d = read_header(&slice[32..])?;
+         let m = read_header(&slice[64..])?;
+         let mut pos = 3 * 32;
+         check_encoded_len(b, k, d, m, 2, 1)?;
 
          let mut A_vecs = Vec::with_capacity(d);
          for _ in 0..d {
@@ -1140,10 +1777,942 @@ impl batched_eCP {
     }
 }
 
+/// Reads a `u64` header from the first 8 bytes of its 32-byte slot.
+///
+/// The other 24 bytes must be zero, so that each proof has exactly one
+/// encoding.
+fn read_header(slot: &[u8]) -> Result<usize, ProofError> {
+    if slot[8..32].iter().any(|&byte| byte != 0) {
+        return Err(ProofError::FormatError);
+    }
+    Ok(u64::from_le_bytes(slot[..8].try_into().unwrap()) as usize)
+}
+
+// Test builds log the length of every witness buffer `batched_eCP::create`
+// clears, tagged with the buffer it came from.
+#[cfg(test)]
+thread_local! {
+    static CLEARED_LOG: RefCell<Option<Vec<(&'static str, usize)>>> = RefCell::new(None);
+}
+
+/// Zeroes every scalar in `scalars`, then empties it for reuse.
+fn clear_witness(_tag: &'static str, scalars: &mut Vec<Scalar>) {
+    #[cfg(test)]
+    CLEARED_LOG.with(|log| {
+        if let Some(log) = log.borrow_mut().as_mut() {
+            log.push((_tag, scalars.len()));
+        }
+    });
+    for e in scalars.iter_mut() {
+        e.clear();
+    }
+    scalars.clear();
+}
+
+/// Returns the encoded length of a folding proof with `k`, `d` and `m`
+/// headers, `points` compressed points per cross term and `vectors`
+/// final vectors, or `None` if the headers cannot describe a real proof.
+///
+/// Checking this before allocating keeps a corrupt header (for instance
+/// one written big-endian) from requesting a huge or overflowing buffer.
+fn encoded_len(k: usize, d: usize, m: usize, points: usize, vectors: usize) -> Option<usize> {
+    let cross_terms = k.checked_mul(2)?.checked_sub(2)?;
+    let slots = d
+        .checked_mul(cross_terms)?
+        .checked_mul(points)?
+        .checked_add(m.checked_mul(vectors)?)?
+        .checked_add(3)?;
+    slots.checked_mul(32)
+}
+
+/// Checks a folding proof's encoded length `len` against the length its
+/// headers declare (see [`encoded_len`]), telling a proof cut off within
+/// its rounds from one cut off within its final vectors.
+///
+/// A folding factor below 2 is rejected outright: with `k = 1` a round
+/// has no cross terms, so any round count would match the length and
+/// the reader would allocate one empty round per claimed round.
+fn check_encoded_len(
+    len: usize,
+    k: usize,
+    d: usize,
+    m: usize,
+    points: usize,
+    vectors: usize,
+) -> Result<(), ProofError> {
+    if k < 2 {
+        return Err(ProofError::FormatError);
+    }
+    let rounds_end = encoded_len(k, d, 0, points, vectors).ok_or(ProofError::FormatError)?;
+    let total = encoded_len(k, d, m, points, vectors).ok_or(ProofError::FormatError)?;
+    if len < rounds_end {
+        Err(ProofError::MissingRounds)
+    } else if len < total {
+        Err(ProofError::MissingFinalVectors)
+    } else if len > total {
+        Err(ProofError::FormatError)
+    } else {
+        Ok(())
+    }
+}
+
 pub fn inner_product(a: &[Scalar], b: &[Scalar]) -> Scalar {
     let mut out = Scalar::zero();
     if a.len() != b.len() { panic!("inner_product(a,b): lengths of vectors do not match"); }
     for i in 0..a.len() { out += a[i] * b[i]; }
     out
 }
-    
\ No newline at end of file
+
+/// Like `inner_product`, but also returns the per-term products `a_i * b_i`,
+/// so a discrepancy against a reference implementation can be localized.
+#[cfg(feature = "debug")]
+pub fn debug_inner_product(a: &[Scalar], b: &[Scalar]) -> (Scalar, Vec<Scalar>) {
+    if a.len() != b.len() { panic!("debug_inner_product(a,b): lengths of vectors do not match"); }
+    let terms: Vec<Scalar> = a.iter().zip(b.iter()).map(|(a_i, b_i)| a_i * b_i).collect();
+    let out = terms.iter().sum();
+    (out, terms)
+}
+    
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::seq::SliceRandom;
+    use rand::{thread_rng, Rng};
+    use sha3::Sha3_512;
+
+    use generators::BulletproofGens;
+    use transcript::TranscriptState;
+
+    fn k_bulletproof_instance(
+        n: usize,
+    ) -> (Vec<RistrettoPoint>, Vec<RistrettoPoint>, RistrettoPoint, Vec<Scalar>, Vec<Scalar>, RistrettoPoint) {
+        let mut rng = thread_rng();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let G: Vec<RistrettoPoint> = bp_gens.share(0).G(n).cloned().collect();
+        let H: Vec<RistrettoPoint> = bp_gens.share(0).H(n).cloned().collect();
+        let Q = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"test point");
+
+        let a: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let b: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let c = inner_product(&a, &b);
+
+        let P = RistrettoPoint::vartime_multiscalar_mul(
+            a.iter().chain(b.iter()).chain(iter::once(&c)),
+            G.iter().chain(H.iter()).chain(iter::once(&Q)),
+        );
+
+        (G, H, Q, a, b, P)
+    }
+
+    fn round_verifier_helper(n: usize, k: usize, num_rounds: usize) {
+        let (G, H, Q, a, b, P) = k_bulletproof_instance(n);
+
+        let mut prover_transcript = Transcript::new(b"RoundVerifierTest");
+        let proof = K_BulletProof::create(&mut prover_transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+        let mut batch_transcript = Transcript::new(b"RoundVerifierTest");
+        assert!(proof.verify(&mut batch_transcript, &G, &H, &Q, &P).is_ok());
+
+        let mut verifier_transcript = Transcript::new(b"RoundVerifierTest");
+        let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+        for U_round in proof.U_vecs.iter() {
+            verifier.push_round(U_round.clone()).unwrap();
+        }
+        assert_eq!(verifier.rounds(), num_rounds);
+        assert!(verifier
+            .finalize(proof.a_final.clone(), proof.b_final.clone(), &G, &H, &Q, &P)
+            .is_ok());
+
+        // Swapping two points in the last round changes every later scalar.
+        let mut verifier_transcript = Transcript::new(b"RoundVerifierTest");
+        let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+        let mut U_vecs = proof.U_vecs.clone();
+        U_vecs[num_rounds - 1].swap(0, 1);
+        for U_round in U_vecs {
+            verifier.push_round(U_round).unwrap();
+        }
+        assert!(verifier
+            .finalize(proof.a_final.clone(), proof.b_final.clone(), &G, &H, &Q, &P)
+            .is_err());
+    }
+
+    #[test]
+    fn create_auto_matches_full_depth_create() {
+        let (n, k) = (1000, 4);
+        let (G, H, Q, a, b, P) = k_bulletproof_instance(n);
+
+        // 1000 -> 250 -> 63 -> 16 -> 4 -> 1
+        let mut transcript = Transcript::new(b"CreateAutoTest");
+        let explicit = K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, 5);
+        let mut transcript = Transcript::new(b"CreateAutoTest");
+        let auto = K_BulletProof::create_auto(&mut transcript, k, &G, &H, Q, &a, &b);
+
+        assert_eq!(auto.U_vecs.len(), 5);
+        assert_eq!(auto.a_final.len(), 1);
+        assert_eq!(auto.to_bytes(), explicit.to_bytes());
+        let mut transcript = Transcript::new(b"CreateAutoTest");
+        assert!(auto.verify(&mut transcript, &G, &H, &Q, &P).is_ok());
+    }
+
+    #[test]
+    fn batch_verification_of_mixed_depths() {
+        let n = 16;
+        let (G, H, Q, _, _, _) = k_bulletproof_instance(n);
+        let mut rng = thread_rng();
+
+        // Three statements over the same bases, folded to different depths.
+        let mut proofs = Vec::new();
+        let mut commitments = Vec::new();
+        for &(k, num_rounds) in &[(2, 4), (4, 1), (2, 2)] {
+            let a: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+            let b: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+            let c = inner_product(&a, &b);
+            commitments.push(RistrettoPoint::vartime_multiscalar_mul(
+                a.iter().chain(b.iter()).chain(iter::once(&c)),
+                G.iter().chain(H.iter()).chain(iter::once(&Q)),
+            ));
+            let mut transcript = Transcript::new(b"BatchTest");
+            proofs.push(K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, num_rounds));
+        }
+
+        let verify = |proofs: &[K_BulletProof]| {
+            let batch: Vec<_> = proofs.iter().zip(commitments.iter()).collect();
+            let mut transcripts = vec![Transcript::new(b"BatchTest"); batch.len()];
+            K_BulletProof::verify_batch(&batch, &mut transcripts, &G, &H, &Q)
+        };
+        assert!(verify(&proofs).is_ok());
+
+        proofs[1].a_final[0] += Scalar::one();
+        assert_eq!(verify(&proofs), Err(ProofError::VerificationError));
+
+        // Any transcript the proofs were made on replays them.
+        let a: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let b: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let c = inner_product(&a, &b);
+        let P = RistrettoPoint::vartime_multiscalar_mul(
+            a.iter().chain(b.iter()).chain(iter::once(&c)),
+            G.iter().chain(H.iter()).chain(iter::once(&Q)),
+        );
+        let mut transcript = TranscriptState::new(b"BatchTest");
+        let proof = K_BulletProof::create(&mut transcript, 2, &G, &H, Q, &a, &b, 3);
+        let mut transcripts = [TranscriptState::new(b"BatchTest"), TranscriptState::new(b"BatchTest")];
+        assert!(K_BulletProof::verify_batch(&[(&proof, &P), (&proof, &P)], &mut transcripts, &G, &H, &Q).is_ok());
+    }
+
+    /// Folds `gens` round by round exactly as `K_BulletProof::create` does,
+    /// weighting the `i`-th split by `weights(c)[i]`.
+    fn fold_forward<F>(gens: &[RistrettoPoint], k: usize, challenges: &[Scalar], weights: F) -> Vec<RistrettoPoint>
+    where
+        F: Fn(Scalar) -> Vec<Scalar>,
+    {
+        let mut curr = gens.to_vec();
+        for c in challenges {
+            let rem = curr.len() % k;
+            if rem != 0 {
+                curr.extend(iter::repeat(RistrettoPoint::default()).take(k - rem));
+            }
+            let m = curr.len() / k;
+            let w = weights(*c);
+            curr = (0..m)
+                .map(|j| RistrettoPoint::vartime_multiscalar_mul(w.iter(), (0..k).map(|i| curr[i * m + j])))
+                .collect();
+        }
+        curr
+    }
+
+    fn folded_generators_match_helper(n: usize, k: usize, num_rounds: usize) {
+        let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+
+        let mut prover_transcript = Transcript::new(b"FoldTest");
+        let proof = K_BulletProof::create(&mut prover_transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+        // Replay the verifier's side of the transcript to recover the challenges.
+        let mut verifier_transcript = Transcript::new(b"FoldTest");
+        let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+        let challenges: Vec<Scalar> = proof.U_vecs.iter()
+            .map(|U_round| verifier.push_round(U_round.clone()).unwrap())
+            .collect();
+
+        let (s_g, s_h, _, _, _) = proof.scalars_from_challenges(n, &challenges).unwrap();
+
+        let g_final = fold_forward(&G, k, &challenges, |c| {
+            let c_inv = c.invert();
+            let c_k_minus_1 = scalar_pow(c, (k - 1) as u64);
+            exp_powers(c_inv, k).into_iter().map(|x| x * c_k_minus_1).collect()
+        });
+        let h_final = fold_forward(&H, k, &challenges, |c| exp_powers(c, k));
+
+        assert_eq!(
+            RistrettoPoint::vartime_multiscalar_mul(s_g.iter(), G.iter()),
+            RistrettoPoint::vartime_multiscalar_mul(proof.a_final.iter(), g_final.iter()),
+        );
+        assert_eq!(
+            RistrettoPoint::vartime_multiscalar_mul(s_h.iter(), H.iter()),
+            RistrettoPoint::vartime_multiscalar_mul(proof.b_final.iter(), h_final.iter()),
+        );
+    }
+
+    fn exp_powers(x: Scalar, k: usize) -> Vec<Scalar> {
+        let mut powers = Vec::with_capacity(k);
+        let mut acc = Scalar::one();
+        for _ in 0..k {
+            powers.push(acc);
+            acc *= x;
+        }
+        powers
+    }
+
+    /// Folds `v` as the prover did before its buffers were reused across
+    /// rounds: padding and allocating a fresh vector every round.
+    fn fold_scalars_reference<F>(v: &[Scalar], k: usize, challenges: &[Scalar], weights: F) -> Vec<Scalar>
+    where
+        F: Fn(Scalar) -> Vec<Scalar>,
+    {
+        let mut curr = v.to_vec();
+        for c in challenges {
+            let rem = curr.len() % k;
+            if rem != 0 {
+                curr.extend(iter::repeat(Scalar::zero()).take(k - rem));
+            }
+            let m = curr.len() / k;
+            let w = weights(*c);
+            let mut next = vec![Scalar::zero(); m];
+            for j in 0..m {
+                for i in 0..k {
+                    next[j] += curr[i * m + j] * w[i];
+                }
+            }
+            curr = next;
+        }
+        curr
+    }
+
+    #[test]
+    fn double_buffered_fold_matches_reference() {
+        let (n, k, num_rounds) = (23, 3, 3);
+        let (G, H, Q, a, b, P) = k_bulletproof_instance(n);
+
+        let mut prover_transcript = Transcript::new(b"FoldTest");
+        let proof = K_BulletProof::create(&mut prover_transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+        let mut verifier_transcript = Transcript::new(b"FoldTest");
+        let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+        let challenges: Vec<Scalar> = proof.U_vecs.iter()
+            .map(|U_round| verifier.push_round(U_round.clone()).unwrap())
+            .collect();
+
+        let a_final = fold_scalars_reference(&a, k, &challenges, |c| exp_powers(c, k));
+        let b_final = fold_scalars_reference(&b, k, &challenges, |c| {
+            let mut w = exp_powers(c, k);
+            w.reverse();
+            w
+        });
+        assert_eq!(proof.a_final, a_final);
+        assert_eq!(proof.b_final, b_final);
+
+        // The generator buffers feed every later round's `U` points.
+        assert!(verifier.finalize(a_final, b_final, &G, &H, &Q, &P).is_ok());
+    }
+
+    #[test]
+    fn folded_generators_match_k2() {
+        folded_generators_match_helper(8, 2, 3);
+    }
+
+    #[test]
+    fn folded_generators_match_k3_padded() {
+        folded_generators_match_helper(7, 3, 2);
+    }
+
+    #[test]
+    fn verify_with_h_factors() {
+        let n = 9;
+        let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+        let mut rng = thread_rng();
+        let factors: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let H_prime: Vec<RistrettoPoint> = H.iter().zip(factors.iter()).map(|(H_i, f_i)| H_i * f_i).collect();
+
+        let c = inner_product(&a, &b);
+        let P = RistrettoPoint::vartime_multiscalar_mul(
+            a.iter().chain(b.iter()).chain(iter::once(&c)),
+            G.iter().chain(H_prime.iter()).chain(iter::once(&Q)),
+        );
+
+        let mut prover_transcript = Transcript::new(b"FactorsTest");
+        let proof = K_BulletProof::create(&mut prover_transcript, 3, &G, &H_prime, Q, &a, &b, 2);
+
+        let mut verifier_transcript = Transcript::new(b"FactorsTest");
+        assert!(proof.verify_with_factors(&mut verifier_transcript, &factors, &G, &H, &Q, &P).is_ok());
+
+        let mut verifier_transcript = Transcript::new(b"FactorsTest");
+        let ones = vec![Scalar::one(); n];
+        assert!(proof.verify_with_factors(&mut verifier_transcript, &ones, &G, &H, &Q, &P).is_err());
+
+        let mut verifier_transcript = Transcript::new(b"FactorsTest");
+        assert!(proof.verify_with_factors(&mut verifier_transcript, &factors[1..], &G, &H, &Q, &P).is_err());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn debug_inner_product_terms_sum_to_inner_product() {
+        let mut rng = thread_rng();
+        let a: Vec<_> = (0..7).map(|_| Scalar::random(&mut rng)).collect();
+        let b: Vec<_> = (0..7).map(|_| Scalar::random(&mut rng)).collect();
+
+        let (out, terms) = debug_inner_product(&a, &b);
+        assert_eq!(terms.len(), 7);
+        assert_eq!(terms[3], a[3] * b[3]);
+        assert_eq!(out, inner_product(&a, &b));
+        assert_eq!(terms.iter().sum::<Scalar>(), inner_product(&a, &b));
+    }
+
+    #[test]
+    fn inner_product_proof_verify_compressed_p() {
+        let n = 8;
+        let mut rng = thread_rng();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let G: Vec<RistrettoPoint> = bp_gens.share(0).G(n).cloned().collect();
+        let H: Vec<RistrettoPoint> = bp_gens.share(0).H(n).cloned().collect();
+        let Q = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"test point");
+
+        let a: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let b: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let c = inner_product(&a, &b);
+        let ones = vec![Scalar::one(); n];
+
+        let P = RistrettoPoint::vartime_multiscalar_mul(
+            a.iter().chain(b.iter()).chain(iter::once(&c)),
+            G.iter().chain(H.iter()).chain(iter::once(&Q)),
+        );
+
+        let mut prover_transcript = Transcript::new(b"innerproducttest");
+        let proof = InnerProductProof::create(
+            &mut prover_transcript, &Q, &ones, G.clone(), H.clone(), a.clone(), b.clone(),
+        );
+
+        let mut verifier_transcript = Transcript::new(b"innerproducttest");
+        assert!(proof
+            .verify_compressed_p(n, &mut verifier_transcript, &ones, &P.compress(), &Q, &G, &H)
+            .is_ok());
+
+        let mut verifier_transcript = Transcript::new(b"innerproducttest");
+        assert_eq!(
+            proof.verify_compressed_p(n, &mut verifier_transcript, &ones, &Q.compress(), &Q, &G, &H),
+            Err(ProofError::VerificationError)
+        );
+
+        let non_canonical = CompressedRistretto([0xff; 32]);
+        let mut verifier_transcript = Transcript::new(b"innerproducttest");
+        assert_eq!(
+            proof.verify_compressed_p(n, &mut verifier_transcript, &ones, &non_canonical, &Q, &G, &H),
+            Err(ProofError::FormatError)
+        );
+    }
+
+    #[test]
+    fn binary_k_bulletproof_agrees_with_inner_product_proof() {
+        // With k = 2 folded all the way down, the k-ary argument is the
+        // binary one: log2(n) rounds of two cross terms, ending in a single
+        // a and b.  Both must accept the same statement and reject a wrong one.
+        let n = 8;
+        let (G, H, Q, a, b, P) = k_bulletproof_instance(n);
+        let ones = vec![Scalar::one(); n];
+        let wrong_P = P + Q;
+
+        let mut transcript = Transcript::new(b"BinaryAgreementTest");
+        let k_proof = K_BulletProof::create(&mut transcript, 2, &G, &H, Q, &a, &b, 3);
+        let mut transcript = Transcript::new(b"BinaryAgreementTest");
+        let binary = InnerProductProof::create(&mut transcript, &Q, &ones, G.clone(), H.clone(), a, b);
+
+        assert_eq!(k_proof.U_vecs.len(), binary.L_vec.len());
+        assert!(k_proof.U_vecs.iter().all(|U_round| U_round.len() == 2));
+        assert_eq!((k_proof.a_final.len(), k_proof.b_final.len()), (1, 1));
+
+        for &(P, expected) in [(&P, true), (&wrong_P, false)].iter() {
+            let mut transcript = Transcript::new(b"BinaryAgreementTest");
+            assert_eq!(k_proof.verify(&mut transcript, &G, &H, &Q, P).is_ok(), expected);
+            let mut transcript = Transcript::new(b"BinaryAgreementTest");
+            assert_eq!(binary.verify(n, &mut transcript, &ones, P, &Q, &G, &H).is_ok(), expected);
+        }
+    }
+
+    #[test]
+    fn try_create_rejects_zero_hprime_factor() {
+        let n = 4;
+        let mut rng = thread_rng();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let G: Vec<RistrettoPoint> = bp_gens.share(0).G(n).cloned().collect();
+        let H: Vec<RistrettoPoint> = bp_gens.share(0).H(n).cloned().collect();
+        let Q = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"test point");
+        let a: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let b: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let mut factors = vec![Scalar::one(); n];
+
+        let mut transcript = Transcript::new(b"innerproducttest");
+        assert!(InnerProductProof::try_create(
+            &mut transcript, &Q, &factors, G.clone(), H.clone(), a.clone(), b.clone(),
+        ).is_ok());
+
+        factors[2] = Scalar::zero();
+        let mut transcript = Transcript::new(b"innerproducttest");
+        assert_eq!(
+            InnerProductProof::try_create(&mut transcript, &Q, &factors, G, H, a, b).err(),
+            Some(ProofError::InvalidGeneratorsLength)
+        );
+    }
+
+    #[test]
+    fn prefixed_proof_is_self_delimiting() {
+        let n = 8;
+        let mut rng = thread_rng();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let G: Vec<RistrettoPoint> = bp_gens.share(0).G(n).cloned().collect();
+        let H: Vec<RistrettoPoint> = bp_gens.share(0).H(n).cloned().collect();
+        let Q = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"test point");
+        let a: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let b: Vec<_> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let ones = vec![Scalar::one(); n];
+
+        let mut transcript = Transcript::new(b"innerproducttest");
+        let proof = InnerProductProof::create(&mut transcript, &Q, &ones, G, H, a, b);
+
+        let header = b"variable-length header";
+        let trailer = b"trailing data";
+        let mut message = header.to_vec();
+        message.extend_from_slice(&proof.to_bytes_prefixed());
+        message.extend_from_slice(trailer);
+
+        let (decoded, len) = InnerProductProof::from_bytes_prefixed(&message[header.len()..]).unwrap();
+        assert_eq!(len, 4 + proof.serialized_size());
+        assert_eq!(decoded.to_bytes(), proof.to_bytes());
+        assert_eq!(&message[header.len() + len..], &trailer[..]);
+
+        // A prefix claiming more elements than are present is rejected.
+        let prefixed = proof.to_bytes_prefixed();
+        assert_eq!(
+            InnerProductProof::from_bytes_prefixed(&prefixed[..prefixed.len() - 32]).unwrap_err(),
+            ProofError::FormatError
+        );
+    }
+
+    #[test]
+    fn round_verifier_k2() {
+        round_verifier_helper(16, 2, 4);
+    }
+
+    #[test]
+    fn round_verifier_k3_padded() {
+        round_verifier_helper(10, 3, 2);
+    }
+
+    #[test]
+    fn round_verifier_on_custom_transcript() {
+        let (n, k, num_rounds) = (16, 2, 4);
+        let (G, H, Q, a, b, P) = k_bulletproof_instance(n);
+
+        let mut prover_transcript = TranscriptState::new(b"RoundVerifierTest");
+        let proof = K_BulletProof::create(&mut prover_transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+        let mut verifier_transcript = TranscriptState::new(b"RoundVerifierTest");
+        let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+        for U_round in proof.U_vecs.iter() {
+            verifier.push_round(U_round.clone()).unwrap();
+        }
+        assert!(verifier
+            .finalize(proof.a_final.clone(), proof.b_final.clone(), &G, &H, &Q, &P)
+            .is_ok());
+    }
+
+    #[test]
+    fn round_verifier_rejects_short_round() {
+        let mut transcript = Transcript::new(b"RoundVerifierTest");
+        let mut verifier = RoundVerifier::new(&mut transcript, 8, 4).unwrap();
+        let round = vec![RistrettoPoint::default().compress(); 5];
+        assert!(verifier.push_round(round).is_err());
+    }
+
+    #[test]
+    fn zero_challenge_is_rejected() {
+        let (n, k, num_rounds) = (8, 2, 3);
+        let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+
+        let mut prover_transcript = Transcript::new(b"ZeroChallengeTest");
+        let proof = K_BulletProof::create(&mut prover_transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+        let mut verifier_transcript = Transcript::new(b"ZeroChallengeTest");
+        let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+        let mut challenges: Vec<Scalar> = proof.U_vecs.iter()
+            .map(|U_round| verifier.push_round(U_round.clone()).unwrap())
+            .collect();
+        assert!(proof.scalars_from_challenges(n, &challenges).is_ok());
+
+        challenges[1] = Scalar::zero();
+        assert_eq!(
+            proof.scalars_from_challenges(n, &challenges).unwrap_err(),
+            ProofError::VerificationError
+        );
+    }
+
+    #[test]
+    fn folded_basis_matches_forward_fold() {
+        for &(n, k, num_rounds) in &[(8, 2, 3), (10, 3, 3), (9, 3, 2)] {
+            let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+
+            let mut prover_transcript = Transcript::new(b"FoldedBasisTest");
+            let proof = K_BulletProof::create(&mut prover_transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+            let mut verifier_transcript = Transcript::new(b"FoldedBasisTest");
+            let mut verifier = RoundVerifier::new(&mut verifier_transcript, n, k).unwrap();
+            let challenges: Vec<Scalar> = proof.U_vecs.iter()
+                .map(|U_round| verifier.push_round(U_round.clone()).unwrap())
+                .collect();
+
+            let g_final = fold_forward(&G, k, &challenges, |c| {
+                let c_inv = c.invert();
+                let c_k_minus_1 = scalar_pow(c, (k - 1) as u64);
+                exp_powers(c_inv, k).into_iter().map(|x| x * c_k_minus_1).collect()
+            });
+            let h_final = fold_forward(&H, k, &challenges, |c| exp_powers(c, k));
+
+            assert_eq!((g_final.len(), h_final.len()), (1, 1));
+
+            let mut transcript = Transcript::new(b"FoldedBasisTest");
+            let basis = proof.folded_basis(&G, &H, &mut transcript).unwrap();
+            assert_eq!(basis, (g_final[0], h_final[0]));
+        }
+
+        // 9 folded once by 3 leaves three elements, not one.
+        let (G, H, Q, a, b, _) = k_bulletproof_instance(9);
+        let mut transcript = Transcript::new(b"FoldedBasisTest");
+        let proof = K_BulletProof::create(&mut transcript, 3, &G, &H, Q, &a, &b, 1);
+        let mut transcript = Transcript::new(b"FoldedBasisTest");
+        assert_eq!(proof.folded_basis(&G, &H, &mut transcript), Err(ProofError::FoldingError));
+    }
+
+    #[test]
+    fn s_P_is_product_of_challenge_powers() {
+        // n = 9 folds by 3 to 3, then to 1.
+        let p = RistrettoPoint::default().compress();
+        let proof = K_BulletProof {
+            k: 3,
+            U_vecs: vec![vec![p; 4]; 2],
+            a_final: vec![Scalar::one()],
+            b_final: vec![Scalar::one()],
+        };
+        let challenges = [Scalar::from(2u64), Scalar::from(3u64)];
+
+        let (_, _, _, s_P, _) = proof.scalars_from_challenges(9, &challenges).unwrap();
+        assert_eq!(s_P, Scalar::from(36u64));
+        assert_eq!(s_P, challenge_power_product(&challenges, 2));
+    }
+
+    /// Runs `f` and returns the transcript operations it logged.
+    fn recorded<F: FnOnce()>(f: F) -> Vec<(&'static str, &'static [u8])> {
+        TRANSCRIPT_LOG.with(|log| *log.borrow_mut() = Some(Vec::new()));
+        f();
+        TRANSCRIPT_LOG.with(|log| log.borrow_mut().take().unwrap())
+    }
+
+    #[test]
+    fn prover_and_verifier_transcripts_match() {
+        for &(n, k, num_rounds) in [(16, 2, 4), (27, 3, 3), (10, 3, 2)].iter() {
+            let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+
+            let mut proof = None;
+            let prover_log = recorded(|| {
+                let mut transcript = Transcript::new(b"RecorderTest");
+                proof = Some(K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, num_rounds));
+            });
+            let proof = proof.unwrap();
+
+            let verifier_log = recorded(|| {
+                let mut transcript = Transcript::new(b"RecorderTest");
+                proof.verification_scalars(n, &mut transcript).unwrap();
+            });
+
+            assert_eq!(prover_log, verifier_log);
+            let challenges = prover_log.iter().filter(|(op, _)| *op == "challenge").count();
+            assert_eq!(challenges, num_rounds);
+        }
+    }
+
+    /// Proves a fixed instance and checks the proof bytes against a known
+    /// digest, so a build with `--features simd` must reproduce the serial
+    /// backend's proof exactly.
+    #[test]
+    fn k_bulletproof_bytes_are_backend_independent() {
+        use digest::Digest;
+        use sha3::Sha3_256;
+
+        let (n, k, num_rounds) = (12, 3, 2);
+        let bp_gens = BulletproofGens::new(n, 1);
+        let G: Vec<RistrettoPoint> = bp_gens.share(0).G(n).cloned().collect();
+        let H: Vec<RistrettoPoint> = bp_gens.share(0).H(n).cloned().collect();
+        let Q = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"test point");
+        let a: Vec<Scalar> = (0..n).map(|i| Scalar::from(i as u64 + 1)).collect();
+        let b: Vec<Scalar> = (0..n).map(|i| Scalar::from(2 * i as u64 + 3)).collect();
+
+        let mut transcript = Transcript::new(b"BackendTest");
+        let proof = K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, num_rounds);
+
+        let expected: [u8; 32] = [
+            156, 89, 247, 183, 247, 227, 225, 216, 234, 73, 98, 102, 230, 203, 90, 93,
+            18, 48, 249, 189, 3, 142, 74, 33, 167, 68, 137, 162, 151, 170, 7, 52,
+        ];
+        let mut hasher = Sha3_256::new();
+        hasher.input(&proof.to_bytes());
+        assert_eq!(hasher.result().as_slice(), &expected[..]);
+    }
+
+    #[test]
+    fn borrowed_view_matches_owned_proof() {
+        let (n, k, num_rounds) = (27, 3, 2);
+        let (G, H, Q, a, b, P) = k_bulletproof_instance(n);
+        let mut transcript = Transcript::new(b"BorrowedViewTest");
+        let bytes = K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, num_rounds).to_bytes();
+
+        let owned = K_BulletProof::from_bytes(&bytes).unwrap();
+        let view = K_BulletProofRef::from_bytes(&bytes).unwrap();
+        assert_eq!(view.to_proof().to_bytes(), bytes);
+        assert_eq!(view.U_point(1, 2), owned.U_vecs[1][2]);
+
+        let mut transcript = Transcript::new(b"BorrowedViewTest");
+        assert!(owned.verify(&mut transcript, &G, &H, &Q, &P).is_ok());
+        let mut transcript = Transcript::new(b"BorrowedViewTest");
+        assert!(view.verify(&mut transcript, &G, &H, &Q, &P).is_ok());
+
+        // A wrong commitment fails through the view as well.
+        let mut transcript = Transcript::new(b"BorrowedViewTest");
+        assert!(view.verify(&mut transcript, &G, &H, &Q, &(P + Q)).is_err());
+
+        // Framing errors are caught up front, as by the owned path.
+        assert!(K_BulletProofRef::from_bytes(&bytes[..bytes.len() - 32]).is_err());
+        let mut non_canonical = bytes.clone();
+        let last = non_canonical.len() - 1;
+        non_canonical[last] = 0xff;
+        assert!(K_BulletProof::from_bytes(&non_canonical).is_err());
+        assert!(K_BulletProofRef::from_bytes(&non_canonical).is_err());
+    }
+
+    #[test]
+    fn truncation_is_located() {
+        let (n, k, num_rounds) = (27, 3, 2);
+        let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+        let mut transcript = Transcript::new(b"TruncationTest");
+        let bytes = K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, num_rounds).to_bytes();
+
+        // Header, then 2 rounds of 4 points, then a_final and b_final.
+        let rounds_end = (3 + num_rounds * (2 * k - 2)) * 32;
+        assert_eq!(
+            K_BulletProof::from_bytes(&bytes[..rounds_end]).unwrap_err(),
+            ProofError::MissingFinalVectors
+        );
+        assert_eq!(
+            K_BulletProofRef::from_bytes(&bytes[..rounds_end]).unwrap_err(),
+            ProofError::MissingFinalVectors
+        );
+        assert_eq!(
+            K_BulletProof::from_bytes(&bytes[..rounds_end - 32]).unwrap_err(),
+            ProofError::MissingRounds
+        );
+        assert_eq!(
+            K_BulletProof::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            ProofError::MissingFinalVectors
+        );
+
+        let mut extended = bytes.clone();
+        extended.extend_from_slice(&[0u8; 32]);
+        assert_eq!(K_BulletProof::from_bytes(&extended).unwrap_err(), ProofError::FormatError);
+    }
+
+    /// Builds a folding proof header claiming `(k, d, m)`, followed by
+    /// `body` zero bytes.
+    fn header_bytes(k: u64, d: u64, m: u64, body: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; 96 + body];
+        bytes[0..8].copy_from_slice(&k.to_le_bytes());
+        bytes[32..40].copy_from_slice(&d.to_le_bytes());
+        bytes[64..72].copy_from_slice(&m.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected_before_allocating() {
+        // With k < 2 a round holds no points, so a huge d would match the
+        // length of a bare header.
+        for &k in [0, 1].iter() {
+            let bytes = header_bytes(k, 1 << 40, 0, 0);
+            assert_eq!(K_BulletProof::from_bytes(&bytes).unwrap_err(), ProofError::FormatError);
+            assert_eq!(K_BulletProofRef::from_bytes(&bytes).unwrap_err(), ProofError::FormatError);
+            assert_eq!(batched_eCP::from_bytes(&bytes).unwrap_err(), ProofError::FormatError);
+        }
+
+        // Sizes that overflow are malformed; ones that merely exceed the
+        // slice are reported as truncated.
+        for &(k, d, m) in [(3, u64::max_value(), 0), (u64::max_value(), 2, 0), (2, 0, u64::max_value())].iter() {
+            let bytes = header_bytes(k, d, m, 0);
+            assert_eq!(K_BulletProof::from_bytes(&bytes).unwrap_err(), ProofError::FormatError);
+            assert_eq!(batched_eCP::from_bytes(&bytes).unwrap_err(), ProofError::FormatError);
+        }
+        let bytes = header_bytes(3, 1 << 40, 1, 64);
+        assert_eq!(K_BulletProof::from_bytes(&bytes).unwrap_err(), ProofError::MissingRounds);
+        assert_eq!(batched_eCP::from_bytes(&bytes).unwrap_err(), ProofError::MissingRounds);
+    }
+
+    #[test]
+    fn random_headers_never_panic() {
+        fn pick<R: Rng>(rng: &mut R) -> u64 {
+            let interesting = [0, 1, 2, 3, 1 << 20, 1 << 40, u64::max_value()];
+            if rng.gen::<bool>() {
+                *interesting.choose(rng).unwrap()
+            } else {
+                rng.gen_range(0, 8)
+            }
+        }
+
+        let mut rng = thread_rng();
+        for _ in 0..2000 {
+            let (k, d, m) = (pick(&mut rng), pick(&mut rng), pick(&mut rng));
+            let bytes = header_bytes(k, d, m, 32 * rng.gen_range(0, 16));
+
+            // Zero bytes are canonical scalars, so a header matching the
+            // length parses; anything else is an error, never a panic.
+            if let Ok(proof) = K_BulletProof::from_bytes(&bytes) {
+                assert_eq!(proof.to_bytes(), bytes);
+            }
+            if let Ok(proof) = batched_eCP::from_bytes(&bytes) {
+                assert_eq!(proof.to_bytes(), bytes);
+            }
+        }
+    }
+
+    /// A consistency proof over `n` witness entries, with `C1` shorter
+    /// than the witness as in a padded shuffle.
+    fn ecp_instance(
+        n: usize,
+        k: usize,
+        num_rounds: usize,
+    ) -> (batched_eCP, Vec<RistrettoPoint>, Vec<RistrettoPoint>, RistrettoPoint, RistrettoPoint) {
+        let mut rng = thread_rng();
+        let bp_gens = BulletproofGens::new(n, 1);
+        let G: Vec<RistrettoPoint> = bp_gens.share(0).G(n).cloned().collect();
+        let C1: Vec<RistrettoPoint> = (0..n - 2).map(|_| RistrettoPoint::random(&mut rng)).collect();
+        let mut a: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        a[n - 2] = Scalar::zero();
+        a[n - 1] = Scalar::zero();
+
+        let P0 = RistrettoPoint::vartime_multiscalar_mul(&a, &G);
+        let P1 = RistrettoPoint::vartime_multiscalar_mul(&a[..n - 2], &C1);
+        let mut transcript = Transcript::new(b"ECPTest");
+        let proof = batched_eCP::create(&mut transcript, k, &G, &C1, &a, num_rounds);
+        (proof, G, C1, P0, P1)
+    }
+
+    #[test]
+    fn standalone_ecp_verify_matches_fused_weights() {
+        let mut rng = thread_rng();
+        for &(n, k, num_rounds) in [(16, 2, 3), (9, 3, 2)].iter() {
+            let (proof, G, C1, P0, P1) = ecp_instance(n, k, num_rounds);
+            let mut tampered = proof.clone();
+            tampered.z[0] += Scalar::one();
+
+            let r = Scalar::random(&mut rng);
+            let (r3, r4) = (r * r * r, r * r * r * r);
+            for &(proof, expected) in [(&proof, true), (&tampered, false)].iter() {
+                let mut transcript = Transcript::new(b"ECPTest");
+                let standalone = proof.verify(&mut transcript, &G, &C1, &P0, &P1);
+                let mut transcript = Transcript::new(b"ECPTest");
+                let fused = proof.verify_with_weights(&mut transcript, &G, &C1, &P0, &P1, r4, r3);
+                assert_eq!(standalone.is_ok(), expected);
+                assert_eq!(fused.is_ok(), expected);
+            }
+
+            // Verifying draws no challenge beyond the folding challenges.
+            let mut verified = Transcript::new(b"ECPTest");
+            proof.verify(&mut verified, &G, &C1, &P0, &P1).unwrap();
+            let mut replayed = Transcript::new(b"ECPTest");
+            proof.verification_scalars(n, &mut replayed).unwrap();
+            assert_eq!(verified.challenge_scalar(b"next"), replayed.challenge_scalar(b"next"));
+        }
+    }
+
+    #[test]
+    fn ecp_with_zero_rounds_round_trips_and_verifies() {
+        // With no rounds the proof is just the unfolded vector `z = a`,
+        // and the prover and verifier append the same three setup
+        // messages before drawing nothing.
+        let n = 6;
+        let (proof, G, C1, P0, P1) = ecp_instance(n, 3, 0);
+        assert!(proof.A_vecs.is_empty());
+        assert_eq!(proof.z.len(), n);
+
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes.len(), proof.serialized_size());
+        assert_eq!(bytes.len(), (3 + n) * 32);
+        let decoded = batched_eCP::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        let mut transcript = Transcript::new(b"ECPTest");
+        assert!(decoded.verify(&mut transcript, &G, &C1, &P0, &P1).is_ok());
+        let mut prover_transcript = Transcript::new(b"ECPTest");
+        batched_eCP::create(&mut prover_transcript, 3, &G, &C1, &proof.z, 0);
+        assert_eq!(transcript.challenge_scalar(b"next"), prover_transcript.challenge_scalar(b"next"));
+
+        let mut tampered = decoded.clone();
+        tampered.z[0] += Scalar::one();
+        let mut transcript = Transcript::new(b"ECPTest");
+        assert!(tampered.verify(&mut transcript, &G, &C1, &P0, &P1).is_err());
+    }
+
+    #[test]
+    fn ecp_rejects_short_round() {
+        let (mut proof, G, C1, P0, P1) = ecp_instance(16, 2, 3);
+        assert_eq!(proof.pairs_per_round(), 2);
+        proof.A_vecs[1].pop();
+
+        let mut transcript = Transcript::new(b"ECPTest");
+        assert_eq!(proof.verification_scalars(16, &mut transcript).unwrap_err(), ProofError::FormatError);
+        let mut transcript = Transcript::new(b"ECPTest");
+        assert_eq!(proof.verify(&mut transcript, &G, &C1, &P0, &P1), Err(ProofError::FormatError));
+    }
+
+    #[test]
+    fn nonzero_header_padding_is_rejected() {
+        let (n, k, num_rounds) = (27, 3, 2);
+        let (G, H, Q, a, b, _) = k_bulletproof_instance(n);
+        let mut transcript = Transcript::new(b"HeaderPaddingTest");
+        let ipp_bytes = K_BulletProof::create(&mut transcript, k, &G, &H, Q, &a, &b, num_rounds).to_bytes();
+        let ecp_bytes = ecp_instance(16, 2, 3).0.to_bytes();
+        assert!(K_BulletProof::from_bytes(&ipp_bytes).is_ok());
+        assert!(batched_eCP::from_bytes(&ecp_bytes).is_ok());
+
+        // Bytes 8..32 of each of the `k`, `d` and `m` slots are padding.
+        for &slot in [0, 32, 64].iter() {
+            for &offset in [8, 31].iter() {
+                let mut ipp = ipp_bytes.clone();
+                ipp[slot + offset] = 1;
+                assert_eq!(K_BulletProof::from_bytes(&ipp).unwrap_err(), ProofError::FormatError);
+                assert_eq!(K_BulletProofRef::from_bytes(&ipp).unwrap_err(), ProofError::FormatError);
+
+                let mut ecp = ecp_bytes.clone();
+                ecp[slot + offset] = 1;
+                assert_eq!(batched_eCP::from_bytes(&ecp).unwrap_err(), ProofError::FormatError);
+            }
+        }
+    }
+
+    #[test]
+    fn ecp_create_clears_folded_witness() {
+        CLEARED_LOG.with(|log| *log.borrow_mut() = Some(Vec::new()));
+        let (proof, _, _, _, _) = ecp_instance(16, 2, 3);
+        let log = CLEARED_LOG.with(|log| log.borrow_mut().take().unwrap());
+
+        // Each round's input vector is cleared once folded, and the
+        // scratch buffers once the last round is done; the final `z` is
+        // the proof itself and is kept.
+        let folded: Vec<usize> = log.iter().filter(|(tag, _)| *tag == "a").map(|(_, len)| *len).collect();
+        assert_eq!(folded, vec![16, 8, 4]);
+        assert_eq!(&log[log.len() - 2..], &[("scalars", 2), ("scalars", 2)][..]);
+        assert_eq!(proof.z.len(), 2);
+    }
+}
@@ -22,9 +22,13 @@ extern crate serde;
 #[macro_use]
 extern crate failure;
 
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
 #[cfg(test)]
 extern crate bincode;
 
+#[macro_use]
 mod util;
 
 #[doc= include_str!("../docs/notes-intro.md")]
@@ -44,8 +48,16 @@ mod range_proof;
 mod transcript;
 
 pub use errors::ProofError;
-pub use generators::{BulletproofGens, BulletproofGensShare, PedersenGens};
+pub use generators::{BulletproofGens, BulletproofGensShare, PedersenGens, PrecomputedGens};
 pub use range_proof::RangeProof;
+pub use transcript::{ProofTranscript, TranscriptState};
+
+#[cfg(feature = "debug")]
+pub use generators::check_generator_alignment;
+#[cfg(feature = "debug")]
+pub use inner_product_proof::debug_inner_product;
+#[cfg(feature = "debug")]
+pub use transcript::RecordingTranscript;
 
 #[doc= include_str!("../docs/aggregation-api.md")]
 pub mod range_proof_mpc {
@@ -57,3 +69,26 @@ pub mod range_proof_mpc {
 
 #[cfg(feature = "yoloproofs")]
 pub mod r1cs;
+
+/// Re-exports of the types most users need, so that a single
+/// `use bulletproofs::prelude::*;` brings the proof, generator and
+/// configuration types into scope.
+pub mod prelude {
+    pub use errors::ProofError;
+    pub use generators::{BulletproofGens, PedersenGens, PrecomputedGens};
+    pub use inner_product_proof::{
+        batched_eCP, InnerProductProof, K_BulletProof, K_BulletProofRef, RoundVerifier,
+    };
+    pub use range_proof::RangeProof;
+    pub use transcript::{ProofTranscript, TranscriptState};
+
+    #[cfg(feature = "yoloproofs")]
+    pub use r1cs::{
+        config_for_verifier_time, gens_capacity_for_shuffle, rounds_for_size_budget,
+        rounds_for_target, shuffle_proof_size, AggregatedShuffleProof, BatchVerifierAccumulator,
+        CiphertextTree, ComponentStatement, ConstraintSystem, FixedDepthShuffle, LinearCombination,
+        MerkleProof, PermutationWitness, ProofConfig, Prover, R1CSError, R1CSProof,
+        SamePermutationProof, ShuffleChain, ShuffleClaim, ShuffleParams, ShuffleProof,
+        ShuffleStatement, ShuffleWitness, StreamedStatement, Variable, Verifier,
+    };
+}
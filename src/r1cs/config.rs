@@ -0,0 +1,447 @@
+//! Parameters of a k-ary shuffle proof.
+
+#[cfg(feature = "bench")]
+use std::time::{Duration, Instant};
+
+use super::R1CSProof;
+use errors::R1CSError;
+use inner_product_proof::reconstruct_round_lengths;
+
+/// The size parameters a prover and verifier agree on for a proof:
+/// the padded witness length, the folding factor and the number of
+/// folding rounds.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ProofConfig {
+    /// Length of the zero-padded witness vector.
+    pub n_padded: usize,
+    /// Folding factor of the inner-product argument.
+    pub k: usize,
+    /// Number of folding rounds.
+    pub d: usize,
+}
+
+impl ProofConfig {
+    /// Creates a configuration for `n_padded` inputs folded `d` times by `k`.
+    pub fn new(n_padded: usize, k: usize, d: usize) -> Self {
+        ProofConfig { n_padded, k, d }
+    }
+
+    /// Creates the configuration for `n_raw` inputs, padded to the
+    /// smallest length the fold schedule divides exactly: the final
+    /// vector length times `k^d`.
+    ///
+    /// The folding argument pads every round on its own, so this padding
+    /// never changes the proof size; it only makes the witness length
+    /// explicit.  Padding once, to `ceil(n_raw / k^d) * k^d`, gives the
+    /// same length: the per-round ceilings `ceil(ceil(n / k) / k)` nest
+    /// to `ceil(n / k^2)`.
+    pub fn from_raw(n_raw: usize, k: usize, d: usize) -> Self {
+        let m = reconstruct_round_lengths(n_raw, k, d)[d];
+        ProofConfig::new(m * k.pow(d as u32), k, d)
+    }
+
+    /// Returns the exact size in bytes of
+    /// [`R1CSProof::to_bytes`](::r1cs::R1CSProof::to_bytes) for a proof
+    /// of this shape.
+    pub fn proof_size(&self) -> usize {
+        let m = reconstruct_round_lengths(self.n_padded, self.k, self.d)[self.d];
+        let cross_terms = self.d * (2 * self.k - 2);
+
+        // 13 points, 8 scalars and the two u64 sub-proof lengths.
+        let r1cs_size = (13 + 8) * 32 + 16;
+        // 3 headers, the U points of each round and the final a and b.
+        let ipp_size = (3 + cross_terms + 2 * m) * 32;
+        // 3 headers, the A point pairs of each round and the final z.
+        let ecp_size = (3 + 2 * cross_terms + m) * 32;
+
+        r1cs_size + ipp_size + ecp_size
+    }
+
+    /// Returns the number of `(scalar, point)` terms in the multiscalar
+    /// multiplication that verifying a proof of this shape over
+    /// `k_original` ciphertexts performs, as counted by
+    /// [`R1CSProof::verification_msm_size`](::r1cs::R1CSProof::verification_msm_size).
+    ///
+    /// The final multiscalar multiplication dominates verification, so
+    /// this is the verifier-time estimate [`config_for_verifier_time`]
+    /// minimizes.
+    pub fn verification_msm_size(&self, k_original: usize) -> usize {
+        let cross_terms = self.d.saturating_mul(2 * self.k.saturating_sub(1));
+
+        // A_I, A_O, S, V, S_prime, B, B_blinding, V's blinding generator,
+        // the re-encryption key; T_1_prime, T_1 to T_6 (T_2 twice),
+        // S1_prime, S2_prime, C[0] and C[1].
+        let fixed_terms = 9 + 12;
+        self.n_padded
+            .saturating_mul(2)
+            .saturating_add(cross_terms.saturating_mul(3))
+            .saturating_add(k_original.saturating_mul(2))
+            .saturating_add(fixed_terms)
+    }
+
+    /// Returns `true` if `proof` has the shape this configuration implies:
+    /// folding factor `k`, `d` rounds in both sub-proofs, and final vectors
+    /// of the length `n_padded` folds down to.
+    ///
+    /// This only inspects sizes, so it is a cheap way to reject an
+    /// out-of-spec proof before running the full verification.
+    pub fn matches(&self, proof: &R1CSProof) -> bool {
+        let ipp = &proof.ipp_proof;
+        let ecp = &proof.ecp_batched;
+        if self.k < 2 || ipp.k != self.k || ecp.k != self.k {
+            return false;
+        }
+        if ipp.U_vecs.len() != self.d || ecp.A_vecs.len() != self.d {
+            return false;
+        }
+
+        let m = reconstruct_round_lengths(self.n_padded, self.k, self.d)[self.d];
+        ipp.a_final.len() == m && ipp.b_final.len() == m && ecp.z.len() == m
+    }
+
+    /// Proves a random shuffle of `n_raw` ciphertexts with this
+    /// configuration and returns how long proving took.
+    ///
+    /// Only the call to [`ShuffleProof::prove`](::r1cs::ShuffleProof::prove)
+    /// is timed, not building the statement or the generators, so the
+    /// durations of several configurations compare directly and an
+    /// application can keep whichever is fastest on its hardware.
+    ///
+    /// # Panics
+    ///
+    /// If `n_raw` is below 2 or above `n_padded`, or the configuration
+    /// cannot prove (for instance `k < 2`).
+    #[cfg(feature = "bench")]
+    #[allow(non_snake_case)]
+    pub fn benchmark_prove(&self, n_raw: usize) -> Duration {
+        use curve25519_dalek::ristretto::RistrettoPoint;
+        use curve25519_dalek::scalar::Scalar;
+        use generators::{BulletproofGens, PedersenGens};
+        use merlin::Transcript;
+        use rand::thread_rng;
+
+        use super::{ShuffleProof, ShuffleStatement, ShuffleWitness};
+
+        assert!(n_raw > 1 && n_raw <= self.n_padded);
+        let mut rng = thread_rng();
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(self.n_padded, 1);
+
+        // Reverse the inputs, re-randomizing every ciphertext.
+        let input: Vec<Scalar> = (0..n_raw).map(|_| Scalar::random(&mut rng)).collect();
+        let output: Vec<Scalar> = input.iter().rev().cloned().collect();
+        let C1: Vec<RistrettoPoint> = (0..n_raw).map(|_| RistrettoPoint::random(&mut rng)).collect();
+        let C2: Vec<RistrettoPoint> = (0..n_raw).map(|_| RistrettoPoint::random(&mut rng)).collect();
+        let mut C1_prime = Vec::with_capacity(n_raw);
+        let mut C2_prime = Vec::with_capacity(n_raw);
+        let mut r_prime = Scalar::zero();
+        for (i, x) in output.iter().enumerate() {
+            let r_i = Scalar::random(&mut rng);
+            C1_prime.push(C1[n_raw - 1 - i] + pc_gens.B * r_i);
+            C2_prime.push(C2[n_raw - 1 - i] + pc_gens.B_blinding * r_i);
+            r_prime -= r_i * x;
+        }
+        let (input, output) =
+            ShuffleProof::pad_ciphertexts(&C1_prime, &C2_prime, &input, &output, self.n_padded)
+                .expect("sample shuffle is well formed");
+        let statement = ShuffleStatement { input, C1, C2, C1_prime, C2_prime };
+        let witness = ShuffleWitness::new(statement, output, r_prime);
+
+        let mut transcript = Transcript::new(b"ProofConfig benchmark");
+        let start = Instant::now();
+        ShuffleProof::prove(&pc_gens, &bp_gens, &mut transcript, &[], &witness, self)
+            .expect("benchmark configuration should prove");
+        start.elapsed()
+    }
+}
+
+/// Every size parameter of a shuffle over `n_raw` ciphertexts, derived
+/// once from `(n_raw, k, d)` and checked for consistency.
+///
+/// [`ShuffleProof::prove_with_params`](::r1cs::ShuffleProof::prove_with_params)
+/// and [`ShuffleProof::verify_with_params`](::r1cs::ShuffleProof::verify_with_params)
+/// take the raw witness and do the padding themselves, so a prover and
+/// verifier built from the same `ShuffleParams` cannot disagree on
+/// `n_padded`, `k` or `d`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ShuffleParams {
+    n_raw: usize,
+    config: ProofConfig,
+}
+
+impl ShuffleParams {
+    /// Derives the parameters for shuffling `n_raw` ciphertexts with
+    /// folding factor `k` over `d` rounds.
+    ///
+    /// A single ciphertext is the trivial identity shuffle, but the proof
+    /// needs a witness of at least 2 entries; `n_raw == 1` is padded like
+    /// any other size, to `k` entries with `d == 1`.
+    ///
+    /// Returns [`R1CSError::InputLengthError`] if `n_raw == 0`, and
+    /// [`R1CSError::InvalidFoldParameters`] if `k < 2`, `d == 0`, or `d`
+    /// exceeds the rounds needed to fold `max(n_raw, 2)` down to one
+    /// element: such rounds only fold a single padded element and add
+    /// `2k - 2` points to the proof for nothing.
+    pub fn new(n_raw: usize, k: usize, d: usize) -> Result<ShuffleParams, R1CSError> {
+        if n_raw == 0 {
+            return Err(R1CSError::InputLengthError);
+        }
+        if k < 2 || d == 0 || d > rounds_for_target(n_raw.max(2), k, 1) {
+            return Err(R1CSError::InvalidFoldParameters);
+        }
+        Ok(ShuffleParams {
+            n_raw,
+            config: ProofConfig::from_raw(n_raw, k, d),
+        })
+    }
+
+    /// Returns the number of ciphertexts being shuffled.
+    pub fn n_raw(&self) -> usize {
+        self.n_raw
+    }
+
+    /// Returns the length the witness is zero-padded to.
+    pub fn n_padded(&self) -> usize {
+        self.config.n_padded
+    }
+
+    /// Returns the folding factor.
+    pub fn k(&self) -> usize {
+        self.config.k
+    }
+
+    /// Returns the number of folding rounds.
+    pub fn d(&self) -> usize {
+        self.config.d
+    }
+
+    /// Returns the length of the final folded vectors.
+    pub fn m(&self) -> usize {
+        reconstruct_round_lengths(self.config.n_padded, self.config.k, self.config.d)[self.config.d]
+    }
+
+    /// Returns the exact serialized size of the proof.
+    pub fn proof_size(&self) -> usize {
+        self.config.proof_size()
+    }
+
+    /// Returns the smallest `gens_capacity` a
+    /// [`BulletproofGens`](::BulletproofGens) needs for this shuffle.
+    pub fn gens_capacity(&self) -> usize {
+        self.config.n_padded
+    }
+
+    /// Returns the underlying [`ProofConfig`].
+    pub fn config(&self) -> ProofConfig {
+        self.config
+    }
+}
+
+/// Returns the exact serialized size of a shuffle proof over `n_raw`
+/// ciphertexts, folded `d` times by `k`.
+///
+/// This pads `n_raw` with [`ProofConfig::from_raw`] before sizing, so
+/// callers don't have to compute `n_padded` themselves.
+pub fn shuffle_proof_size(n_raw: usize, k: usize, d: usize) -> usize {
+    ProofConfig::from_raw(n_raw, k, d).proof_size()
+}
+
+/// Returns the smallest `gens_capacity` a
+/// [`BulletproofGens`](::BulletproofGens) needs to shuffle `n_original`
+/// ciphertexts, folded `d` times by `k`.
+///
+/// This is the exact `n_padded` of [`ProofConfig::from_raw`], so
+/// `BulletproofGens::new(gens_capacity_for_shuffle(n, k, d), 1)` fits the
+/// witness the prover pads to, and one generator fewer does not.
+pub fn gens_capacity_for_shuffle(n_original: usize, k: usize, d: usize) -> usize {
+    ProofConfig::from_raw(n_original, k, d).n_padded
+}
+
+/// Returns the fewest folding rounds `d` after which `n` elements, folded
+/// by `k` with per-round padding, are down to at most `target_m`.
+///
+/// Unlike a `ceil(log_k(n))` estimate this follows the exact fold
+/// schedule, so `reconstruct_round_lengths(n, k, d)` ends at or below
+/// `target_m`, and at `d - 1` rounds it would not.
+///
+/// # Panics
+///
+/// Panics if `k < 2` or `target_m == 0`, for which no number of rounds
+/// reaches the target.
+pub fn rounds_for_target(n: usize, k: usize, target_m: usize) -> usize {
+    assert!(k >= 2, "k must be at least 2");
+    assert!(target_m >= 1, "target_m must be at least 1");
+
+    let mut m = n;
+    let mut d = 0;
+    while m > target_m {
+        m = reconstruct_round_lengths(m, k, 1)[1];
+        d += 1;
+    }
+    d
+}
+
+/// Returns the number of folding rounds `d` giving the smallest shuffle
+/// proof over `n` ciphertexts, folded by `k`, that fits in `max_bytes`,
+/// sized with [`shuffle_proof_size`].
+///
+/// Only `d` between 1 and the rounds that fold `n` down to one element
+/// are considered, as in [`ShuffleParams::new`].  Deeper folds trade
+/// final-vector scalars for round points, so the size need not shrink
+/// with every round; among equally small proofs the shallowest, fastest
+/// `d` wins.  Returns `None` if `k < 2`, `n < 2`, or no `d` fits.
+pub fn rounds_for_size_budget(n: usize, k: usize, max_bytes: usize) -> Option<usize> {
+    if k < 2 || n < 2 {
+        return None;
+    }
+    (1..=rounds_for_target(n, k, 1))
+        .map(|d| (shuffle_proof_size(n, k, d), d))
+        .filter(|&(size, _)| size <= max_bytes)
+        .min()
+        .map(|(_, d)| d)
+}
+
+/// Returns the configuration for shuffling `n` ciphertexts whose proof
+/// fits in `max_bytes` and is fastest to verify, with the folding factor
+/// `k` between 2 and `max_k`.
+///
+/// Verifier time is estimated by
+/// [`ProofConfig::verification_msm_size`]: the `2 * n_padded` generator
+/// terms favour schedules that pad little, and each round adds
+/// `3 * (2k - 2)` cross terms.  Every `d` from 1 to the rounds folding
+/// `n` down to one element is considered, as in
+/// [`ShuffleParams::new`]; ties go to the smaller proof, then the
+/// smaller `k` and `d`.  Returns `None` if `n < 2`, `max_k < 2`, or no
+/// configuration fits.
+pub fn config_for_verifier_time(n: usize, max_k: usize, max_bytes: usize) -> Option<ProofConfig> {
+    if n < 2 {
+        return None;
+    }
+    (2..=max_k)
+        .flat_map(|k| (1..=rounds_for_target(n, k, 1)).map(move |d| ProofConfig::from_raw(n, k, d)))
+        .filter(|config| config.proof_size() <= max_bytes)
+        .min_by_key(|config| (config.verification_msm_size(n), config.proof_size(), config.k, config.d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shuffle_params_derive_sizes() {
+        let params = ShuffleParams::new(7, 3, 2).unwrap();
+        assert_eq!(params.n_raw(), 7);
+        assert_eq!(params.n_padded(), 9);
+        assert_eq!(params.m(), 1);
+        assert_eq!(params.gens_capacity(), 9);
+        assert_eq!(params.proof_size(), shuffle_proof_size(7, 3, 2));
+
+        let params = ShuffleParams::new(100, 4, 2).unwrap();
+        assert_eq!((params.n_padded(), params.m()), (112, 7));
+    }
+
+    #[test]
+    fn shuffle_params_reject_inconsistent_folds() {
+        assert_eq!(ShuffleParams::new(0, 2, 1), Err(R1CSError::InputLengthError));
+        // A single ciphertext folds like two: one round, padded to k.
+        assert_eq!(ShuffleParams::new(1, 3, 1).unwrap().n_padded(), 3);
+        assert_eq!(ShuffleParams::new(1, 2, 2), Err(R1CSError::InvalidFoldParameters));
+        assert_eq!(ShuffleParams::new(8, 1, 3), Err(R1CSError::InvalidFoldParameters));
+        assert_eq!(ShuffleParams::new(8, 2, 0), Err(R1CSError::InvalidFoldParameters));
+        // 8 folds to 1 in 3 rounds by 2, and in 1 round by 8.
+        assert!(ShuffleParams::new(8, 2, 3).is_ok());
+        assert_eq!(ShuffleParams::new(8, 2, 4), Err(R1CSError::InvalidFoldParameters));
+        assert!(ShuffleParams::new(8, 8, 1).is_ok());
+        assert_eq!(ShuffleParams::new(8, 8, 2), Err(R1CSError::InvalidFoldParameters));
+    }
+
+    #[cfg(feature = "bench")]
+    #[test]
+    fn benchmark_prove_times_a_proof() {
+        let config = ProofConfig::from_raw(7, 3, 2);
+        assert!(config.benchmark_prove(7) > ::std::time::Duration::from_secs(0));
+    }
+
+    #[test]
+    fn rounds_for_size_budget_picks_smallest_fitting_proof() {
+        let sizes: Vec<usize> = (1..=10).map(|d| shuffle_proof_size(1000, 2, d)).collect();
+        let smallest = *sizes.iter().min().unwrap();
+        let d = rounds_for_size_budget(1000, 2, smallest).unwrap();
+        assert_eq!(shuffle_proof_size(1000, 2, d), smallest);
+        assert_eq!(rounds_for_size_budget(1000, 2, usize::max_value()), Some(d));
+
+        // Even the smallest proof is over budget.
+        assert_eq!(rounds_for_size_budget(1000, 2, smallest - 1), None);
+        assert_eq!(rounds_for_size_budget(1000, 1, usize::max_value()), None);
+    }
+
+    #[test]
+    fn from_raw_padding_matches_one_shot_padding() {
+        for n_raw in 1..200 {
+            for k in 2..7usize {
+                for d in 0..5 {
+                    let k_d = k.pow(d as u32);
+                    let one_shot = (n_raw + k_d - 1) / k_d * k_d;
+                    let config = ProofConfig::from_raw(n_raw, k, d);
+                    assert_eq!(config.n_padded, one_shot, "n_raw={} k={} d={}", n_raw, k, d);
+                    assert_eq!(
+                        reconstruct_round_lengths(config.n_padded, k, d)[d],
+                        reconstruct_round_lengths(n_raw, k, d)[d],
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rounds_for_target_follows_fold_schedule() {
+        for &(n, k, target_m, d) in &[
+            (1024, 2, 1, 10),
+            (1000, 2, 1, 10),
+            (1000, 10, 1, 3),
+            (1001, 10, 1, 4),
+            (27, 3, 3, 2),
+            (28, 3, 3, 3),
+            (100, 4, 7, 2),
+            (5, 2, 8, 0),
+            (0, 2, 1, 0),
+        ] {
+            assert_eq!(rounds_for_target(n, k, target_m), d, "n={} k={} target_m={}", n, k, target_m);
+            assert!(reconstruct_round_lengths(n, k, d)[d] <= target_m);
+            if d > 0 {
+                assert!(reconstruct_round_lengths(n, k, d - 1)[d - 1] > target_m);
+            }
+        }
+    }
+
+    #[test]
+    fn config_for_verifier_time_minimizes_msm_size() {
+        let n = 1000;
+        let feasible = |max_k: usize, max_bytes: usize| -> Vec<ProofConfig> {
+            (2..=max_k)
+                .flat_map(|k| (1..=rounds_for_target(n, k, 1)).map(move |d| ProofConfig::from_raw(n, k, d)))
+                .filter(|config| config.proof_size() <= max_bytes)
+                .collect()
+        };
+
+        for &max_bytes in &[4_000, 6_000, 10_000, usize::max_value()] {
+            let chosen = config_for_verifier_time(n, 16, max_bytes).unwrap();
+            assert!(chosen.proof_size() <= max_bytes);
+            let fastest = feasible(16, max_bytes)
+                .iter()
+                .map(|config| config.verification_msm_size(n))
+                .min()
+                .unwrap();
+            assert_eq!(chosen.verification_msm_size(n), fastest, "max_bytes={}", max_bytes);
+        }
+
+        // A tight budget forces a deep fold, which costs verifier time.
+        let tight = config_for_verifier_time(n, 16, 4_000).unwrap();
+        let loose = config_for_verifier_time(n, 16, usize::max_value()).unwrap();
+        assert!(tight.verification_msm_size(n) >= loose.verification_msm_size(n));
+
+        assert_eq!(config_for_verifier_time(n, 16, 0), None);
+        assert_eq!(config_for_verifier_time(n, 1, usize::max_value()), None);
+        assert_eq!(config_for_verifier_time(1, 16, usize::max_value()), None);
+    }
+}
@@ -53,6 +53,12 @@ pub trait ConstraintSystem {
     /// ```
     fn constrain(&mut self, lc: LinearCombination);
 
+    /// Returns the public constant `value` as a linear combination,
+    /// `value * Variable::One()`, for use in further constraints.
+    fn constant(&self, value: Scalar) -> LinearCombination {
+        value * Variable::One()
+    }
+
     /// Obtain a challenge scalar bound to the assignments of all of
     /// the externally committed wires.
     ///
@@ -0,0 +1,121 @@
+//! Interactive shuffle proofs, with the verifier supplying every challenge
+//! instead of deriving it from a Fiat-Shamir transcript.
+
+#![allow(non_snake_case)]
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use rand::rngs::ThreadRng;
+use rand::thread_rng;
+
+use super::{ProofConfig, R1CSProof, ShuffleClaim, ShuffleProof, ShuffleWitness};
+use errors::R1CSError;
+use generators::{BulletproofGens, PedersenGens};
+use transcript::ProofTranscript;
+
+/// Where an interactive prover or verifier gets its challenges from.
+///
+/// On the verifier side this is its own randomness; on the prover side it
+/// is the channel the verifier's challenges arrive on.  Each call must
+/// fill `dest` with the next challenge, and the `label` says which one the
+/// protocol is asking for.
+pub trait ChallengeSource {
+    /// Fills `dest` with the challenge for `label`.
+    fn challenge(&mut self, label: &'static [u8], dest: &mut [u8]);
+}
+
+impl<F: FnMut(&'static [u8], &mut [u8])> ChallengeSource for F {
+    fn challenge(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        self(label, dest)
+    }
+}
+
+/// A transcript that drops the prover's messages and reads every
+/// challenge from a [`ChallengeSource`].
+struct ChallengeTranscript<S> {
+    source: S,
+}
+
+impl<S: ChallengeSource> ProofTranscript for ChallengeTranscript<S> {
+    type Rng = ThreadRng;
+
+    fn append_message(&mut self, _label: &'static [u8], _message: &[u8]) {}
+
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        self.source.challenge(label, dest);
+    }
+
+    fn witness_rng(&self, _witness: &[(&'static [u8], &[u8])]) -> ThreadRng {
+        thread_rng()
+    }
+}
+
+/// The prover of an interactive shuffle proof.
+///
+/// It runs the same protocol as [`ShuffleProof::prove`], but asks its
+/// [`ChallengeSource`] for each challenge at the point the protocol needs
+/// it.
+///
+/// This changes the soundness model.  A Fiat-Shamir proof is sound against
+/// any verifier, and anyone can check it after the fact; an interactive
+/// proof is only sound if the challenges are uniformly random and unknown
+/// to the prover until it has sent every message before them, that is,
+/// against an *honest verifier*.  The run convinces no one but the
+/// verifier who chose the challenges, since anyone knowing them in advance
+/// could have simulated it.
+pub struct InteractiveProver<S> {
+    transcript: ChallengeTranscript<S>,
+}
+
+impl<S: ChallengeSource> InteractiveProver<S> {
+    /// Creates a prover reading its challenges from `source`.
+    pub fn new(source: S) -> Self {
+        InteractiveProver {
+            transcript: ChallengeTranscript { source },
+        }
+    }
+
+    /// Proves that the witness's `output` is a permutation of its
+    /// statement's `input` consistent with the re-encrypted ciphertexts,
+    /// as [`ShuffleProof::prove`] does.
+    pub fn prove(
+        &mut self,
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        ShuffleProof::prove(pc_gens, bp_gens, &mut self.transcript, &[], witness, config)
+    }
+}
+
+/// The verifier of an interactive shuffle proof.
+///
+/// Its [`ChallengeSource`] must produce, in order, the same challenges the
+/// prover was sent, drawn uniformly at random; see [`InteractiveProver`]
+/// for why the proof is only sound against such an honest verifier.
+pub struct InteractiveVerifier<S> {
+    transcript: ChallengeTranscript<S>,
+}
+
+impl<S: ChallengeSource> InteractiveVerifier<S> {
+    /// Creates a verifier reading its challenges from `source`.
+    pub fn new(source: S) -> Self {
+        InteractiveVerifier {
+            transcript: ChallengeTranscript { source },
+        }
+    }
+
+    /// Checks a proof produced by [`InteractiveProver::prove`], as
+    /// [`ShuffleProof::verify`] does.
+    ///
+    /// The claim's `nonce` has no effect: like every other prover message,
+    /// it is dropped rather than bound into a transcript.
+    pub fn verify(
+        &mut self,
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        ShuffleProof::verify(pc_gens, bp_gens, &mut self.transcript, claim)
+    }
+}
@@ -0,0 +1,112 @@
+//! A Merkle tree over the original ciphertexts of a shuffle, so a
+//! verifier can hold only its root.
+
+#![allow(non_snake_case)]
+
+use curve25519_dalek::ristretto::RistrettoPoint;
+use digest::Digest;
+use sha3::Sha3_256;
+
+/// Hashes the ciphertext `(C1, C2)` into a leaf.
+fn leaf_hash(C1: &RistrettoPoint, C2: &RistrettoPoint) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.input(b"ShuffleCiphertext leaf");
+    hasher.input(C1.compress().as_bytes());
+    hasher.input(C2.compress().as_bytes());
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(hasher.result().as_slice());
+    hash
+}
+
+/// Hashes two sibling nodes into their parent.
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha3_256::new();
+    hasher.input(b"ShuffleCiphertext node");
+    hasher.input(left);
+    hasher.input(right);
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(hasher.result().as_slice());
+    hash
+}
+
+/// A Merkle tree over the ciphertexts `(C1[i], C2[i])`, in order.
+///
+/// The leaves are padded with all-zero hashes to a power of two, so every
+/// inclusion proof has the same length.
+#[derive(Clone, Debug)]
+pub struct CiphertextTree {
+    /// `levels[0]` holds the leaves and the last level holds the root.
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl CiphertextTree {
+    /// Builds the tree over the ciphertexts `(C1[i], C2[i])`.
+    ///
+    /// Panics if `C1` and `C2` differ in length or are empty.
+    pub fn new(C1: &[RistrettoPoint], C2: &[RistrettoPoint]) -> CiphertextTree {
+        assert_eq!(C1.len(), C2.len());
+        assert!(!C1.is_empty());
+
+        let mut leaves: Vec<[u8; 32]> = C1.iter().zip(C2.iter()).map(|(a, b)| leaf_hash(a, b)).collect();
+        leaves.resize(C1.len().next_power_of_two(), [0u8; 32]);
+
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], &pair[1]))
+                .collect();
+            levels.push(next);
+        }
+        CiphertextTree { levels }
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> [u8; 32] {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// Returns the inclusion proof for the ciphertext at `index`.
+    pub fn proof(&self, index: usize) -> MerkleProof {
+        let mut position = index;
+        let mut siblings = Vec::with_capacity(self.levels.len() - 1);
+        for level in self.levels[..self.levels.len() - 1].iter() {
+            siblings.push(level[position ^ 1]);
+            position >>= 1;
+        }
+        MerkleProof { index, siblings }
+    }
+}
+
+/// An inclusion proof for one ciphertext in a [`CiphertextTree`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MerkleProof {
+    /// The position of the ciphertext among the leaves.
+    pub index: usize,
+    /// The sibling hashes from the leaf up to the root.
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// Checks that `(C1, C2)` is the leaf at `self.index` of the tree with
+    /// the given `root`.
+    pub fn verify(&self, root: &[u8; 32], C1: &RistrettoPoint, C2: &RistrettoPoint) -> bool {
+        if self.siblings.len() >= 64 || self.index >> self.siblings.len() != 0 {
+            return false;
+        }
+
+        let mut position = self.index;
+        let mut hash = leaf_hash(C1, C2);
+        for sibling in self.siblings.iter() {
+            hash = if position & 1 == 0 {
+                node_hash(&hash, sibling)
+            } else {
+                node_hash(sibling, &hash)
+            };
+            position >>= 1;
+        }
+        &hash == root
+    }
+}
@@ -3,16 +3,33 @@
 #[doc= include_str!("../../docs/cs-proof.md")]
 mod notes {}
 
+mod config;
 mod constraint_system;
+mod interactive;
 mod linear_combination;
+mod merkle;
 mod proof;
 mod prover;
+mod shuffle;
 mod verifier;
 
+pub use self::config::{
+    config_for_verifier_time, gens_capacity_for_shuffle, rounds_for_size_budget,
+    rounds_for_target, shuffle_proof_size, ProofConfig, ShuffleParams,
+};
 pub use self::constraint_system::ConstraintSystem;
+pub use self::interactive::{ChallengeSource, InteractiveProver, InteractiveVerifier};
 pub use self::linear_combination::{LinearCombination, Variable};
-pub use self::proof::R1CSProof;
-pub use self::prover::Prover;
-pub use self::verifier::Verifier;
+pub use self::merkle::{CiphertextTree, MerkleProof};
+pub use self::proof::{ProofMetadata, ProofSections, ProofType, R1CSProof};
+pub use self::prover::{ConsistencyBlindings, Prover};
+pub use self::shuffle::{
+    AggregatedShuffleProof, BatchVerifierAccumulator, ComponentStatement, FixedDepthShuffle,
+    PermutationWitness, SamePermutationProof, ShuffleChain, ShuffleClaim, ShuffleProof,
+    ShuffleStatement, ShuffleWitness, StreamedStatement,
+};
+pub use self::verifier::{VerificationReport, VerificationStage, Verifier};
+#[cfg(any(test, feature = "debug"))]
+pub use self::verifier::VerificationScalars;
 
 pub use errors::R1CSError;
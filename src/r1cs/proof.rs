@@ -1,16 +1,22 @@
 //! Definition of the proof struct.
 
-use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
 
 use inner_product_proof::K_BulletProof;
 use inner_product_proof::batched_eCP;
 
+use digest::Digest;
 use serde::de::Visitor;
+use sha3::Sha3_256;
 use serde::{self, Deserialize, Deserializer, Serialize, Serializer};
-use errors::ProofError;
+use errors::{ProofError, R1CSError};
+use generators::{BulletproofGens, PedersenGens};
 use std::convert::TryInto;
 
+use super::{ProofConfig, ShuffleProof, ShuffleStatement};
+
 /// A proof of some statement specified by a
 /// [`ConstraintSystem`](::r1cs::ConstraintSystem).
 #[derive(Clone, Debug)]
@@ -63,7 +69,72 @@ pub struct R1CSProof {
     pub(super) ecp_batched: batched_eCP,
 }
 
+/// A serialized [`R1CSProof`] split by kind of data, so a transport can
+/// encode the point arrays separately from the rest.
+///
+/// Produced by [`R1CSProof::to_bytes_sections`] and read back by
+/// [`R1CSProof::from_bytes_sections`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProofSections {
+    /// The `(k, d, m)` headers of the inner-product proof, then of the
+    /// consistency proof: six little-endian `u64`s.
+    pub header: Vec<u8>,
+    /// Every compressed point: the 13 fixed points, the inner-product
+    /// proof's `U` points, then the consistency proof's `A` pairs.
+    pub points: Vec<u8>,
+    /// Every scalar: the 8 fixed scalars, the inner-product proof's final
+    /// `a` and `b` vectors, then the consistency proof's final `z` vector.
+    pub scalars: Vec<u8>,
+}
+
+/// The kind of proof a [`ProofMetadata`] describes.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProofType {
+    /// A re-encryption shuffle [`R1CSProof`], as produced by
+    /// [`ShuffleProof::prove`](::r1cs::ShuffleProof::prove).
+    Shuffle,
+}
+
+/// A summary of an [`R1CSProof`] for indexing and storage, returned by
+/// [`R1CSProof::metadata`]; it carries none of the proof's points or
+/// scalars.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ProofMetadata {
+    /// The folding factor.
+    pub k: usize,
+    /// The number of folding rounds.
+    pub d: usize,
+    /// The length of the final folded vectors.
+    pub m: usize,
+    /// The size in bytes of [`R1CSProof::to_bytes`].
+    pub size: usize,
+    /// The proof's [`R1CSProof::transcript_id`].
+    pub transcript_id: [u8; 32],
+    /// The kind of proof.
+    pub proof_type: ProofType,
+}
+
 impl R1CSProof {
+    /// Returns the blinding of the output commitment in the consistency
+    /// check, `v_blinding + ec_mask * x_prime` (see
+    /// [`ConsistencyBlindings`](::r1cs::ConsistencyBlindings)).
+    pub fn ec_blinding(&self) -> Scalar {
+        self.ec_blinding
+    }
+
+    /// Returns the blinding of the re-randomization in the consistency
+    /// check, `r_prime + r_mask * x_prime` (see
+    /// [`ConsistencyBlindings`](::r1cs::ConsistencyBlindings)).
+    pub fn r_blinding(&self) -> Scalar {
+        self.r_blinding
+    }
+
+    /// Returns the size in bytes of the proof serialized by
+    /// [`R1CSProof::to_bytes`], without serializing it.
+    pub fn serialized_size(&self) -> usize {
+        (13 + 8) * 32 + 16 + self.ipp_proof.serialized_size() + self.ecp_batched.serialized_size()
+    }
+
     /// Serializes the proof into a byte array.
     ///
     /// Format:
@@ -72,7 +143,7 @@ impl R1CSProof {
     /// 3. 2 u64 lengths (16 bytes)
     /// 4. Variable-sized proof data
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
+        let mut buf = Vec::with_capacity(self.serialized_size());
         
         // Write 13 compressed points
         buf.extend_from_slice(self.A_I.as_bytes());
@@ -114,6 +185,103 @@ impl R1CSProof {
         buf
     }
 
+    /// Returns the witness length implied by the proof's fold schedule,
+    /// `m * k^d`, where `m` is the final vector length.
+    ///
+    /// This is exact for witnesses padded with
+    /// [`ProofConfig::from_raw`](::r1cs::ProofConfig::from_raw), and an
+    /// upper bound on the witness length otherwise.  It saturates at
+    /// `usize::MAX` for schedules too large to represent.
+    pub fn implied_circuit_size(&self) -> usize {
+        let k = self.ipp_proof.k;
+        let d = self.ipp_proof.U_vecs.len();
+        let m = self.ipp_proof.a_final.len();
+        k.checked_pow(d as u32)
+            .map_or(usize::max_value(), |k_d| k_d.saturating_mul(m))
+    }
+
+    /// Returns the number of zero entries padding `k_original` inputs up
+    /// to the proof's witness length, as given by
+    /// [`R1CSProof::implied_circuit_size`].
+    pub fn padding_amount(&self, k_original: usize) -> usize {
+        self.implied_circuit_size().saturating_sub(k_original)
+    }
+
+    /// Returns the number of `(scalar, point)` terms in the multiscalar
+    /// multiplication that verifying this proof over `k_original`
+    /// ciphertexts performs, without any group operations.
+    ///
+    /// The count follows from the proof's folding factor `k`, its `d`
+    /// rounds and final length `m`: the `G` and `H` generators of the
+    /// `m * k^d` padded inputs, `2k - 2` `U` points and `2k - 2` pairs of
+    /// `A` points per round, the `C1_prime` and `C2_prime` ciphertexts,
+    /// and 21 fixed commitment and statement points.  A verifier can
+    /// compare it against a work budget before accepting the proof; like
+    /// [`R1CSProof::padding_amount`], it assumes a witness padded with
+    /// [`ProofConfig::from_raw`](::r1cs::ProofConfig::from_raw).
+    pub fn verification_msm_size(&self, k_original: usize) -> usize {
+        let config = ProofConfig::new(
+            self.implied_circuit_size(),
+            self.ipp_proof.k,
+            self.ipp_proof.U_vecs.len(),
+        );
+        config.verification_msm_size(k_original)
+    }
+
+    /// Returns the `(scalar, point)` terms that verifying this proof of
+    /// `statement` under `nonce` with [`ShuffleProof::verify`] sums in its final
+    /// multiscalar multiplication; they sum to the identity exactly when
+    /// the proof is valid.
+    ///
+    /// A protocol verifying many proofs can scale each proof's terms by an
+    /// independent random weight and check them all with a single
+    /// multiscalar multiplication.
+    pub fn msm_terms(
+        &self,
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut Transcript,
+        nonce: &[u8],
+        statement: &ShuffleStatement,
+        output_commitment: CompressedRistretto,
+    ) -> Result<Vec<(Scalar, RistrettoPoint)>, R1CSError> {
+        let cs = ShuffleProof::verifier_cs(
+            pc_gens, bp_gens, transcript, nonce, output_commitment, statement, None,
+        )?;
+        cs.msm_terms(self, &statement.C1_prime, &statement.C2_prime, &statement.aggregate())
+    }
+
+    /// Returns a stable 32-byte identifier for this proof.
+    ///
+    /// The id is a domain-separated SHA3-256 hash of [`to_bytes`](R1CSProof::to_bytes),
+    /// suitable for logging or for deduplicating replayed submissions.
+    /// It is only an identifier: it does not bind the proof to a statement,
+    /// and two proofs of the same statement will have different ids.
+    pub fn transcript_id(&self) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.input(b"R1CSProof transcript_id");
+        hasher.input(&self.to_bytes());
+
+        let mut id = [0u8; 32];
+        id.copy_from_slice(hasher.result().as_slice());
+        id
+    }
+
+    /// Returns the proof's [`ProofMetadata`].
+    ///
+    /// Everything but the transcript id is read off the proof's structure;
+    /// the id hashes the serialized proof once.
+    pub fn metadata(&self) -> ProofMetadata {
+        ProofMetadata {
+            k: self.ipp_proof.k,
+            d: self.ipp_proof.U_vecs.len(),
+            m: self.ipp_proof.a_final.len(),
+            size: self.serialized_size(),
+            transcript_id: self.transcript_id(),
+            proof_type: ProofType::Shuffle,
+        }
+    }
+
     /// Deserializes the proof from a byte slice.
     pub fn from_bytes(slice: &[u8]) -> Result<R1CSProof, ProofError> {
         let point_count = 13;
@@ -208,6 +376,218 @@ impl R1CSProof {
             ecp_batched,
         })
     }
+
+    /// Serializes the proof like [`to_bytes`](R1CSProof::to_bytes), with
+    /// the bytes regrouped into headers, points and scalars.
+    ///
+    /// The `U_vecs` and `A_vecs` point arrays make up most of a large
+    /// proof; keeping them in one section lets a transport apply a
+    /// point-specific encoding to them alone.
+    pub fn to_bytes_sections(&self) -> ProofSections {
+        let bytes = self.to_bytes();
+        let fixed_points = 13 * 32;
+        let fixed_scalars = 8 * 32;
+
+        let mut sections = ProofSections {
+            header: Vec::with_capacity(6 * 8),
+            points: bytes[..fixed_points].to_vec(),
+            scalars: bytes[fixed_points..fixed_points + fixed_scalars].to_vec(),
+        };
+
+        let ipp_start = fixed_points + fixed_scalars + 16;
+        let ecp_start = ipp_start + self.ipp_proof.serialized_size();
+        let ipp_points: usize = self.ipp_proof.U_vecs.iter().map(|U_round| U_round.len()).sum();
+        let ecp_points: usize = self.ecp_batched.A_vecs.iter().map(|A_round| 2 * A_round.len()).sum();
+        for &(start, end, n_points) in &[
+            (ipp_start, ecp_start, ipp_points),
+            (ecp_start, bytes.len(), ecp_points),
+        ] {
+            for slot in 0..3 {
+                let pos = start + slot * 32;
+                sections.header.extend_from_slice(&bytes[pos..pos + 8]);
+            }
+            let body = start + 3 * 32;
+            sections.points.extend_from_slice(&bytes[body..body + n_points * 32]);
+            sections.scalars.extend_from_slice(&bytes[body + n_points * 32..end]);
+        }
+        sections
+    }
+
+    /// Reassembles a proof from the sections of
+    /// [`to_bytes_sections`](R1CSProof::to_bytes_sections), with the same
+    /// checks as [`from_bytes`](R1CSProof::from_bytes).
+    ///
+    /// Returns [`ProofError::FormatError`] if a section's length doesn't
+    /// match the headers.
+    pub fn from_bytes_sections(sections: &ProofSections) -> Result<R1CSProof, ProofError> {
+        if sections.header.len() != 6 * 8 {
+            return Err(ProofError::FormatError);
+        }
+        let header = |i: usize| u64::from_le_bytes(sections.header[i * 8..i * 8 + 8].try_into().unwrap()) as usize;
+        // (k, d, m, points per cross term, final vectors) of each sub-proof.
+        let sub_proofs = [
+            (header(0), header(1), header(2), 1, 2),
+            (header(3), header(4), header(5), 2, 1),
+        ];
+
+        let mut point_count = 13;
+        let mut scalar_count = 8;
+        let mut counts = Vec::with_capacity(2);
+        for &(k, d, m, points, vectors) in &sub_proofs {
+            let n_points = k
+                .checked_mul(2)
+                .and_then(|x| x.checked_sub(2))
+                .and_then(|x| x.checked_mul(d))
+                .and_then(|x| x.checked_mul(points))
+                .ok_or(ProofError::FormatError)?;
+            let n_scalars = m.checked_mul(vectors).ok_or(ProofError::FormatError)?;
+            point_count = n_points.checked_add(point_count).ok_or(ProofError::FormatError)?;
+            scalar_count = n_scalars.checked_add(scalar_count).ok_or(ProofError::FormatError)?;
+            counts.push((n_points, n_scalars));
+        }
+        if Some(sections.points.len()) != point_count.checked_mul(32)
+            || Some(sections.scalars.len()) != scalar_count.checked_mul(32)
+        {
+            return Err(ProofError::FormatError);
+        }
+
+        let mut sub_proof_bytes = Vec::with_capacity(2);
+        let (mut points, mut scalars) = (13 * 32, 8 * 32);
+        for (i, &(n_points, n_scalars)) in counts.iter().enumerate() {
+            let mut buf = Vec::with_capacity((3 + n_points + n_scalars) * 32);
+            for slot in 0..3 {
+                let mut temp = [0u8; 32];
+                temp[..8].copy_from_slice(&sections.header[(3 * i + slot) * 8..(3 * i + slot) * 8 + 8]);
+                buf.extend_from_slice(&temp);
+            }
+            buf.extend_from_slice(&sections.points[points..points + n_points * 32]);
+            buf.extend_from_slice(&sections.scalars[scalars..scalars + n_scalars * 32]);
+            points += n_points * 32;
+            scalars += n_scalars * 32;
+            sub_proof_bytes.push(buf);
+        }
+
+        let mut bytes = Vec::with_capacity(sections.points.len() + sections.scalars.len() + 16 + 6 * 32);
+        bytes.extend_from_slice(&sections.points[..13 * 32]);
+        bytes.extend_from_slice(&sections.scalars[..8 * 32]);
+        for buf in sub_proof_bytes.iter() {
+            bytes.extend_from_slice(&(buf.len() as u64).to_le_bytes());
+        }
+        for buf in sub_proof_bytes.iter() {
+            bytes.extend_from_slice(buf);
+        }
+        R1CSProof::from_bytes(&bytes)
+    }
+
+    /// Deserializes the proof like [`from_bytes`](R1CSProof::from_bytes),
+    /// but diagnoses proofs whose `u64` headers were written big-endian.
+    ///
+    /// If the canonical parse fails and the same bytes parse once every
+    /// header is read as big-endian, this returns
+    /// [`ProofError::EndiannessMismatch`] instead of the original error,
+    /// which is usually the sign of a serialization bug in another
+    /// implementation.
+    pub fn from_bytes_detect(slice: &[u8]) -> Result<R1CSProof, ProofError> {
+        R1CSProof::from_bytes(slice).map_err(|e| match swap_header_endianness(slice) {
+            Some(ref swapped) if R1CSProof::from_bytes(swapped).is_ok() => {
+                ProofError::EndiannessMismatch
+            }
+            _ => e,
+        })
+    }
+
+    /// Returns `true` if `bytes` is exactly what [`to_bytes`](R1CSProof::to_bytes)
+    /// produces for the proof they deserialize to.
+    ///
+    /// A relay that re-encodes a proof must not be able to make it look
+    /// like a new submission.  A verifier deduplicating replayed proofs by
+    /// their bytes (or by [`transcript_id`](R1CSProof::transcript_id))
+    /// should reject any proof for which this returns `false`, so that
+    /// each accepted proof has a single encoding.  The check does not
+    /// verify the proof.
+    pub fn is_canonical_encoding(bytes: &[u8]) -> bool {
+        match R1CSProof::from_bytes(bytes) {
+            Ok(proof) => proof.to_bytes() == bytes,
+            Err(_) => false,
+        }
+    }
+
+    /// Checks that every scalar of the proof, including the final vectors
+    /// of the inner-product and consistency proofs, is canonically reduced,
+    /// and that those final vectors all have the same length.
+    ///
+    /// [`R1CSProof::from_bytes`] already rejects unreduced scalars, but a
+    /// proof can also be assembled in memory, for instance by
+    /// `Scalar::from_bits`.  This is a defense-in-depth check for such
+    /// proofs, and for callers that want to re-check a proof after
+    /// deserializing it; it returns [`ProofError::FormatError`] on the
+    /// first problem, and does not verify the proof.
+    pub fn validate_scalars(&self) -> Result<(), ProofError> {
+        let ipp = &self.ipp_proof;
+        let m = ipp.a_final.len();
+        if ipp.b_final.len() != m || self.ecp_batched.z.len() != m {
+            return Err(ProofError::FormatError);
+        }
+
+        let fields = [
+            &self.t_x,
+            &self.t_x_blinding,
+            &self.e_blinding,
+            &self.tc_x,
+            &self.tc_x_blinding,
+            &self.ec_blinding,
+            &self.t_cross,
+            &self.r_blinding,
+        ];
+        let scalars = fields
+            .iter()
+            .cloned()
+            .chain(ipp.a_final.iter())
+            .chain(ipp.b_final.iter())
+            .chain(self.ecp_batched.z.iter());
+        for scalar in scalars {
+            if Scalar::from_canonical_bytes(scalar.to_bytes()).is_none() {
+                return Err(ProofError::FormatError);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a copy of `slice` with every `u64` header byte-swapped: the two
+/// sub-proof lengths, and the `k`, `d`, `m` headers of each sub-proof.
+///
+/// The sub-proof lengths are read as big-endian to locate the sub-proofs;
+/// returns `None` if they don't add up to the slice length.
+fn swap_header_endianness(slice: &[u8]) -> Option<Vec<u8>> {
+    let mut offset = (13 + 8) * 32;
+    if slice.len() < offset + 16 {
+        return None;
+    }
+
+    let mut swapped = slice.to_vec();
+    let mut lengths = [0usize; 2];
+    for len in lengths.iter_mut() {
+        swapped[offset..offset + 8].reverse();
+        let len_bytes: [u8; 8] = slice[offset..offset + 8].try_into().ok()?;
+        *len = u64::from_be_bytes(len_bytes) as usize;
+        offset += 8;
+    }
+
+    let total = offset.checked_add(lengths[0])?.checked_add(lengths[1])?;
+    if total != slice.len() {
+        return None;
+    }
+    for len in lengths.iter() {
+        if *len >= 3 * 32 {
+            for header in 0..3 {
+                let pos = offset + header * 32;
+                swapped[pos..pos + 8].reverse();
+            }
+        }
+        offset += len;
+    }
+    Some(swapped)
 }
 
 impl Serialize for R1CSProof {
@@ -243,4 +623,194 @@ impl<'de> Deserialize<'de> for R1CSProof {
 
         deserializer.deserialize_bytes(R1CSProofVisitor)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+
+    fn dummy_proof() -> R1CSProof {
+        let p = RISTRETTO_BASEPOINT_COMPRESSED;
+        let s = Scalar::from(7u64);
+        R1CSProof {
+            A_I: p, A_O: p, S: p,
+            T_1: p, T_2: p, T_3: p, T_4: p, T_5: p, T_6: p,
+            t_x: s, t_x_blinding: s, e_blinding: s,
+            ipp_proof: K_BulletProof {
+                k: 2,
+                U_vecs: vec![vec![p, p]],
+                a_final: vec![s],
+                b_final: vec![s],
+            },
+            S_prime: p, T_1_prime: p, S1_prime: p, S2_prime: p,
+            tc_x: s, tc_x_blinding: s, ec_blinding: s,
+            t_cross: s, r_blinding: s,
+            ecp_batched: batched_eCP {
+                k: 2,
+                A_vecs: vec![vec![[p, p], [p, p]]],
+                z: vec![s],
+            },
+        }
+    }
+
+    #[test]
+    fn sections_round_trip() {
+        let mut proof = dummy_proof();
+        proof.ipp_proof.U_vecs[0][1] = CompressedRistretto([3u8; 32]);
+        proof.ecp_batched.z[0] = Scalar::from(9u64);
+
+        let sections = proof.to_bytes_sections();
+        assert_eq!(sections.header.len(), 48);
+        assert_eq!(sections.points.len(), (13 + 2 + 4) * 32);
+        assert_eq!(sections.scalars.len(), (8 + 2 + 1) * 32);
+
+        let decoded = R1CSProof::from_bytes_sections(&sections).unwrap();
+        assert_eq!(decoded.to_bytes(), proof.to_bytes());
+
+        // A point moved into the scalar section no longer matches the headers.
+        let mut shifted = sections.clone();
+        let point: Vec<u8> = shifted.points.drain(..32).collect();
+        shifted.scalars.extend_from_slice(&point);
+        assert!(R1CSProof::from_bytes_sections(&shifted).is_err());
+    }
+
+    #[test]
+    fn transcript_id_identifies_proof_bytes() {
+        let proof = dummy_proof();
+        let copy = R1CSProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(proof.transcript_id(), copy.transcript_id());
+
+        let mut bytes = proof.to_bytes();
+        bytes[0] ^= 1;
+        let tampered = R1CSProof::from_bytes(&bytes).unwrap();
+        assert_ne!(proof.transcript_id(), tampered.transcript_id());
+    }
+
+    #[test]
+    fn metadata_matches_proof_parameters() {
+        let mut proof = dummy_proof();
+        proof.ipp_proof.k = 3;
+        proof.ipp_proof.U_vecs = vec![vec![RISTRETTO_BASEPOINT_COMPRESSED; 4]; 2];
+        proof.ipp_proof.a_final = vec![Scalar::one(); 2];
+        proof.ipp_proof.b_final = vec![Scalar::one(); 2];
+
+        let metadata = proof.metadata();
+        assert_eq!((metadata.k, metadata.d, metadata.m), (3, 2, 2));
+        assert_eq!(metadata.size, proof.to_bytes().len());
+        assert_eq!(metadata.transcript_id, proof.transcript_id());
+        assert_eq!(metadata.proof_type, ProofType::Shuffle);
+
+        let encoded = bincode::serialize(&metadata).unwrap();
+        assert_eq!(bincode::deserialize::<ProofMetadata>(&encoded).unwrap(), metadata);
+    }
+
+    /// Serializes `proof` the way a big-endian writer would: every `u64`
+    /// header uses `to_be_bytes`.
+    fn to_big_endian_bytes(proof: &R1CSProof) -> Vec<u8> {
+        fn header(buf: &mut Vec<u8>, x: usize) {
+            let mut slot = [0u8; 32];
+            slot[..8].copy_from_slice(&(x as u64).to_be_bytes());
+            buf.extend_from_slice(&slot);
+        }
+
+        let le = proof.to_bytes();
+        let fixed_len = (13 + 8) * 32;
+        let ipp_len = proof.ipp_proof.serialized_size();
+        let ecp_len = proof.ecp_batched.serialized_size();
+
+        let mut buf = le[..fixed_len].to_vec();
+        buf.extend_from_slice(&(ipp_len as u64).to_be_bytes());
+        buf.extend_from_slice(&(ecp_len as u64).to_be_bytes());
+
+        let ipp = &proof.ipp_proof;
+        header(&mut buf, ipp.k);
+        header(&mut buf, ipp.U_vecs.len());
+        header(&mut buf, ipp.a_final.len());
+        let ipp_start = fixed_len + 16;
+        buf.extend_from_slice(&le[ipp_start + 96..ipp_start + ipp_len]);
+
+        let ecp = &proof.ecp_batched;
+        header(&mut buf, ecp.k);
+        header(&mut buf, ecp.A_vecs.len());
+        header(&mut buf, ecp.z.len());
+        buf.extend_from_slice(&le[ipp_start + ipp_len + 96..]);
+
+        buf
+    }
+
+    #[test]
+    fn big_endian_headers_are_detected() {
+        let proof = dummy_proof();
+        let bytes = to_big_endian_bytes(&proof);
+        assert_eq!(bytes.len(), proof.to_bytes().len());
+
+        assert_eq!(R1CSProof::from_bytes(&bytes).unwrap_err(), ProofError::FormatError);
+        assert_eq!(
+            R1CSProof::from_bytes_detect(&bytes).unwrap_err(),
+            ProofError::EndiannessMismatch
+        );
+
+        // Canonical proofs and unrelated corruption are unaffected.
+        let le = proof.to_bytes();
+        assert_eq!(R1CSProof::from_bytes_detect(&le).unwrap().to_bytes(), le);
+        assert_eq!(
+            R1CSProof::from_bytes_detect(&le[..le.len() - 1]).unwrap_err(),
+            ProofError::FormatError
+        );
+    }
+
+    /// The unreduced encoding `7 + l` of the scalar 7.
+    const SEVEN_PLUS_L: [u8; 32] = [
+        0xf4, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+    ];
+
+    #[test]
+    fn non_canonical_encodings_are_detected() {
+        let bytes = dummy_proof().to_bytes();
+        assert!(R1CSProof::is_canonical_encoding(&bytes));
+
+        // Padding in the inner-product proof's `k` header slot.
+        let ipp_start = (13 + 8) * 32 + 16;
+        let mut padded_header = bytes.clone();
+        padded_header[ipp_start + 8] = 1;
+        assert!(!R1CSProof::is_canonical_encoding(&padded_header));
+
+        // `t_x = 7` encoded unreduced, as `7 + l`.
+        assert_eq!(Scalar::from_bytes_mod_order(SEVEN_PLUS_L), Scalar::from(7u64));
+        let mut unreduced = bytes.clone();
+        unreduced[13 * 32..14 * 32].copy_from_slice(&SEVEN_PLUS_L);
+        assert!(!R1CSProof::is_canonical_encoding(&unreduced));
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(!R1CSProof::is_canonical_encoding(&extended));
+        assert!(!R1CSProof::is_canonical_encoding(&bytes[..bytes.len() - 1]));
+    }
+
+    #[test]
+    fn validate_scalars_catches_unreduced_nested_scalars() {
+        assert_eq!(dummy_proof().validate_scalars(), Ok(()));
+        let decoded = R1CSProof::from_bytes(&dummy_proof().to_bytes()).unwrap();
+        assert_eq!(decoded.validate_scalars(), Ok(()));
+
+        let unreduced = Scalar::from_bits(SEVEN_PLUS_L);
+        let mut proof = dummy_proof();
+        proof.ipp_proof.b_final[0] = unreduced;
+        assert_eq!(proof.validate_scalars(), Err(ProofError::FormatError));
+
+        let mut proof = dummy_proof();
+        proof.ecp_batched.z[0] = unreduced;
+        assert_eq!(proof.validate_scalars(), Err(ProofError::FormatError));
+
+        let mut proof = dummy_proof();
+        proof.r_blinding = unreduced;
+        assert_eq!(proof.validate_scalars(), Err(ProofError::FormatError));
+
+        let mut proof = dummy_proof();
+        proof.ecp_batched.z.push(Scalar::one());
+        assert_eq!(proof.validate_scalars(), Err(ProofError::FormatError));
+    }
+}
@@ -13,8 +13,9 @@ use errors::R1CSError;
 use generators::{BulletproofGens, PedersenGens};
 use inner_product_proof::K_BulletProof;
 use inner_product_proof::batched_eCP;
-use transcript::TranscriptProtocol;
+use transcript::{ProofTranscript, TranscriptProtocol};
 use std::iter;
+use std::mem;
 
 
 
@@ -31,17 +32,45 @@ use std::iter;
 ///
 /// When all constraints are added, the proving code calls `prove`
 /// on the instance of the constraint system and receives the complete proof.
-pub struct Prover<'a, 'b> {
+pub struct Prover<'a, 'b, T: 'a = Transcript> {
     /// Number of high-level variables
     m: u64,
 
     /// Constraint system implementation
-    cs: ProverCS<'a, 'b>,
+    cs: ProverCS<'a, 'b, T>,
+}
+
+/// The one-time masks behind a proof's consistency blindings, returned by
+/// [`ProverCS::prove_with_blindings`].
+///
+/// With `x_prime` the consistency challenge, the proof's blindings are
+///
+/// ```text
+/// ec_blinding = v_blinding + ec_mask * x_prime
+/// r_blinding  = r_prime    + r_mask  * x_prime
+/// ```
+///
+/// where `v_blinding` is the output commitment's blinding and `r_prime`
+/// the re-randomization scalar passed to [`ProverCS::prove`].  Both
+/// blindings are public (see [`R1CSProof::ec_blinding`]), and only the
+/// masks keep `v_blinding` and `r_prime` hidden: anyone holding this
+/// struct can recover them.  Expose it only to protocols that must
+/// reconcile the proof against an external commitment, never to the
+/// verifier.
+#[derive(Copy, Clone, Debug)]
+pub struct ConsistencyBlindings {
+    /// The consistency challenge, drawn after `S_prime`, `T_1_prime`,
+    /// `S1_prime` and `S2_prime`.
+    pub x_prime: Scalar,
+    /// The blinding of `S_prime`, masking `v_blinding`.
+    pub ec_mask: Scalar,
+    /// The blinding of `S1_prime` and `S2_prime`, masking `r_prime`.
+    pub r_mask: Scalar,
 }
 
 /// A [`ConstraintSystem`] implementation for use by the prover.
-pub struct ProverCS<'a, 'b> {
-    transcript: &'a mut Transcript,
+pub struct ProverCS<'a, 'b, T: 'a = Transcript> {
+    transcript: &'a mut T,
     bp_gens: &'b BulletproofGens,
     pc_gens: &'b PedersenGens,
     /// The constraints accumulated so far.
@@ -56,10 +85,21 @@ pub struct ProverCS<'a, 'b> {
     v: Vec<Scalar>,
     /// High-level witness data (blinding openings to V commitments)
     v_blinding: Scalar,
+    /// The generator `v_blinding` multiplies in `V`, which also blinds
+    /// `S_prime`.
+    v_blinding_gen: RistrettoPoint,
+    /// The public key the ciphertexts' second components were
+    /// re-randomized under; `B_blinding` unless set.
+    reencryption_key: RistrettoPoint,
+    /// The party whose share of `bp_gens` the proof uses; 0 unless set.
+    party: usize,
+    /// Set by `finalize_inputs`; challenges drawn before then would not
+    /// bind every committed input.
+    inputs_finalized: bool,
 }
 
 /// Overwrite secrets with null bytes when they go out of scope.
-impl<'a, 'b> Drop for ProverCS<'a, 'b> {
+impl<'a, 'b, T> Drop for ProverCS<'a, 'b, T> {
     fn drop(&mut self) {
         self.v.clear();
         self.v_blinding.clear();
@@ -82,7 +122,7 @@ impl<'a, 'b> Drop for ProverCS<'a, 'b> {
     }
 }
 
-impl<'a, 'b> ConstraintSystem for ProverCS<'a, 'b> {
+impl<'a, 'b, T: ProofTranscript> ConstraintSystem for ProverCS<'a, 'b, T> {
     fn multiply(
         &mut self,
         mut left: LinearCombination,
@@ -136,11 +176,12 @@ impl<'a, 'b> ConstraintSystem for ProverCS<'a, 'b> {
     }
 
     fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        assert!(self.inputs_finalized, "challenge_scalar called before finalize_inputs");
         self.transcript.challenge_scalar(label)
     }
 }
 
-impl<'a, 'b> Prover<'a, 'b> {
+impl<'a, 'b, T: ProofTranscript> Prover<'a, 'b, T> {
     /// Construct an empty constraint system with specified external
     /// input variables.
     ///
@@ -150,7 +191,10 @@ impl<'a, 'b> Prover<'a, 'b> {
     /// and for the Pedersen commitments, respectively.  The
     /// [`BulletproofGens`] should have `gens_capacity` greater than
     /// the number of multiplication constraints that will eventually
-    /// be added into the constraint system.
+    /// be added into the constraint system.  Their
+    /// [`digest`](BulletproofGens::digest) is committed to the
+    /// transcript, so a prover and verifier using different generators
+    /// diverge from the first challenge.
     ///
     /// The `transcript` parameter is a Merlin proof transcript.  The
     /// `ProverCS` holds onto the `&mut Transcript` until it consumes
@@ -164,9 +208,10 @@ impl<'a, 'b> Prover<'a, 'b> {
     pub fn new(
         bp_gens: &'b BulletproofGens,
         pc_gens: &'b PedersenGens,
-        transcript: &'a mut Transcript,
+        transcript: &'a mut T,
     ) -> Self {
         transcript.r1cs_domain_sep();
+        transcript.append_message(b"generators", &bp_gens.digest(pc_gens));
 
         Prover {
             m: 0,
@@ -176,6 +221,10 @@ impl<'a, 'b> Prover<'a, 'b> {
                 transcript,
                 v: Vec::new(),
                 v_blinding: Scalar::zero(),
+                v_blinding_gen: pc_gens.B_blinding,
+                reencryption_key: pc_gens.B_blinding,
+                party: 0,
+                inputs_finalized: false,
                 constraints: Vec::new(),
                 a_L: Vec::new(),
                 a_R: Vec::new(),
@@ -201,14 +250,27 @@ impl<'a, 'b> Prover<'a, 'b> {
     ///
     /// Returns a pair of a Pedersen commitment (as a compressed Ristretto point),
     /// and a [`Variable`] corresponding to it, which can be used to form constraints.
-    
-   
-    
     pub fn commit_vec(
         &mut self,
         v: &[Scalar],
         v_blinding: Scalar,
         k_original: usize,
+    ) -> (CompressedRistretto, Vec<Variable>) {
+        self.commit_vec_with_generator(v, v_blinding, k_original, None)
+    }
+
+    /// Like [`Prover::commit_vec`], but blinds the commitment with
+    /// `blinding_gen` instead of `B_blinding`, for instance to bind it to a
+    /// specific key.  `None` means `B_blinding`.
+    ///
+    /// The verifier must pass the same generator to
+    /// [`Verifier::commit_vec_with_generator`](::r1cs::Verifier::commit_vec_with_generator).
+    pub fn commit_vec_with_generator(
+        &mut self,
+        v: &[Scalar],
+        v_blinding: Scalar,
+        k_original: usize,
+        blinding_gen: Option<RistrettoPoint>,
     ) -> (CompressedRistretto, Vec<Variable>) {
         let start_index = self.m as usize;
         let n_padded = v.len();
@@ -223,12 +285,13 @@ impl<'a, 'b> Prover<'a, 'b> {
         }
         //self.cs.v_blinding.push(v_blinding);
         self.cs.v_blinding = v_blinding;
+        self.cs.v_blinding_gen = blinding_gen.unwrap_or(self.cs.pc_gens.B_blinding);
 
         let V = RistrettoPoint::multiscalar_mul(
             iter::once(&v_blinding)
                 .chain(v.iter()), 
-            iter::once(&self.cs.pc_gens.B_blinding)
-                .chain(self.cs.bp_gens.G(n_padded, 1)), 
+            iter::once(&self.cs.v_blinding_gen)
+                .chain(self.cs.bp_gens.share(self.cs.party).G(n_padded)), 
         )
         .compress();
 
@@ -245,21 +308,89 @@ impl<'a, 'b> Prover<'a, 'b> {
         (V, vars)
     }
 
+    /// Commits to `v` with one blinding factor per element.
+    ///
+    /// Each element gets its own commitment
+    /// `V_i = v_i * G_i + blindings_i * B_blinding`, and the vector
+    /// commitment is their sum, so a single `V_i` can be opened without
+    /// revealing the rest of the vector.  The blinding generator is
+    /// shared, so the proof only has to account for the summed blinding
+    /// `sum(blindings_i)`, which is what [`ProverCS::prove`] opens.
+    ///
+    /// # Returns
+    ///
+    /// Returns the vector commitment `V`, the per-element commitments
+    /// (which sum to `V`), and the committed variables.
+    pub fn commit_vec_per_element(
+        &mut self,
+        v: &[Scalar],
+        blindings: &[Scalar],
+        k_original: usize,
+    ) -> (CompressedRistretto, Vec<CompressedRistretto>, Vec<Variable>) {
+        assert_eq!(v.len(), blindings.len());
+
+        let mut v_blinding = blindings.iter().fold(Scalar::zero(), |acc, b| acc + b);
+        let B_blinding = self.cs.pc_gens.B_blinding;
+        let V_i: Vec<CompressedRistretto> = v
+            .iter()
+            .zip(blindings.iter())
+            .zip(self.cs.bp_gens.share(self.cs.party).G(v.len()))
+            .map(|((v_i, b_i), G_i)| (G_i * v_i + B_blinding * b_i).compress())
+            .collect();
+
+        let (V, vars) = self.commit_vec(v, v_blinding, k_original);
+        v_blinding.clear();
+        (V, V_i, vars)
+    }
+
+    /// Sets the public key the ciphertexts' second components were
+    /// re-randomized under, in place of `B_blinding`.
+    ///
+    /// The consistency proof then checks
+    /// `C2_prime[i] = C2[pi[i]] + r[i] * key`, while the first components
+    /// stay re-randomized under `B`.  The key is committed to the
+    /// transcript, so the verifier must set the same one with
+    /// [`Verifier::set_reencryption_key`](::r1cs::Verifier::set_reencryption_key)
+    /// at the same point.
+    pub fn set_reencryption_key(&mut self, key: RistrettoPoint) {
+        self.cs.reencryption_key = key;
+        self.cs.transcript.commit_point(b"reencryption key", &key.compress());
+    }
+
+    /// Makes the proof use party `j`'s share of the generators instead of
+    /// party 0's, so that the proofs of several parties can be checked
+    /// together without sharing any generator.
+    ///
+    /// Must be called before committing any variable.  The party index is
+    /// committed to the transcript, so the verifier must set the same one
+    /// with [`Verifier::set_party`](::r1cs::Verifier::set_party).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `j` is not below the generators' `party_capacity`.
+    pub fn set_party(&mut self, j: usize) {
+        assert!(j < self.cs.bp_gens.party_capacity, "party index exceeds the generators' party capacity");
+        assert_eq!(self.m, 0, "set_party must be called before committing variables");
+        self.cs.party = j;
+        self.cs.transcript.commit_u64(b"party", j as u64);
+    }
+
     /// Consume the `Prover`, provide the `ConstraintSystem` implementation to the closure,
     /// and produce a proof.
-    pub fn finalize_inputs(self) -> ProverCS<'a, 'b> {
+    pub fn finalize_inputs(mut self) -> ProverCS<'a, 'b, T> {
         // Commit a length _suffix_ for the number of high-level variables.
         // We cannot do this in advance because user can commit variables one-by-one,
         // but this suffix provides safe disambiguation because each variable
         // is prefixed with a separate label.
         self.cs.transcript.commit_u64(b"m", self.m);
+        self.cs.inputs_finalized = true;
         self.cs
     }
 }
 
 
 
-impl<'a, 'b> ProverCS<'a, 'b> {
+impl<'a, 'b, T: ProofTranscript> ProverCS<'a, 'b, T> {
     /// Use a challenge, `z`, to flatten the constraints in the
     /// constraint system into vectors used for proving and
     /// verification.
@@ -326,17 +457,64 @@ impl<'a, 'b> ProverCS<'a, 'b> {
             .sum()
     }
 
+    /// Returns the transcript the proof is written to, for instance to
+    /// export a [`TranscriptState`](::TranscriptState) after
+    /// [`Prover::finalize_inputs`].
+    pub fn transcript(&self) -> &T {
+        self.transcript
+    }
+
+    /// Moves the constraint system and its witness onto `transcript`, to
+    /// continue the proof there.
+    ///
+    /// `transcript` must be in the same state as the current one, such as
+    /// a [`TranscriptState`](::TranscriptState) imported from it; the
+    /// proof is then the same as if it had been finished on the original.
+    pub fn with_transcript<'c, U: ProofTranscript>(mut self, transcript: &'c mut U) -> ProverCS<'c, 'b, U> {
+        ProverCS {
+            transcript,
+            bp_gens: self.bp_gens,
+            pc_gens: self.pc_gens,
+            constraints: mem::replace(&mut self.constraints, Vec::new()),
+            a_L: mem::replace(&mut self.a_L, Vec::new()),
+            a_R: mem::replace(&mut self.a_R, Vec::new()),
+            a_O: mem::replace(&mut self.a_O, Vec::new()),
+            v: mem::replace(&mut self.v, Vec::new()),
+            v_blinding: self.v_blinding,
+            v_blinding_gen: self.v_blinding_gen,
+            reencryption_key: self.reencryption_key,
+            party: self.party,
+            inputs_finalized: self.inputs_finalized,
+        }
+    }
+
     pub fn prove(
-    mut self,
+    self,
     C1_prime: &[RistrettoPoint],
     C2_prime: &[RistrettoPoint],
     r_prime: Scalar,
     k_fold: usize,
     num_rounds:usize,
 ) -> Result<R1CSProof, R1CSError> {
+    self.prove_with_blindings(C1_prime, C2_prime, r_prime, k_fold, num_rounds)
+        .map(|(proof, _)| proof)
+}
+
+    /// Like [`ProverCS::prove`], also returning the masks behind the
+    /// proof's consistency blindings, for reconciling them against an
+    /// external commitment scheme.
+    ///
+    /// See [`ConsistencyBlindings`] for the relations they satisfy and
+    /// why they must not leave the prover in most protocols.
+    pub fn prove_with_blindings(
+    mut self,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    r_prime: Scalar,
+    k_fold: usize,
+    num_rounds:usize,
+) -> Result<(R1CSProof, ConsistencyBlindings), R1CSError> {
     // Standard Imports
-    use inner_product_proof::inner_product;
-    use rand::thread_rng;
     use std::iter;
     use util;
 
@@ -350,44 +528,43 @@ impl<'a, 'b> ProverCS<'a, 'b> {
         return Err(R1CSError::InvalidGeneratorsLength);
     }
     
-    let gens = self.bp_gens.share(0);
+    let gens = self.bp_gens.share(self.party);
 
     // -----------------------------------------------------------------------------
     // 1. Transcript & RNG
     // -----------------------------------------------------------------------------
-    let mut rng = {
-        let mut builder = self.transcript.build_rng();
-        builder = builder.commit_witness_bytes(b"v_blinding", self.v_blinding.as_bytes());
-        builder.finalize(&mut thread_rng())
-    };
+    let mut rng = self.transcript.witness_rng(&[(b"v_blinding", self.v_blinding.as_bytes())]);
 
     // -----------------------------------------------------------------------------
     // 2. Circuit Commitment
     // -----------------------------------------------------------------------------
-    let i_blinding = Scalar::random(&mut rng);
-    let o_blinding = Scalar::random(&mut rng);
-    let s_blinding = Scalar::random(&mut rng);
+    // Zeroed when dropped, on every return path.
+    let mut secrets = ProverBlindings::default();
+    secrets.i_blinding = Scalar::random(&mut rng);
+    secrets.o_blinding = Scalar::random(&mut rng);
+    secrets.s_blinding = Scalar::random(&mut rng);
 
     // OPTIMIZATION: Pre-allocate memory to avoid re-allocations
-    let mut s_L = Vec::with_capacity(n);
-    let mut s_R = Vec::with_capacity(n);
+    secrets.s_L.reserve(n);
+    secrets.s_R.reserve(n);
     for _ in 0..n {
-        s_L.push(Scalar::random(&mut rng));
-        s_R.push(Scalar::random(&mut rng));
+        secrets.s_L.push(Scalar::random(&mut rng));
+        secrets.s_R.push(Scalar::random(&mut rng));
     }
+    let (s_L, s_R) = (&secrets.s_L, &secrets.s_R);
 
     let A_I = RistrettoPoint::multiscalar_mul(
-        iter::once(&i_blinding).chain(self.a_L.iter()).chain(self.a_R.iter()),
+        iter::once(&secrets.i_blinding).chain(self.a_L.iter()).chain(self.a_R.iter()),
         iter::once(&self.pc_gens.B_blinding).chain(gens.G(n)).chain(gens.H(n)),
     ).compress();
 
     let A_O = RistrettoPoint::multiscalar_mul(
-        iter::once(&o_blinding).chain(self.a_O.iter()),
+        iter::once(&secrets.o_blinding).chain(self.a_O.iter()),
         iter::once(&self.pc_gens.B_blinding).chain(gens.G(n)),
     ).compress();
 
     let S = RistrettoPoint::multiscalar_mul(
-        iter::once(&s_blinding).chain(s_L.iter()).chain(s_R.iter()),
+        iter::once(&secrets.s_blinding).chain(s_L.iter()).chain(s_R.iter()),
         iter::once(&self.pc_gens.B_blinding).chain(gens.G(n)).chain(gens.H(n)),
     ).compress();
 
@@ -404,34 +581,33 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     let (wL, wR, wO, wV) = self.flattened_constraints(&z);
 
     let y_inv = y.invert();
-    let exp_y_inv: Vec<Scalar> = util::exp_iter(y_inv).take(k).collect();
+    let exp_y = util::PowerTable::up_to(y, k);
+    let exp_y_inv = util::PowerTable::up_to(y_inv, k);
 
     let mut l_poly = util::VecPoly3::zero(n);
     let mut r_poly = util::VecPoly3::zero(n);
-    let mut exp_y = Scalar::one();
 
     for i in 0..n {
         l_poly.1[i] = self.a_L[i] + exp_y_inv[i] * wR[i];
         l_poly.2[i] = self.a_O[i];
         l_poly.3[i] = s_L[i];
 
-        r_poly.0[i] = wO[i] - exp_y;
-        r_poly.1[i] = exp_y * self.a_R[i] + wL[i];
-        r_poly.3[i] = exp_y * s_R[i];
-
-        exp_y *= y;
+        r_poly.0[i] = wO[i] - exp_y[i];
+        r_poly.1[i] = exp_y[i] * self.a_R[i] + wL[i];
+        r_poly.3[i] = exp_y[i] * s_R[i];
     }
 
     let t_poly = util::VecPoly3::special_inner_product(&l_poly, &r_poly);
 
     // T Commitments
-    let t_blindings = [
+    secrets.t_blindings = [
         Scalar::random(&mut rng), // 1
         Scalar::random(&mut rng), // 3
         Scalar::random(&mut rng), // 4
         Scalar::random(&mut rng), // 5
         Scalar::random(&mut rng), // 6
     ];
+    let t_blindings = &secrets.t_blindings;
 
     let T_1 = self.pc_gens.commit(t_poly.t1, t_blindings[0]).compress();
     let T_3 = self.pc_gens.commit(t_poly.t3, t_blindings[1]).compress();
@@ -440,9 +616,9 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     let T_6 = self.pc_gens.commit(t_poly.t6, t_blindings[4]).compress();
 
     // T2 Calculation
-    let t_2_blinding = Scalar::random(&mut rng);
+    secrets.t_2_blinding = Scalar::random(&mut rng);
     let t_2: Scalar = wV.iter().zip(self.v.iter()).map(|(c, v)| c * v).sum();
-    let T_2 = self.pc_gens.commit(t_2, t_2_blinding).compress();
+    let T_2 = self.pc_gens.commit(t_2, secrets.t_2_blinding).compress();
 
     self.transcript.commit_point(b"T_1", &T_1);
     self.transcript.commit_point(b"T_3", &T_3);
@@ -456,23 +632,24 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     // Evaluate T(x)
     let t_x = t_poly.eval(x);
     let t_x_blinding = util::Poly6 {
-        t1: t_blindings[0], t2: t_2_blinding, t3: t_blindings[1], 
+        t1: t_blindings[0], t2: secrets.t_2_blinding, t3: t_blindings[1], 
         t4: t_blindings[2], t5: t_blindings[3], t6: t_blindings[4],
     }.eval(x);
 
     // Evaluate l and r vectors
     let mut l_vec = l_poly.eval(x);
     let mut r_vec = r_poly.eval(x);
+    #[cfg(feature = "debug")]
+    assert_eq!(inner_product(&l_vec, &r_vec), t_x, "<l(x), r(x)> != t(x)");
     
     l_vec.resize(k, Scalar::zero());
     r_vec.resize(k, Scalar::zero());
 
     for i in n..k {
-        r_vec[i] = -exp_y;
-        exp_y *= y;
+        r_vec[i] = -exp_y[i];
     }
 
-    let e_blinding = x * (i_blinding + x * (o_blinding + x * s_blinding));
+    let e_blinding = x * (secrets.i_blinding + x * (secrets.o_blinding + x * secrets.s_blinding));
 
     self.transcript.commit_scalar(b"t_x", &t_x);
     self.transcript.commit_scalar(b"t_x_blinding", &t_x_blinding);
@@ -481,29 +658,32 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     // -----------------------------------------------------------------------------
     // 4. Consistency Setup
     // -----------------------------------------------------------------------------
-    let s_bl_prime = Scalar::random(&mut rng);
-    let rnd = Scalar::random(&mut rng);
+    secrets.s_bl_prime = Scalar::random(&mut rng);
+    secrets.rnd = Scalar::random(&mut rng);
     let k_original = C1_prime.len();
 
-    let mut s_L_prime = Vec::with_capacity(k);
+    // Room for the padding below, so that it never moves the vector and
+    // leaves an uncleared copy behind.
+    secrets.s_L_prime.reserve(k);
     
     for _ in 0..k_original { 
-        s_L_prime.push(Scalar::random(&mut rng)); 
+        secrets.s_L_prime.push(Scalar::random(&mut rng)); 
     }
+    let s_L_prime = &mut secrets.s_L_prime;
 
     let S_prime = RistrettoPoint::multiscalar_mul(
-        iter::once(&s_bl_prime).chain(s_L_prime[0..k_original].iter()),
-        iter::once(&self.pc_gens.B_blinding).chain(gens.G(k_original)),
+        iter::once(&secrets.s_bl_prime).chain(s_L_prime[0..k_original].iter()),
+        iter::once(&self.v_blinding_gen).chain(gens.G(k_original)),
     ).compress();
 
     let S1_prime = RistrettoPoint::multiscalar_mul(
-        iter::once(&rnd).chain(s_L_prime[0..k_original].iter()),
+        iter::once(&secrets.rnd).chain(s_L_prime[0..k_original].iter()),
         iter::once(&self.pc_gens.B).chain(C1_prime.iter()),
     ).compress();
 
     let S2_prime = RistrettoPoint::multiscalar_mul(
-        iter::once(&rnd).chain(s_L_prime[0..k_original].iter()),
-        iter::once(&self.pc_gens.B_blinding).chain(C2_prime.iter()),
+        iter::once(&secrets.rnd).chain(s_L_prime[0..k_original].iter()),
+        iter::once(&self.reencryption_key).chain(C2_prime.iter()),
     ).compress();
 
 
@@ -522,9 +702,9 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     }
 
     let tc_poly = lc_poly.inner_product(&rc_poly);
-    let t1_bl_prime = Scalar::random(&mut rng);
-    let T_1_prime = self.pc_gens.commit(tc_poly.1, t1_bl_prime).compress();
-    let tc_bl_poly = util::Poly2(t_2_blinding, t1_bl_prime, Scalar::zero());
+    secrets.t1_bl_prime = Scalar::random(&mut rng);
+    let T_1_prime = self.pc_gens.commit(tc_poly.1, secrets.t1_bl_prime).compress();
+    let tc_bl_poly = util::Poly2(secrets.t_2_blinding, secrets.t1_bl_prime, Scalar::zero());
 
     self.transcript.commit_point(b"S_prime", &S_prime);
     self.transcript.commit_point(b"T_1_prime", &T_1_prime);
@@ -535,8 +715,8 @@ impl<'a, 'b> ProverCS<'a, 'b> {
 
     let tc_x = tc_poly.eval(x_prime);
     let tc_x_blinding = tc_bl_poly.eval(x_prime);
-    let ec_blinding = self.v_blinding + s_bl_prime * x_prime;
-    let r_blinding = r_prime + rnd * x_prime;
+    let ec_blinding = self.v_blinding + secrets.s_bl_prime * x_prime;
+    let r_blinding = r_prime + secrets.rnd * x_prime;
 
     self.transcript.commit_scalar(b"tc_x", &tc_x);
     self.transcript.commit_scalar(b"tc_x_blinding", &tc_x_blinding);
@@ -555,9 +735,12 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     // we only dot-product the valid range (0..k).
     // t_cross = <l, rc_pad> + <lc_pad, r>  
     // => <l[0..k], rc> + <lc, r[0..k]>
-    let t_cross = inner_product(&l_vec[0..k], &rc_vec) 
-                + inner_product(&lc_vec, &r_vec[0..k]);
+    let t_cross = cross_term(&l_vec, &r_vec, &lc_vec, &rc_vec, k)?;
 
+    // Invariant: `t_cross` is committed before `x_ipp` is drawn, and both
+    // come before the IPA and the ECP, so `x_ipp` binds the cross term and
+    // every later challenge (including `chall_batched_ecp`) depends on it.
+    // The verifier replays the same order; see `VerifierCS::verify_diagnostic`.
     self.transcript.commit_scalar(b"t_cross", &t_cross);
     let x_ipp = self.transcript.challenge_scalar(b"x_ipp");
 
@@ -578,7 +761,7 @@ impl<'a, 'b> ProverCS<'a, 'b> {
 
     
     let H_prime: Vec<RistrettoPoint> = gens.H(k)
-        .zip(exp_y_inv.iter())
+        .zip(exp_y_inv.as_slice().iter())
         .map(|(H_i, exp_i)| H_i * exp_i)
         .collect();
 
@@ -587,7 +770,7 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     let ipp_proof = K_BulletProof::create(
         self.transcript,
         k_fold,
-        &self.bp_gens.G_vec[0][0..k], 
+        &self.bp_gens.G_vec[self.party][0..k], 
         &H_prime,                        
         Q_agg,
         &l_agg,                          
@@ -609,20 +792,25 @@ impl<'a, 'b> ProverCS<'a, 'b> {
     let ecp_batched = batched_eCP::create(
         self.transcript,
         k_fold,
-        &self.bp_gens.G_vec[0][0..k], 
+        &self.bp_gens.G_vec[self.party][0..k], 
         &C_agg,
         &lc_vec,
         num_rounds,
     );
-   
+    check_fold_factor(ipp_proof.k, ecp_batched.k, k_fold);
     
+    let blindings = ConsistencyBlindings {
+        x_prime,
+        ec_mask: secrets.s_bl_prime,
+        r_mask: secrets.rnd,
+    };
+
     // -----------------------------------------------------------------------------
     // 7. Cleanup
     // -----------------------------------------------------------------------------
-    for e in s_L.iter_mut() { e.clear(); }
-    for e in s_R.iter_mut() { e.clear(); }
+    drop(secrets);
 
-    Ok(R1CSProof {
+    Ok((R1CSProof {
         A_I, A_O, S,
         T_1, T_2, T_3, T_4, T_5, T_6,
         t_x, t_x_blinding, e_blinding,
@@ -632,7 +820,185 @@ impl<'a, 'b> ProverCS<'a, 'b> {
         t_cross,
         S1_prime, S2_prime, r_blinding,
         ecp_batched,
-    })
+    }, blindings))
+}
+
+}
+
+/// Asserts that both sub-proofs were folded by the caller's `k_fold`.
+///
+/// The verifier reads each sub-proof's `k` from the proof itself, so a
+/// wiring bug passing different factors to them would otherwise surface
+/// only as an unexplained verification failure.
+fn check_fold_factor(ipp_k: usize, ecp_k: usize, k_fold: usize) {
+    assert!(
+        ipp_k == k_fold && ecp_k == k_fold,
+        "sub-proofs folded by k = {} (IPA) and {} (ECP), expected k_fold = {}",
+        ipp_k, ecp_k, k_fold
+    );
+}
+
+/// Computes `<l[0..k], rc> + <lc, r[0..k]>`, checking first that no
+/// vector would be silently truncated by the slicing.
+fn cross_term(
+    l_vec: &[Scalar],
+    r_vec: &[Scalar],
+    lc_vec: &[Scalar],
+    rc_vec: &[Scalar],
+    k: usize,
+) -> Result<Scalar, R1CSError> {
+    if l_vec.len() < k || r_vec.len() < k || lc_vec.len() != k || rc_vec.len() != k {
+        return Err(R1CSError::PolynomialLengthMismatch);
+    }
+    Ok(inner_product(&l_vec[0..k], rc_vec) + inner_product(lc_vec, &r_vec[0..k]))
+}
+
+/// The one-time blinding scalars drawn while proving.
+///
+/// None of these appear in the proof, and each one reveals part of the
+/// witness given the proof, so they are overwritten with zeros on drop,
+/// whether proving succeeds or returns early.
+#[derive(Default)]
+struct ProverBlindings {
+    i_blinding: Scalar,
+    o_blinding: Scalar,
+    s_blinding: Scalar,
+    s_L: Vec<Scalar>,
+    s_R: Vec<Scalar>,
+    t_blindings: [Scalar; 5],
+    t_2_blinding: Scalar,
+    s_bl_prime: Scalar,
+    rnd: Scalar,
+    t1_bl_prime: Scalar,
+    s_L_prime: Vec<Scalar>,
+}
+
+impl ProverBlindings {
+    fn scalars_mut(&mut self) -> impl Iterator<Item = &mut Scalar> {
+        iter::once(&mut self.i_blinding)
+            .chain(iter::once(&mut self.o_blinding))
+            .chain(iter::once(&mut self.s_blinding))
+            .chain(self.s_L.iter_mut())
+            .chain(self.s_R.iter_mut())
+            .chain(self.t_blindings.iter_mut())
+            .chain(iter::once(&mut self.t_2_blinding))
+            .chain(iter::once(&mut self.s_bl_prime))
+            .chain(iter::once(&mut self.rnd))
+            .chain(iter::once(&mut self.t1_bl_prime))
+            .chain(self.s_L_prime.iter_mut())
+    }
+}
+
+impl Drop for ProverBlindings {
+    fn drop(&mut self) {
+        // Item by item: `Clear` on a `Vec` only clears its header (see
+        // `ProverCS::drop`).
+        for e in self.scalars_mut() {
+            e.clear();
+        }
+
+        #[cfg(test)]
+        tests::DROPPED_BLINDINGS.with(|dropped| {
+            *dropped.borrow_mut() = self.scalars_mut().map(|e| *e).collect();
+        });
+    }
 }
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use r1cs::{ProofConfig, ShuffleProof, ShuffleStatement, ShuffleWitness};
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// The scalars of the last `ProverBlindings` dropped on this
+        /// thread, as they were left after clearing.
+        pub(super) static DROPPED_BLINDINGS: RefCell<Vec<Scalar>> = RefCell::new(Vec::new());
+    }
+
+    #[test]
+    fn prove_clears_blinding_scalars() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(4, 1);
+        let input: Vec<Scalar> = (1..5u64).map(Scalar::from).collect();
+        let C: Vec<RistrettoPoint> = (0..4).map(|i| pc_gens.commit(input[i], Scalar::one())).collect();
+
+        DROPPED_BLINDINGS.with(|dropped| dropped.borrow_mut().clear());
+
+        let statement = ShuffleStatement {
+            input: input.clone(), C1: C.clone(), C2: C.clone(), C1_prime: C.clone(), C2_prime: C,
+        };
+        let witness = ShuffleWitness::new(statement, input, Scalar::zero());
+
+        let mut transcript = Transcript::new(b"ProverBlindingsTest");
+        ShuffleProof::prove(
+            &pc_gens, &bp_gens, &mut transcript, &[], &witness, &ProofConfig::new(4, 2, 1),
+        ).unwrap();
+
+        // 3 commitment blindings, s_L and s_R over the shuffle's 3 gates,
+        // 6 t blindings, 3 consistency blindings and s_L' over the 4 inputs.
+        let dropped = DROPPED_BLINDINGS.with(|dropped| dropped.borrow().clone());
+        assert_eq!(dropped.len(), 3 + 2 * 3 + 6 + 3 + 4);
+        assert!(dropped.iter().all(|e| e.as_bytes() == &[0u8; 32]));
+    }
+
+    #[test]
+    fn cross_term_rejects_length_mismatch() {
+        let v: Vec<Scalar> = (1..5u64).map(Scalar::from).collect();
+
+        // <v[0..3], v[0..3]> + <v[0..3], v[0..3]> = 2 * (1 + 4 + 9)
+        assert_eq!(cross_term(&v, &v, &v[0..3], &v[0..3], 3), Ok(Scalar::from(28u64)));
+
+        assert_eq!(
+            cross_term(&v, &v, &v, &v[0..3], 3),
+            Err(R1CSError::PolynomialLengthMismatch)
+        );
+        assert_eq!(
+            cross_term(&v, &v, &v[0..3], &v[0..2], 3),
+            Err(R1CSError::PolynomialLengthMismatch)
+        );
+        assert_eq!(
+            cross_term(&v[0..2], &v, &v[0..3], &v[0..3], 3),
+            Err(R1CSError::PolynomialLengthMismatch)
+        );
+    }
+
+    #[test]
+    fn matching_fold_factors_pass() {
+        check_fold_factor(3, 3, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected k_fold = 3")]
+    fn mismatched_fold_factor_is_caught() {
+        check_fold_factor(3, 2, 3);
+    }
+
+    #[test]
+    fn constant_evaluates_to_its_value() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(2, 1);
+        let mut transcript = Transcript::new(b"ConstantTest");
+        let mut prover = Prover::new(&bp_gens, &pc_gens, &mut transcript);
+        let (_, vars) = prover.commit_vec(&[Scalar::from(3u64), Scalar::zero()], Scalar::one(), 2);
+        let mut cs = prover.finalize_inputs();
+
+        let five = cs.constant(Scalar::from(5u64));
+        let (_, _, out) = cs.multiply(vars[0].into(), five);
+        let fifteen = cs.constant(Scalar::from(15u64));
+        assert_eq!(cs.eval(&fifteen), Scalar::from(15u64));
+        assert_eq!(cs.eval(&(out - fifteen)), Scalar::zero());
+    }
+
+    #[test]
+    #[should_panic(expected = "challenge_scalar called before finalize_inputs")]
+    fn early_challenge_is_caught() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(4, 1);
+        let mut transcript = Transcript::new(b"EarlyChallengeTest");
+        let mut prover = Prover::new(&bp_gens, &pc_gens, &mut transcript);
+        prover.commit_vec(&[Scalar::one(); 4], Scalar::one(), 4);
+        prover.cs.challenge_scalar(b"k-scalar shuffle challenge");
+    }
+}
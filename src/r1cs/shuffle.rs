@@ -0,0 +1,2138 @@
+//! A k-scalar shuffle proof over re-encrypted ElGamal ciphertexts.
+
+#![allow(non_snake_case)]
+
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, MultiscalarMul, VartimeMultiscalarMul};
+use rand::thread_rng;
+use merlin::Transcript;
+use sha3::Sha3_512;
+use std::collections::{HashMap, VecDeque};
+use std::iter;
+use std::mem;
+
+use super::verifier::VerifierCS;
+use super::{MerkleProof, ProofConfig, ShuffleParams};
+use super::{ConstraintSystem, LinearCombination, Prover, R1CSProof, Variable, Verifier};
+
+use errors::R1CSError;
+use generators::{BulletproofGens, PedersenGens, PrecomputedGens};
+use transcript::{ProofTranscript, TranscriptProtocol};
+use util;
+use util::MaybeParallelIterator;
+
+/// Proves that a committed vector of scalars is a permutation of a public
+/// one, and that the permuted ciphertexts `(C1', C2')` are a re-encryption
+/// of the original ciphertexts under that permutation.
+///
+/// The statement has two sizes:
+///
+/// * `k_original`, the number of ciphertexts, which is always the length of
+///   `C1_prime` and `C2_prime`;
+/// * `n_padded >= k_original`, the length of the `input`/`output` witness,
+///   which is zero-padded so the folding argument can run on it.
+///
+/// Only the witness is padded; see [`ShuffleProof::pad_ciphertexts`].
+///
+/// Ciphertexts with another number of components, such as a single group
+/// element, are shuffled with [`ShuffleProof::prove_components`].
+pub struct ShuffleProof {}
+
+/// The public side of one re-encryption shuffle: the padded `input`
+/// scalars, the original ciphertexts `(C1, C2)` and the shuffled,
+/// re-randomized ciphertexts `(C1_prime, C2_prime)`.
+///
+/// A shuffle with permutation `pi` and re-randomizers `r` satisfies
+/// `C1_prime[i] = C1[pi[i]] + r[i] * B` and
+/// `C2_prime[i] = C2[pi[i]] + r[i] * B_blinding`.
+#[derive(Clone, Debug)]
+pub struct ShuffleStatement {
+    /// The public scalars, zero-padded to `n_padded`.
+    pub input: Vec<Scalar>,
+    /// First components of the original ciphertexts.
+    pub C1: Vec<RistrettoPoint>,
+    /// Second components of the original ciphertexts.
+    pub C2: Vec<RistrettoPoint>,
+    /// First components of the shuffled ciphertexts.
+    pub C1_prime: Vec<RistrettoPoint>,
+    /// Second components of the shuffled ciphertexts.
+    pub C2_prime: Vec<RistrettoPoint>,
+}
+
+impl ShuffleStatement {
+    /// Returns the aggregated original ciphertexts
+    /// `(sum input_i * C1_i, sum input_i * C2_i)` that the shuffled
+    /// ciphertexts are checked against.
+    pub fn aggregate(&self) -> Vec<RistrettoPoint> {
+        let mut C = vec![RistrettoPoint::default(); 2];
+        for ((x, C1), C2) in self.input.iter().zip(self.C1.iter()).zip(self.C2.iter()) {
+            C[0] += C1 * x;
+            C[1] += C2 * x;
+        }
+        C
+    }
+
+    /// Returns the statement `self + lambda * other`, taken elementwise.
+    fn combine(&self, other: &ShuffleStatement, lambda: Scalar) -> ShuffleStatement {
+        fn add<T>(a: &[T], b: &[T], f: impl Fn(&T, &T) -> T) -> Vec<T> {
+            a.iter().zip(b.iter()).map(|(x, y)| f(x, y)).collect()
+        }
+        ShuffleStatement {
+            input: add(&self.input, &other.input, |x, y| x + lambda * y),
+            C1: add(&self.C1, &other.C1, |x, y| x + y * lambda),
+            C2: add(&self.C2, &other.C2, |x, y| x + y * lambda),
+            C1_prime: add(&self.C1_prime, &other.C1_prime, |x, y| x + y * lambda),
+            C2_prime: add(&self.C2_prime, &other.C2_prime, |x, y| x + y * lambda),
+        }
+    }
+
+    /// Checks the statement's lengths against each other: the shuffled
+    /// ciphertexts must hold exactly the `k_original` ciphertexts of the
+    /// original ones, which `input` must be padded to cover.
+    fn check(&self) -> Result<(), R1CSError> {
+        let k_original = self.C1.len();
+        if self.C2.len() != k_original {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        ShuffleProof::check_ciphertexts(&self.C1_prime, &self.C2_prime, k_original, self.input.len())
+    }
+}
+
+/// The public side of a shuffle of ciphertexts with any number `m >= 1`
+/// of components, proved by [`ShuffleProof::prove_components`].
+///
+/// `originals[j]` and `shuffled[j]` hold the `j`-th component of every
+/// original and shuffled ciphertext.  The first component is
+/// re-randomized under `B` and component `j >= 1` under `keys[j - 1]`:
+/// `shuffled[j][i] = originals[j][pi[i]] + r[i] * keys[j - 1]`, so an
+/// ElGamal shuffle has `m = 2` and a single group element per ciphertext
+/// has `m = 1` and no keys.
+#[derive(Clone, Debug)]
+pub struct ComponentStatement {
+    /// The public scalars, zero-padded to `n_padded`.
+    pub input: Vec<Scalar>,
+    /// The components of the original ciphertexts, one vector per component.
+    pub originals: Vec<Vec<RistrettoPoint>>,
+    /// The components of the shuffled ciphertexts, one vector per component.
+    pub shuffled: Vec<Vec<RistrettoPoint>>,
+    /// The re-encryption key of each component after the first.
+    pub keys: Vec<RistrettoPoint>,
+}
+
+impl ComponentStatement {
+    /// Returns the aggregate `sum input_i * originals[j][i]` of each
+    /// component `j`, as [`ShuffleStatement::aggregate`] does for both
+    /// components of an ElGamal pair.
+    pub fn aggregate(&self) -> Vec<RistrettoPoint> {
+        self.originals
+            .iter()
+            .map(|component| component.iter().zip(self.input.iter()).map(|(P, x)| P * x).sum())
+            .collect()
+    }
+
+    /// Checks the number of components against the keys, and every
+    /// component's length against the first original one's.
+    fn check(&self) -> Result<(), R1CSError> {
+        let m = self.shuffled.len();
+        if m == 0 || self.originals.len() != m || self.keys.len() + 1 != m {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        let k_original = self.originals[0].len();
+        for (original, shuffled) in self.originals.iter().zip(self.shuffled.iter()) {
+            ShuffleProof::check_ciphertexts(original, shuffled, k_original, self.input.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// The public side of a shuffle checked by
+/// [`ShuffleProof::verify_streaming`], which streams the shuffled
+/// ciphertexts instead of holding them, and takes the original ones only
+/// as their aggregate `C`.
+#[derive(Clone, Debug)]
+pub struct StreamedStatement {
+    /// The public scalars, zero-padded to `n_padded`.
+    pub input: Vec<Scalar>,
+    /// The number of ciphertexts to be streamed.
+    pub k_original: usize,
+    /// The aggregated original ciphertexts, as returned by
+    /// [`ShuffleStatement::aggregate`].
+    pub C: Vec<RistrettoPoint>,
+}
+
+/// The prover's side of a shuffle: the public `statement` and its
+/// witness.
+///
+/// For a shuffle with permutation `pi` and re-randomizers `r`, `output`
+/// holds `output[i] = input[pi[i]]` and `r_prime` is
+/// `-sum r_i * input[pi[i]]`.
+#[derive(Clone, Debug)]
+pub struct ShuffleWitness<S = ShuffleStatement> {
+    /// The statement being proved.
+    pub statement: S,
+    /// The shuffled scalars, zero-padded like `statement.input`.
+    pub output: Vec<Scalar>,
+    /// The combined re-randomizer of the consistency check.
+    pub r_prime: Scalar,
+    /// The blinding of the commitment to `output`, which must be
+    /// uniformly random and not reused for another vector.
+    pub v_blinding: Scalar,
+}
+
+impl<S> ShuffleWitness<S> {
+    /// Creates a witness whose output is committed under a fresh random
+    /// blinding.
+    ///
+    /// A caller that needs the output commitment ahead of the proof,
+    /// such as the input commitment of the next round of a mixnet, sets
+    /// `v_blinding` itself and publishes
+    /// [`ShuffleProof::commit_output`]`(output, v_blinding)`.
+    pub fn new(statement: S, output: Vec<Scalar>, r_prime: Scalar) -> Self {
+        ShuffleWitness {
+            statement,
+            output,
+            r_prime,
+            v_blinding: Scalar::random(&mut thread_rng()),
+        }
+    }
+}
+
+/// The prover's side of a shuffle known by its permutation, for the
+/// proofs that need the re-randomizers individually, such as
+/// [`ShuffleProof::prove_same_permutation`].
+///
+/// `permutation` and `r` are the `pi` and `r` of [`ShuffleStatement`],
+/// with one entry per ciphertext.
+#[derive(Clone, Debug)]
+pub struct PermutationWitness {
+    /// The statement being proved.
+    pub statement: ShuffleStatement,
+    /// The permutation `pi`, with `C1_prime[i] = C1[pi[i]] + r[i] * B`.
+    pub permutation: Vec<usize>,
+    /// The per-ciphertext re-randomizers.
+    pub r: Vec<Scalar>,
+}
+
+impl PermutationWitness {
+    /// Returns the [`ShuffleWitness`] of the same shuffle, with a fresh
+    /// random output blinding.
+    ///
+    /// Returns [`R1CSError::InvalidPermutation`] if `permutation` repeats
+    /// an index or points past the ciphertexts.
+    pub fn to_witness(&self) -> Result<ShuffleWitness, R1CSError> {
+        self.to_witness_with_blinding(Scalar::random(&mut thread_rng()))
+    }
+
+    /// Like [`PermutationWitness::to_witness`], with the output committed
+    /// under `v_blinding`.
+    fn to_witness_with_blinding(&self, v_blinding: Scalar) -> Result<ShuffleWitness, R1CSError> {
+        self.check()?;
+        let (output, r_prime) = ShuffleProof::permute(&self.statement.input, &self.permutation, &self.r);
+        Ok(ShuffleWitness {
+            statement: self.statement.clone(),
+            output,
+            r_prime,
+            v_blinding,
+        })
+    }
+
+    /// Checks the statement, and the permutation and re-randomizers
+    /// against its ciphertexts.
+    fn check(&self) -> Result<(), R1CSError> {
+        self.statement.check()?;
+        let k_original = self.statement.C1_prime.len();
+        if self.permutation.len() != k_original || self.r.len() != k_original {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        ShuffleProof::check_permutation(&self.permutation)
+    }
+}
+
+/// Two shuffle proofs bound to a single permutation by
+/// [`ShuffleProof::prove_same_permutation`].
+///
+/// Besides the proof of each shuffle, it carries a proof for the
+/// combination `a + lambda * b` of the two statements, where `lambda` is
+/// a transcript challenge drawn after both output commitments.  The
+/// combined output commitment is `V_a + lambda * V_b`, so it is a
+/// permutation of the combined input only if both outputs were permuted
+/// the same way.
+#[derive(Clone, Debug)]
+pub struct SamePermutationProof {
+    /// The proof for the first shuffle.
+    pub proof_a: R1CSProof,
+    /// The commitment to the first shuffle's output.
+    pub output_commitment_a: CompressedRistretto,
+    /// The proof for the second shuffle.
+    pub proof_b: R1CSProof,
+    /// The commitment to the second shuffle's output.
+    pub output_commitment_b: CompressedRistretto,
+    /// The proof for the combined shuffle.
+    pub proof_combined: R1CSProof,
+}
+
+/// The shuffles of several parties, each proved by
+/// [`ShuffleProof::prove_party`] over its own share of the generators.
+///
+/// Together they prove the combined statement made of every party's
+/// statement, in party order: party `i`'s ciphertexts are shuffled among
+/// themselves, and its proof is folded into a single check with the
+/// others' by [`ShuffleProof::verify_aggregated`].
+#[derive(Clone, Debug)]
+pub struct AggregatedShuffleProof {
+    /// The proof of party `i`'s shuffle, at index `i`.
+    pub proofs: Vec<R1CSProof>,
+    /// The commitment to party `i`'s output, at index `i`.
+    pub output_commitments: Vec<CompressedRistretto>,
+}
+
+/// One shuffle proof together with the statement and `nonce` it claims,
+/// as the verifiers take it.
+///
+/// The statement is a [`ShuffleStatement`] for every verifier except
+/// [`ShuffleProof::verify_components`] and
+/// [`ShuffleProof::verify_streaming`].
+#[derive(Clone, Debug)]
+pub struct ShuffleClaim<S = ShuffleStatement> {
+    /// The proof, as produced by [`ShuffleProof::prove`].
+    pub proof: R1CSProof,
+    /// The commitment to the shuffled output.
+    pub output_commitment: CompressedRistretto,
+    /// The statement the proof claims.
+    pub statement: S,
+    /// The nonce the proof was made under.
+    pub nonce: Vec<u8>,
+}
+
+/// The hops of a multi-hop mix, in order, each shuffling the ciphertexts
+/// the one before it output.
+///
+/// Hop `i + 1`'s original ciphertexts `(C1, C2)` must be exactly hop
+/// `i`'s shuffled ciphertexts `(C1_prime, C2_prime)`, so a verified chain
+/// proves the last hop's output is a re-randomized permutation of the
+/// first hop's input, without any single hop's permutation being known.
+#[derive(Clone, Debug)]
+pub struct ShuffleChain {
+    /// Each hop's proof and statement, as produced by
+    /// [`ShuffleProof::prove`] on a fresh transcript.
+    pub hops: Vec<ShuffleClaim>,
+}
+
+impl ShuffleChain {
+    /// Returns the original ciphertexts `(C1, C2)` entering the first hop,
+    /// or `None` for an empty chain.
+    pub fn original_ciphertexts(&self) -> Option<(&[RistrettoPoint], &[RistrettoPoint])> {
+        self.hops
+            .first()
+            .map(|hop| (&hop.statement.C1[..], &hop.statement.C2[..]))
+    }
+
+    /// Returns the shuffled ciphertexts `(C1_prime, C2_prime)` leaving the
+    /// last hop, or `None` for an empty chain.
+    pub fn final_ciphertexts(&self) -> Option<(&[RistrettoPoint], &[RistrettoPoint])> {
+        self.hops
+            .last()
+            .map(|hop| (&hop.statement.C1_prime[..], &hop.statement.C2_prime[..]))
+    }
+
+    /// Checks that consecutive hops link, then verifies each hop with
+    /// [`ShuffleProof::verify`] on a fresh transcript labelled `label`.
+    ///
+    /// A hop whose originals are not the previous hop's output is
+    /// reported as [`R1CSError::BrokenChainLink`] before any proof is
+    /// checked; an empty chain proves nothing and is rejected with
+    /// [`R1CSError::FormatError`].
+    pub fn verify_chain(
+        &self,
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        label: &'static [u8],
+    ) -> Result<(), R1CSError> {
+        if self.hops.is_empty() {
+            return Err(R1CSError::FormatError);
+        }
+        for (hop, pair) in self.hops.windows(2).enumerate() {
+            let (prev, next) = (&pair[0].statement, &pair[1].statement);
+            if next.C1 != prev.C1_prime || next.C2 != prev.C2_prime {
+                return Err(R1CSError::BrokenChainLink { hop: hop + 1 });
+            }
+        }
+        for claim in self.hops.iter() {
+            let mut transcript = Transcript::new(label);
+            ShuffleProof::verify(pc_gens, bp_gens, &mut transcript, claim)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shuffle proofs with the number of folding rounds fixed to `D` by the
+/// type, for protocols whose depth is known when they are compiled.
+///
+/// [`FixedDepthShuffle::prove`] and [`FixedDepthShuffle::verify`] are
+/// [`ShuffleProof::prove`] and [`ShuffleProof::verify`] without the
+/// `num_rounds` parameter, so a prover and verifier naming the same type
+/// cannot disagree on it; the verifier rejects a proof of any other
+/// depth with [`R1CSError::FormatError`] before replaying its
+/// transcript.
+pub struct FixedDepthShuffle<const D: usize>;
+
+impl<const D: usize> FixedDepthShuffle<D> {
+    /// The number of folding rounds of every proof of this type.
+    pub const ROUNDS: usize = D;
+
+    /// Like [`ShuffleProof::prove`], folding by `k_fold` over `D` rounds.
+    pub fn prove<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness,
+        k_fold: usize,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        let config = ProofConfig::new(witness.statement.input.len(), k_fold, D);
+        ShuffleProof::prove(pc_gens, bp_gens, transcript, nonce, witness, &config)
+    }
+
+    /// Like [`ShuffleProof::verify`], for a proof of exactly `D` rounds.
+    pub fn verify<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        let proof = &claim.proof;
+        if proof.ipp_proof.U_vecs.len() != D || proof.ecp_batched.A_vecs.len() != D {
+            return Err(R1CSError::FormatError);
+        }
+        ShuffleProof::verify(pc_gens, bp_gens, transcript, claim)
+    }
+}
+
+/// Accumulates the verification checks of shuffle proofs received over
+/// time into one multiscalar multiplication, run by
+/// [`BatchVerifierAccumulator::finalize`].
+///
+/// Each [`BatchVerifierAccumulator::add`] replays a proof's transcript,
+/// as [`ShuffleProof::verify`] would on a fresh transcript labelled
+/// `label`, and keeps its final-check terms scaled by an independent
+/// random weight, so one invalid proof cannot be cancelled by another.
+/// A failed `finalize` does not say which proof was invalid; a verifier
+/// that needs to know re-checks the batch one proof at a time.
+pub struct BatchVerifierAccumulator<'g> {
+    pc_gens: &'g PedersenGens,
+    bp_gens: &'g BulletproofGens,
+    label: &'static [u8],
+    scalars: Vec<Scalar>,
+    points: Vec<RistrettoPoint>,
+    pending: usize,
+}
+
+impl<'g> BatchVerifierAccumulator<'g> {
+    /// Creates an empty accumulator for proofs made on transcripts
+    /// labelled `label`.
+    pub fn new(
+        pc_gens: &'g PedersenGens,
+        bp_gens: &'g BulletproofGens,
+        label: &'static [u8],
+    ) -> Self {
+        BatchVerifierAccumulator {
+            pc_gens,
+            bp_gens,
+            label,
+            scalars: Vec::new(),
+            points: Vec::new(),
+            pending: 0,
+        }
+    }
+
+    /// Folds the check of `claim` into the batch.
+    ///
+    /// Errors that don't need the final multiscalar multiplication, such
+    /// as a malformed statement or a proof of the wrong size, are returned
+    /// here and leave the batch unchanged.
+    pub fn add(&mut self, claim: &ShuffleClaim) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        statement.check()?;
+        ShuffleProof::check_generators(self.bp_gens, &claim.proof)?;
+        let mut transcript = Transcript::new(self.label);
+        let cs = ShuffleProof::verifier_cs(
+            self.pc_gens, self.bp_gens, &mut transcript, &claim.nonce, claim.output_commitment,
+            statement, None,
+        )?;
+        let terms = cs.msm_terms(
+            &claim.proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate(),
+        )?;
+
+        let weight = Scalar::random(&mut thread_rng());
+        for (scalar, point) in terms {
+            self.scalars.push(weight * scalar);
+            self.points.push(point);
+        }
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Returns the number of proofs added since the last
+    /// [`BatchVerifierAccumulator::finalize`].
+    pub fn len(&self) -> usize {
+        self.pending
+    }
+
+    /// Returns `true` if no proof is waiting to be checked.
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Checks every proof added since the last call in one multiscalar
+    /// multiplication, then empties the batch for the next ones.
+    ///
+    /// Returns [`R1CSError::VerificationError`] if any of them is
+    /// invalid; an empty batch is accepted.
+    pub fn finalize(&mut self) -> Result<(), R1CSError> {
+        let scalars = mem::take(&mut self.scalars);
+        let points = mem::take(&mut self.points);
+        self.pending = 0;
+        if !RistrettoPoint::vartime_multiscalar_mul(scalars, points).is_identity() {
+            return Err(R1CSError::VerificationError);
+        }
+        Ok(())
+    }
+}
+
+impl ShuffleProof {
+    /// Adds the shuffle constraints for committed `x` against public `y`.
+    ///
+    /// Entries of `x` at positions `>= k_original` are constrained to zero.
+    pub fn gadget<CS: ConstraintSystem>(cs: &mut CS, x: &[Variable], y: &[Scalar], k_original: usize) {
+        let z = Self::shuffle_challenge(cs, y);
+        Self::gadget_with_challenge(cs, x, y, k_original, z);
+    }
+
+    /// Adds the shuffle constraints of [`ShuffleProof::gadget`] for the
+    /// given challenge `z`, which must not equal any entry of `y`.
+    fn gadget_with_challenge<CS: ConstraintSystem>(
+        cs: &mut CS,
+        x: &[Variable],
+        y: &[Scalar],
+        k_original: usize,
+        z: Scalar,
+    ) {
+        assert_eq!(x.len(), y.len());
+
+        let mut prod_y = Scalar::one();
+        for yi in y {
+            prod_y *= *yi - z;
+        }
+
+        let mut prev_lc = if k_original == 0 {
+            cs.constrain(x[0] - Scalar::zero());
+            LinearCombination::from(-z)
+        } else {
+            x[0] - z
+        };
+
+        for (i, x_i) in x.iter().enumerate().skip(1) {
+            if i >= k_original {
+                cs.constrain(*x_i - Scalar::zero());
+                prev_lc = prev_lc * (-z);
+            } else {
+                let term = *x_i - z;
+                let (_, _, out_var) = cs.multiply(prev_lc, term);
+                prev_lc = LinearCombination::from(out_var);
+            }
+        }
+
+        cs.constrain(prev_lc - prod_y);
+    }
+
+    /// Adds constraints that each of the first `k_original` entries of
+    /// committed `x` lies in `[0, 2^n_bits)`.
+    ///
+    /// Each entry is decomposed into `n_bits` multiplier bits `b_j`, with
+    /// `b_j * (1 - b_j) = 0` and `x_i = sum b_j * 2^j`.  `values` is the
+    /// prover's assignment of `x`, and `None` on the verifier side.  A value
+    /// out of range still gets its low `n_bits` bits assigned, so the
+    /// prover produces a proof that fails to verify.
+    pub fn range_gadget<CS: ConstraintSystem>(
+        cs: &mut CS,
+        x: &[Variable],
+        values: Option<&[Scalar]>,
+        k_original: usize,
+        n_bits: usize,
+    ) -> Result<(), R1CSError> {
+        for (i, x_i) in x.iter().enumerate().take(k_original) {
+            let mut sum = LinearCombination::from(*x_i);
+            let mut exp_2 = Scalar::one();
+            for j in 0..n_bits {
+                let (a, b, o) = cs.allocate(|| {
+                    let v = values.ok_or(R1CSError::MissingAssignment)?[i];
+                    let bit = Scalar::from(((v.as_bytes()[j / 8] >> (j % 8)) & 1) as u64);
+                    Ok((Scalar::one() - bit, bit, Scalar::zero()))
+                })?;
+                // a * b = 0 and a = 1 - b
+                cs.constrain(o.into());
+                cs.constrain(a + (b - Scalar::one()));
+
+                sum = sum - b * exp_2;
+                exp_2 = exp_2 + exp_2;
+            }
+            cs.constrain(sum);
+        }
+        Ok(())
+    }
+
+    /// Returns the smallest witness length that fits the multipliers of a
+    /// shuffle of `k_original` values with each output range-checked to
+    /// `n_bits` bits.
+    ///
+    /// The constraint system has no more multipliers than committed
+    /// values, so [`ShuffleProof::prove_in_range`] needs `input` and
+    /// `output` padded to at least this length rather than just
+    /// `k_original`.
+    pub fn range_witness_len(k_original: usize, n_bits: usize) -> usize {
+        k_original.saturating_sub(1) + k_original * n_bits
+    }
+
+    /// Rejects a bit width outside `1..=64` or a witness of length `k` too
+    /// short for the range constraints.
+    fn check_range(k: usize, k_original: usize, n_bits: usize) -> Result<(), R1CSError> {
+        if n_bits == 0 || n_bits > 64 || k < Self::range_witness_len(k_original, n_bits) {
+            return Err(R1CSError::InvalidRangeParameters);
+        }
+        Ok(())
+    }
+
+    /// Starts a range-checked shuffle proof's transcript, ahead of
+    /// [`ShuffleProof::append_statement`], so a proof with range
+    /// constraints never verifies as a plain shuffle proof or under a
+    /// different bit width.
+    fn append_range<T: ProofTranscript>(transcript: &mut T, n_bits: usize) {
+        transcript.append_message(b"dom-sep", b"ShuffleRangeProof");
+        transcript.commit_u64(b"n_bits", n_bits as u64);
+    }
+
+    /// Draws the shuffle challenge `z`, drawing again while it equals one of
+    /// the public inputs `y`.
+    ///
+    /// If `z == y_i`, the factor `y_i - z` makes `prod(y_i - z)` zero, and
+    /// the permutation check then only asks that some output equal `z`
+    /// too.  A uniformly random `z` hits an input with negligible
+    /// probability, but a protocol with low-entropy inputs should not rely
+    /// on that.  The prover and verifier both run this, so they re-squeeze
+    /// identically.
+    fn shuffle_challenge<CS: ConstraintSystem>(cs: &mut CS, y: &[Scalar]) -> Scalar {
+        let mut z = cs.challenge_scalar(b"k-scalar shuffle challenge");
+        while y.contains(&z) {
+            z = cs.challenge_scalar(b"k-scalar shuffle challenge retry");
+        }
+        z
+    }
+
+    /// Starts a shuffle proof's transcript: the domain separator, the
+    /// padded size `k`, and every public `input` scalar.
+    ///
+    /// The shuffle challenge `z` is drawn after this, so it is bound to the
+    /// public statement and a prover cannot pick `input` after seeing `z`.
+    /// Callers building the constraint system by hand (with
+    /// [`ShuffleProof::gadget`]) must call this first, exactly as
+    /// [`ShuffleProof::prove`] and [`ShuffleProof::verify`] do.
+    pub fn append_statement<T: ProofTranscript>(transcript: &mut T, input: &[Scalar]) {
+        transcript.append_message(b"dom-sep", b"ShuffleProof");
+        transcript.append_message(b"k", Scalar::from(input.len() as u64).as_bytes());
+        for x in input {
+            transcript.append_message(b"input", x.as_bytes());
+        }
+    }
+
+    /// Binds a shuffle proof's transcript to `nonce`, right after
+    /// [`ShuffleProof::append_statement`].
+    ///
+    /// [`ShuffleProof::prove`] and [`ShuffleProof::verify`] call this with
+    /// their `nonce` argument; callers building the constraint system by
+    /// hand must call it too, with an empty `nonce` if they have none.
+    pub fn append_nonce<T: ProofTranscript>(transcript: &mut T, nonce: &[u8]) {
+        transcript.append_message(b"nonce", nonce);
+    }
+
+    /// Seeds a transcript with the output commitment of the previous
+    /// shuffle in a chain, such as the previous server's proof in a mixnet.
+    ///
+    /// Both the prover and the verifier call this on a fresh transcript
+    /// before [`ShuffleProof::prove`] or [`ShuffleProof::verify`]; every
+    /// challenge of the proof then depends on `previous_output`, so the
+    /// proof verifies only as the successor of that commitment.
+    pub fn link_previous<T: ProofTranscript>(transcript: &mut T, previous_output: &CompressedRistretto) {
+        transcript.append_message(b"dom-sep", b"ShuffleChain");
+        transcript.commit_point(b"previous output", previous_output);
+    }
+
+    /// Zero-pads the `input` and `output` witness to `n_padded`, after
+    /// checking that the ciphertexts themselves were left unpadded, that
+    /// is, that they are as long as the unpadded `input`.
+    ///
+    /// Ciphertexts must stay at `k_original` entries.  Padding
+    /// `C1_prime`/`C2_prime` out to `n_padded`, for instance with identity
+    /// points, would still prove and verify, but as a shuffle of
+    /// `n_padded` ciphertexts whose padding slots are part of the
+    /// statement.  Every prover and verifier therefore checks the shuffled
+    /// ciphertexts against the `k_original` original ones and rejects
+    /// padded ones with [`R1CSError::InvalidCiphertextLength`].
+    pub fn pad_ciphertexts(
+        C1_prime: &[RistrettoPoint],
+        C2_prime: &[RistrettoPoint],
+        input: &[Scalar],
+        output: &[Scalar],
+        n_padded: usize,
+    ) -> Result<(Vec<Scalar>, Vec<Scalar>), R1CSError> {
+        if output.len() != input.len() {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        Self::check_ciphertexts(C1_prime, C2_prime, input.len(), n_padded)?;
+
+        let mut input = input.to_vec();
+        let mut output = output.to_vec();
+        input.resize(n_padded, Scalar::zero());
+        output.resize(n_padded, Scalar::zero());
+        Ok((input, output))
+    }
+
+    /// Rejects ciphertext vectors that do not hold exactly the
+    /// `k_original` ciphertexts of the unpadded statement, such as ones
+    /// padded out to the witness length, or more than the `n_padded`
+    /// witness covers.
+    ///
+    /// Only lengths are checked: an identity component is a valid
+    /// ciphertext, such as an encryption of 0 with `r = 0`, so it cannot
+    /// be told apart from padding.
+    fn check_ciphertexts(
+        C1_prime: &[RistrettoPoint],
+        C2_prime: &[RistrettoPoint],
+        k_original: usize,
+        n_padded: usize,
+    ) -> Result<(), R1CSError> {
+        if C1_prime.len() != k_original || C2_prime.len() != k_original || k_original > n_padded {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        Ok(())
+    }
+
+    /// Attempt to construct a proof that `witness.output` is a permutation
+    /// of the statement's `input` consistent with the re-encrypted
+    /// ciphertexts `C1_prime`, `C2_prime`.
+    ///
+    /// `input` and `output` must already be padded to `config.n_padded`,
+    /// which must be at least 2; a single ciphertext is shuffled with its
+    /// witness padded to `[x, 0]`, and shorter witnesses are rejected with
+    /// [`R1CSError::InputLengthError`].  The proof is folded by `config.k`
+    /// over `config.d` rounds.
+    /// `nonce` is committed to the transcript right after the statement,
+    /// so the proof verifies only under the same `nonce`; a verifier that
+    /// accepts each nonce once cannot be replayed an old proof of the same
+    /// statement.
+    /// The identity permutation without re-randomization (`r_prime = 0`,
+    /// `C1_prime = C1`) is a valid statement, useful for conformance tests.
+    /// Returns the proof and the commitment to `output`.
+    pub fn prove<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        Self::check_witness(bp_gens, witness, config)?;
+        let prover = Self::start_prover(pc_gens, bp_gens, transcript, nonce, &witness.statement.input);
+        Self::prove_shuffle(prover, witness, config)
+    }
+
+    /// Like [`ShuffleProof::prove`], also returning the size in bytes of
+    /// the proof's [`R1CSProof::to_bytes`] encoding, for callers that log
+    /// it without serializing the proof.
+    pub fn prove_with_size<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto, usize), R1CSError> {
+        let (proof, output_commitment) = Self::prove(pc_gens, bp_gens, transcript, nonce, witness, config)?;
+        let size = proof.serialized_size();
+        Ok((proof, output_commitment, size))
+    }
+
+    /// Rejects a statement whose lengths disagree with each other or with
+    /// `config`, or that `bp_gens` has too few generators for.
+    fn check_statement(
+        bp_gens: &BulletproofGens,
+        statement: &ShuffleStatement,
+        config: &ProofConfig,
+    ) -> Result<(), R1CSError> {
+        let k = statement.input.len();
+        if k <= 1 || k != config.n_padded {
+            return Err(R1CSError::InputLengthError);
+        }
+        statement.check()?;
+        if bp_gens.gens_capacity < k {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+        Ok(())
+    }
+
+    /// Like [`ShuffleProof::check_statement`], also checking that the
+    /// output is padded like the input.
+    fn check_witness(
+        bp_gens: &BulletproofGens,
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(), R1CSError> {
+        if witness.output.len() != witness.statement.input.len() {
+            return Err(R1CSError::InputLengthError);
+        }
+        Self::check_statement(bp_gens, &witness.statement, config)
+    }
+
+    /// Starts the transcript of a shuffle of `input` under `nonce`, and
+    /// returns the prover on it, on which a re-encryption key or party can
+    /// still be set.
+    fn start_prover<'a, 'b, T: ProofTranscript>(
+        pc_gens: &'b PedersenGens,
+        bp_gens: &'b BulletproofGens,
+        transcript: &'a mut T,
+        nonce: &[u8],
+        input: &[Scalar],
+    ) -> Prover<'a, 'b, T> {
+        Self::append_statement(transcript, input);
+        Self::append_nonce(transcript, nonce);
+        Prover::new(bp_gens, pc_gens, transcript)
+    }
+
+    /// Commits to `witness.output` on `prover` and proves the shuffle
+    /// constraints along with the consistency of the ciphertexts.
+    fn prove_shuffle<T: ProofTranscript>(
+        mut prover: Prover<T>,
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        let statement = &witness.statement;
+        let k_original = statement.C1_prime.len();
+        let (output_commitment, output_vars) =
+            prover.commit_vec(&witness.output, witness.v_blinding, k_original);
+        let mut cs = prover.finalize_inputs();
+        Self::gadget(&mut cs, &output_vars, &statement.input, k_original);
+        let proof = cs.prove(
+            &statement.C1_prime, &statement.C2_prime, witness.r_prime, config.k, config.d,
+        )?;
+        Ok((proof, output_commitment))
+    }
+
+    /// Commits to the padded `output` vector with `blinding`, with the same
+    /// generators as a shuffle's output commitment.
+    ///
+    /// This is the output commitment of a shuffle proof whose witness has
+    /// the same `output` and `v_blinding`; see [`ShuffleWitness::new`].
+    pub fn commit_output(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        output: &[Scalar],
+        blinding: Scalar,
+    ) -> Result<CompressedRistretto, R1CSError> {
+        if bp_gens.gens_capacity < output.len() {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+        let scalars = iter::once(&blinding).chain(output.iter());
+        let points = iter::once(&pc_gens.B_blinding).chain(bp_gens.G(output.len(), 1));
+        Ok(RistrettoPoint::multiscalar_mul(scalars, points).compress())
+    }
+
+    /// Proves that a public shuffled order is a permutation of a committed
+    /// original order, the mirror image of [`ShuffleProof::prove`]: the
+    /// shuffled order is revealed and the original order stays hidden.
+    ///
+    /// The statement is mirrored too.  For a shuffle with
+    /// `C1_prime[i] = C1[pi[i]] + r_i * B` and
+    /// `C2_prime[i] = C2[pi[i]] + r_i * B_blinding` as in
+    /// [`ShuffleStatement`], `witness.statement` holds the public output
+    /// as `input`, the shuffled ciphertexts as `C1`, `C2`, and the
+    /// original ciphertexts, which must stay at `k_original` entries, as
+    /// `C1_prime`, `C2_prime`.  The verifier thus aggregates the shuffled
+    /// ciphertexts by the public output.  `witness.output` is the
+    /// committed input, and `r_prime` is `sum r_i * public_output_i`, with
+    /// the opposite sign of `r_prime` in [`ShuffleProof::prove`].
+    ///
+    /// Returns the proof and the commitment to the input.
+    pub fn prove_committed_input<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        Self::check_witness(bp_gens, witness, config)?;
+        Self::append_committed_input(transcript);
+        let prover = Self::start_prover(pc_gens, bp_gens, transcript, nonce, &witness.statement.input);
+        Self::prove_shuffle(prover, witness, config)
+    }
+
+    /// Separates committed-input proofs from [`ShuffleProof::prove`]'s, so
+    /// one can't be passed off as the other.
+    fn append_committed_input<T: ProofTranscript>(transcript: &mut T) {
+        transcript.append_message(b"dom-sep", b"ShuffleCommittedInput");
+    }
+
+    /// Folds the shuffled components after the first into the single
+    /// second slot the proof has room for, returning that slot's
+    /// ciphertexts, key and aggregate, given the aggregates `C` of every
+    /// component.
+    ///
+    /// Component `j >= 1` is weighted by `rho^(j-1)`, for a challenge
+    /// `rho` drawn after every component, key, aggregate and the output
+    /// commitment are committed to the transcript.  The first component
+    /// keeps its own slot under `B`, which pins down `r_prime`; a single
+    /// component is repeated in the second slot under `B`.  The statement
+    /// must already have been checked.
+    fn fold_components<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        transcript: &mut T,
+        statement: &ComponentStatement,
+        C: &[RistrettoPoint],
+        output_commitment: &CompressedRistretto,
+    ) -> (Vec<RistrettoPoint>, RistrettoPoint, RistrettoPoint) {
+        let components = &statement.shuffled;
+        let m = components.len();
+
+        transcript.append_message(b"dom-sep", b"ShuffleComponents");
+        transcript.append_message(b"m", Scalar::from(m as u64).as_bytes());
+        for component in components {
+            for point in component.iter() {
+                transcript.commit_point(b"component", &point.compress());
+            }
+        }
+        for key in statement.keys.iter() {
+            transcript.commit_point(b"key", &key.compress());
+        }
+        for C_j in C {
+            transcript.commit_point(b"C", &C_j.compress());
+        }
+        transcript.commit_point(b"output commitment", output_commitment);
+
+        if m == 1 {
+            return (components[0].clone(), pc_gens.B, C[0]);
+        }
+        let rho = transcript.challenge_scalar(b"component fold");
+        let weights: Vec<Scalar> = util::exp_iter(rho).take(m - 1).collect();
+        let folded = (0..components[0].len())
+            .map(|i| RistrettoPoint::multiscalar_mul(&weights, components[1..].iter().map(|c| c[i])))
+            .collect();
+        let key = RistrettoPoint::multiscalar_mul(&weights, &statement.keys);
+        let C_folded = RistrettoPoint::multiscalar_mul(&weights, &C[1..]);
+        (folded, key, C_folded)
+    }
+
+    /// Like [`ShuffleProof::prove`], for ciphertexts whose second
+    /// components were re-randomized under the public `key` instead of
+    /// `B_blinding`: `C2_prime[i] = C2[pi[i]] + r[i] * key`.
+    ///
+    /// One proof covers one key.  Ciphertexts under different keys cannot
+    /// be mixed with each other, since re-encryption keeps a ciphertext
+    /// under its key, so a mixnet holding several keys shuffles each key's
+    /// ciphertexts separately and proves each group with that group's key.
+    pub fn prove_with_key<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        key: RistrettoPoint,
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        Self::check_witness(bp_gens, witness, config)?;
+        let mut prover = Self::start_prover(pc_gens, bp_gens, transcript, nonce, &witness.statement.input);
+        prover.set_reencryption_key(key);
+        Self::prove_shuffle(prover, witness, config)
+    }
+
+    /// Like [`ShuffleProof::prove_with_key`], for ciphertexts with any
+    /// number `m >= 1` of components instead of an ElGamal pair, as
+    /// described by [`ComponentStatement`].  `r_prime` is as in
+    /// [`ShuffleProof::prove`], over the first component.
+    ///
+    /// The proof has room for two components.  The components after the
+    /// first are combined with powers of a transcript challenge `rho`,
+    /// which is drawn once the whole statement and the output commitment
+    /// are in the transcript, and the consistency proof then checks
+    /// `shuffled[0] + c * (sum rho^(j-1) * shuffled[j])` in place of
+    /// `C1_prime + c * C2_prime`.  A proof for `m = 2` therefore differs
+    /// from a [`ShuffleProof::prove_with_key`] proof of the same shuffle,
+    /// and verifies only with [`ShuffleProof::verify_components`].
+    pub fn prove_components<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness<ComponentStatement>,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        let statement = &witness.statement;
+        let k = statement.input.len();
+        if k <= 1 || k != config.n_padded || witness.output.len() != k {
+            return Err(R1CSError::InputLengthError);
+        }
+        statement.check()?;
+
+        // The prover's aggregates, equal to the verifier's `C` for an
+        // honest shuffle.
+        let C: Vec<RistrettoPoint> = statement
+            .shuffled
+            .iter()
+            .zip(iter::once(&pc_gens.B).chain(statement.keys.iter()))
+            .map(|(component, key)| {
+                RistrettoPoint::multiscalar_mul(
+                    iter::once(&witness.r_prime).chain(witness.output.iter().take(component.len())),
+                    iter::once(key).chain(component.iter()),
+                )
+            })
+            .collect();
+
+        let output_commitment = Self::commit_output(pc_gens, bp_gens, &witness.output, witness.v_blinding)?;
+        let (folded, key, _) = Self::fold_components(pc_gens, transcript, statement, &C, &output_commitment);
+
+        let k_original = statement.shuffled[0].len();
+        let mut prover = Self::start_prover(pc_gens, bp_gens, transcript, nonce, &statement.input);
+        prover.set_reencryption_key(key);
+        let (output_commitment, output_vars) =
+            prover.commit_vec(&witness.output, witness.v_blinding, k_original);
+        let mut cs = prover.finalize_inputs();
+        Self::gadget(&mut cs, &output_vars, &statement.input, k_original);
+        let proof = cs.prove(&statement.shuffled[0], &folded, witness.r_prime, config.k, config.d)?;
+        Ok((proof, output_commitment))
+    }
+
+    /// Proves a shuffle followed by adding a public `offset`: `output` is a
+    /// permutation of `input[i] + offset[i]`, so that
+    /// `output[j] = input[pi[j]] + offset[pi[j]]` for the permutation `pi`
+    /// of the ciphertexts.
+    ///
+    /// `offset` has one entry per ciphertext, `k_original` of them, and the
+    /// padded tail of the witness is left unshifted.  The offset is
+    /// committed to the transcript ahead of the statement, so the proof
+    /// verifies only with [`ShuffleProof::verify_with_offset`] under the
+    /// same `offset`.  The statement's `input` holds the unshifted values,
+    /// while `r_prime` is taken over the shifted ones,
+    /// `r_prime = -sum r_i * (input + offset)[pi[i]]`.
+    pub fn prove_with_offset<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness,
+        offset: &[Scalar],
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        let statement = &witness.statement;
+        let shifted = Self::apply_offset(transcript, &statement.input, offset, statement.C1_prime.len())?;
+        let mut witness = witness.clone();
+        witness.statement.input = shifted;
+        Self::prove(pc_gens, bp_gens, transcript, nonce, &witness, config)
+    }
+
+    /// Checks that `offset` has an entry for each of the `k_original`
+    /// ciphertexts, commits it to the transcript under its own domain
+    /// separator, and returns `input` with it added.
+    fn apply_offset<T: ProofTranscript>(
+        transcript: &mut T,
+        input: &[Scalar],
+        offset: &[Scalar],
+        k_original: usize,
+    ) -> Result<Vec<Scalar>, R1CSError> {
+        if offset.len() != k_original || k_original > input.len() {
+            return Err(R1CSError::InputLengthError);
+        }
+        transcript.append_message(b"dom-sep", b"ShuffleOffset");
+        for o in offset {
+            transcript.append_message(b"offset", o.as_bytes());
+        }
+        let mut shifted = input.to_vec();
+        for (x, o) in shifted.iter_mut().zip(offset.iter()) {
+            *x += o;
+        }
+        Ok(shifted)
+    }
+
+    /// Like [`ShuffleProof::prove`], with the shuffle challenge `z` taken
+    /// from `z_source` instead of this proof's transcript, for composing
+    /// the shuffle inside a larger protocol that derives its challenges
+    /// from its own commitments.
+    ///
+    /// The permutation check is only sound if `z` is unpredictable to the
+    /// prover until it is bound to the statement: the outer protocol must
+    /// derive it after committing `input`, the ciphertexts and the output
+    /// commitment, which the caller publishes there as
+    /// [`ShuffleProof::commit_output`]`(output, v_blinding)` of the
+    /// witness.  A `z` known beforehand lets a prover pass off a
+    /// non-permutation whose product `prod(y_i - z)` happens to match.
+    /// `z` must not equal an entry of `input`, or
+    /// [`R1CSError::InvalidExternalChallenge`] is returned.
+    ///
+    /// `z` is committed to the transcript ahead of the proof's other
+    /// challenges, which are still drawn internally.  The proof verifies
+    /// only with [`ShuffleProof::verify_with_external_challenge`] under the
+    /// same `z`.
+    pub fn prove_with_external_challenge<T: ProofTranscript, F: Fn() -> Scalar>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        z_source: F,
+        witness: &ShuffleWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        Self::check_witness(bp_gens, witness, config)?;
+        let statement = &witness.statement;
+        let k_original = statement.C1_prime.len();
+
+        let z = z_source();
+        Self::append_statement(transcript, &statement.input);
+        Self::append_nonce(transcript, nonce);
+        Self::append_external_challenge(transcript, &statement.input, &z)?;
+
+        let mut prover = Prover::new(bp_gens, pc_gens, transcript);
+        let (output_commitment, output_vars) =
+            prover.commit_vec(&witness.output, witness.v_blinding, k_original);
+        let mut cs = prover.finalize_inputs();
+        Self::gadget_with_challenge(&mut cs, &output_vars, &statement.input, k_original, z);
+        let proof = cs.prove(
+            &statement.C1_prime, &statement.C2_prime, witness.r_prime, config.k, config.d,
+        )?;
+        Ok((proof, output_commitment))
+    }
+
+    /// Rejects an external shuffle challenge equal to one of the public
+    /// `input` scalars, and commits it to the transcript under its own
+    /// domain separator, so the proof never verifies as a plain shuffle
+    /// proof or under another `z`.
+    fn append_external_challenge<T: ProofTranscript>(
+        transcript: &mut T,
+        input: &[Scalar],
+        z: &Scalar,
+    ) -> Result<(), R1CSError> {
+        if input.contains(z) {
+            return Err(R1CSError::InvalidExternalChallenge);
+        }
+        transcript.append_message(b"dom-sep", b"ShuffleExternalChallenge");
+        transcript.commit_scalar(b"z", z);
+        Ok(())
+    }
+
+    /// Like [`ShuffleProof::prove`], with the sizes taken from `params`:
+    /// the statement's `input` and the witness's `output` are the raw
+    /// `n_raw` values, padded here to `params.n_padded()`, and the proof
+    /// is folded by `params.k()` over `params.d()` rounds.
+    pub fn prove_with_params<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        params: &ShuffleParams,
+        witness: &ShuffleWitness,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        let statement = &witness.statement;
+        if statement.C1_prime.len() != params.n_raw() {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        if bp_gens.gens_capacity < params.gens_capacity() {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+        let (input, output) = Self::pad_ciphertexts(
+            &statement.C1_prime, &statement.C2_prime, &statement.input, &witness.output,
+            params.n_padded(),
+        )?;
+        let witness = ShuffleWitness {
+            statement: ShuffleStatement { input, ..statement.clone() },
+            output,
+            ..*witness
+        };
+        Self::prove(pc_gens, bp_gens, transcript, nonce, &witness, &params.config())
+    }
+
+    /// Proves that the shuffles `a` and `b` used the same permutation.
+    ///
+    /// Unlike [`ShuffleProof::prove`], the prover needs each shuffle's
+    /// re-randomizers individually, to re-randomize the combined
+    /// statement.  Returns [`R1CSError::InvalidPermutation`] if `a` and
+    /// `b` have different permutations.
+    pub fn prove_same_permutation<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        a: &PermutationWitness,
+        b: &PermutationWitness,
+        config: &ProofConfig,
+    ) -> Result<SamePermutationProof, R1CSError> {
+        if a.permutation != b.permutation {
+            return Err(R1CSError::InvalidPermutation);
+        }
+        let mut rng = thread_rng();
+        let v_blindings = (Scalar::random(&mut rng), Scalar::random(&mut rng));
+        Self::prove_same_permutation_with_blindings(pc_gens, bp_gens, transcript, a, b, v_blindings, config)
+    }
+
+    /// Like [`ShuffleProof::prove_same_permutation`], with the caller
+    /// choosing the blindings of both output commitments.  The
+    /// permutations must already have been compared.
+    fn prove_same_permutation_with_blindings<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        a: &PermutationWitness,
+        b: &PermutationWitness,
+        v_blindings: (Scalar, Scalar),
+        config: &ProofConfig,
+    ) -> Result<SamePermutationProof, R1CSError> {
+        let (v_blinding_a, v_blinding_b) = v_blindings;
+        let witness_a = a.to_witness_with_blinding(v_blinding_a)?;
+        let witness_b = b.to_witness_with_blinding(v_blinding_b)?;
+        Self::check_witness(bp_gens, &witness_a, config)?;
+        Self::check_witness(bp_gens, &witness_b, config)?;
+
+        // The sub-proofs take the empty nonce; a caller needing replay
+        // protection appends its own nonce to the transcript beforehand.
+        transcript.append_message(b"dom-sep", b"SamePermutationProof");
+
+        let (proof_a, output_commitment_a) =
+            Self::prove(pc_gens, bp_gens, transcript, &[], &witness_a, config)?;
+        let (proof_b, output_commitment_b) =
+            Self::prove(pc_gens, bp_gens, transcript, &[], &witness_b, config)?;
+
+        let lambda = transcript.challenge_scalar(b"same-permutation lambda");
+        let combined = PermutationWitness {
+            statement: a.statement.combine(&b.statement, lambda),
+            permutation: a.permutation.clone(),
+            r: a.r.iter().zip(b.r.iter()).map(|(x, y)| x + lambda * y).collect(),
+        };
+        let witness_combined = combined.to_witness_with_blinding(v_blinding_a + lambda * v_blinding_b)?;
+        let (proof_combined, _) =
+            Self::prove(pc_gens, bp_gens, transcript, &[], &witness_combined, config)?;
+
+        Ok(SamePermutationProof {
+            proof_a,
+            output_commitment_a,
+            proof_b,
+            output_commitment_b,
+            proof_combined,
+        })
+    }
+
+    /// Proves party `party`'s contribution to an aggregated shuffle: that
+    /// `witness.statement` was shuffled under `witness.permutation`, using
+    /// `bp_gens.share(party)`.
+    ///
+    /// The parties prove in order `0, 1, ...` on one transcript, each
+    /// continuing from the state the previous party left it in (for
+    /// instance through a [`TranscriptState`](::TranscriptState)), and
+    /// their proofs and output commitments are collected into an
+    /// [`AggregatedShuffleProof`].  `bp_gens` must have a `party_capacity`
+    /// above `party`.  As in [`ShuffleProof::prove_same_permutation`], a
+    /// caller needing replay protection appends its own nonce first.
+    pub fn prove_party<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        party: usize,
+        witness: &PermutationWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        let witness = witness.to_witness()?;
+        Self::check_witness(bp_gens, &witness, config)?;
+        if party >= bp_gens.party_capacity {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+
+        Self::append_party(transcript);
+        let mut prover = Self::start_prover(pc_gens, bp_gens, transcript, &[], &witness.statement.input);
+        prover.set_party(party);
+        Self::prove_shuffle(prover, &witness, config)
+    }
+
+    /// Separates the parties' contributions to an aggregated shuffle from
+    /// standalone shuffle proofs.
+    fn append_party<T: ProofTranscript>(transcript: &mut T) {
+        transcript.append_message(b"dom-sep", b"AggregatedShuffleParty");
+    }
+
+    /// Commits to `permutation` as the vector of scalars
+    /// `(permutation[0], ..., permutation[k_original - 1])`, zero-padded
+    /// to `n_padded`, with the same generators as a shuffle's output
+    /// commitment.
+    ///
+    /// This is the commitment that
+    /// [`ShuffleProof::verify_committed_permutation`] checks a shuffle
+    /// against; `blinding` must be passed back to
+    /// [`ShuffleProof::prove_committed_permutation`].
+    pub fn commit_permutation(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        permutation: &[usize],
+        n_padded: usize,
+        blinding: Scalar,
+    ) -> Result<CompressedRistretto, R1CSError> {
+        if n_padded < permutation.len() {
+            return Err(R1CSError::InputLengthError);
+        }
+        if bp_gens.gens_capacity < n_padded {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+        Self::check_permutation(permutation)?;
+
+        let mut indices: Vec<Scalar> = permutation.iter().map(|&j| Scalar::from(j as u64)).collect();
+        indices.resize(n_padded, Scalar::zero());
+        let scalars = iter::once(&blinding).chain(indices.iter());
+        let points = iter::once(&pc_gens.B_blinding).chain(bp_gens.G(n_padded, 1));
+        Ok(RistrettoPoint::multiscalar_mul(scalars, points).compress())
+    }
+
+    /// Returns the statement shuffling the index vector
+    /// `(0, 1, ..., k_original - 1)` with the same ciphertexts as
+    /// `statement`.
+    ///
+    /// Its output is the permutation itself, so its output commitment is
+    /// the one built by [`ShuffleProof::commit_permutation`].
+    fn index_statement(statement: &ShuffleStatement) -> ShuffleStatement {
+        let mut input: Vec<Scalar> = (0..statement.C1_prime.len())
+            .map(|j| Scalar::from(j as u64))
+            .collect();
+        input.resize(statement.input.len(), Scalar::zero());
+        ShuffleStatement { input, ..statement.clone() }
+    }
+
+    /// Proves the shuffle `witness` under a permutation that was committed
+    /// to ahead of time with [`ShuffleProof::commit_permutation`] and
+    /// `permutation_blinding`.
+    ///
+    /// This is a [`ShuffleProof::prove_same_permutation`] between the
+    /// shuffle and the shuffle of the index vector over the same
+    /// ciphertexts, whose output commitment is the permutation commitment.
+    pub fn prove_committed_permutation<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        witness: &PermutationWitness,
+        permutation_blinding: Scalar,
+        config: &ProofConfig,
+    ) -> Result<SamePermutationProof, R1CSError> {
+        let n_padded = witness.statement.input.len();
+        if n_padded <= 1 {
+            return Err(R1CSError::InputLengthError);
+        }
+        witness.check()?;
+        let permutation_commitment = Self::commit_permutation(
+            pc_gens, bp_gens, &witness.permutation, n_padded, permutation_blinding,
+        )?;
+
+        transcript.append_message(b"dom-sep", b"CommittedPermutationProof");
+        transcript.commit_point(b"permutation commitment", &permutation_commitment);
+
+        let index = PermutationWitness {
+            statement: Self::index_statement(&witness.statement),
+            permutation: witness.permutation.clone(),
+            r: witness.r.clone(),
+        };
+        let v_blindings = (Scalar::random(&mut thread_rng()), permutation_blinding);
+        Self::prove_same_permutation_with_blindings(
+            pc_gens, bp_gens, transcript, witness, &index, v_blindings, config,
+        )
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_committed_permutation`] against the
+    /// `permutation_commitment` published beforehand.
+    pub fn verify_committed_permutation<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        proof: &SamePermutationProof,
+        statement: &ShuffleStatement,
+        permutation_commitment: CompressedRistretto,
+    ) -> Result<(), R1CSError> {
+        if proof.output_commitment_b != permutation_commitment {
+            return Err(R1CSError::VerificationError);
+        }
+
+        transcript.append_message(b"dom-sep", b"CommittedPermutationProof");
+        transcript.commit_point(b"permutation commitment", &permutation_commitment);
+
+        let index = Self::index_statement(statement);
+        Self::verify_same_permutation(pc_gens, bp_gens, transcript, proof, statement, &index)
+    }
+
+    /// Attempt to verify a proof produced by [`ShuffleProof::prove`]
+    /// under the claim's `nonce`.
+    ///
+    /// The shuffled ciphertexts are checked against the original ones
+    /// aggregated by the public input, as [`ShuffleStatement::aggregate`]
+    /// returns them.
+    ///
+    /// A proof whose fold schedule implies more witness entries than
+    /// `bp_gens` has generators for is rejected with
+    /// [`R1CSError::InvalidGeneratorsLength`] before `transcript` is
+    /// touched.
+    pub fn verify<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        Self::verify_statement(
+            pc_gens, bp_gens, transcript, &claim.nonce, &claim.proof, claim.output_commitment,
+            &claim.statement,
+        )
+    }
+
+    /// Verifies `proof` of `statement` as [`ShuffleProof::verify`] does,
+    /// for the verifiers that derive the statement from the claim's.
+    fn verify_statement<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        proof: &R1CSProof,
+        output_commitment: CompressedRistretto,
+        statement: &ShuffleStatement,
+    ) -> Result<(), R1CSError> {
+        Self::check_generators(bp_gens, proof)?;
+        let cs = Self::verifier_cs(pc_gens, bp_gens, transcript, nonce, output_commitment, statement, None)?;
+        cs.verify(proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate())
+    }
+
+    /// Like [`ShuffleProof::verify`], with the `G` and `H` generators taken
+    /// from the precomputed `table`, which must be built from `bp_gens`
+    /// with a capacity of at least the padded input length.
+    ///
+    /// A verifier checking many proofs against the same generators builds
+    /// the table once with [`PrecomputedGens::new`] and reuses it; the
+    /// result is always the same as [`ShuffleProof::verify`]'s.
+    pub fn verify_precomputed<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        table: &PrecomputedGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        Self::check_generators(bp_gens, &claim.proof)?;
+        let cs = Self::verifier_cs(
+            pc_gens, bp_gens, transcript, &claim.nonce, claim.output_commitment, statement, None,
+        )?;
+        cs.verify_precomputed(
+            table, &claim.proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate(),
+        )
+    }
+
+    /// Like [`ShuffleProof::verify`], but leaves the terms with a zero
+    /// scalar out of the final multiscalar multiplication; the result is
+    /// always the same as [`ShuffleProof::verify`]'s.
+    ///
+    /// This does not drop the padded tail of the witness: the
+    /// inner-product proof gives every `G_i` and `H_i` up to the padded
+    /// size a nonzero scalar, so only terms that actually cancel are
+    /// skipped.
+    pub fn verify_sparse<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        Self::check_generators(bp_gens, &claim.proof)?;
+        let cs = Self::verifier_cs(
+            pc_gens, bp_gens, transcript, &claim.nonce, claim.output_commitment, statement, None,
+        )?;
+        cs.verify_sparse(&claim.proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate())
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_with_offset`] under the same `nonce` and
+    /// `offset`.
+    ///
+    /// The claim's statement holds the unshifted `input`; the original
+    /// ciphertexts are aggregated by the shifted values
+    /// `input[i] + offset[i]`.
+    pub fn verify_with_offset<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+        offset: &[Scalar],
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        let shifted = Self::apply_offset(transcript, &statement.input, offset, statement.C1_prime.len())?;
+        let statement = ShuffleStatement { input: shifted, ..statement.clone() };
+        Self::verify_statement(
+            pc_gens, bp_gens, transcript, &claim.nonce, &claim.proof, claim.output_commitment,
+            &statement,
+        )
+    }
+
+    /// Like [`ShuffleProof::verify`], with the `k_original` shuffled
+    /// ciphertexts streamed as `(C1_prime[i], C2_prime[i])` pairs from
+    /// `ciphertexts` instead of held in slices; see
+    /// [`VerifierCS::verify_streaming`].
+    ///
+    /// The result is the same as [`ShuffleProof::verify`]'s on the
+    /// collected ciphertexts.
+    pub fn verify_streaming<T: ProofTranscript, I>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim<StreamedStatement>,
+        ciphertexts: I,
+    ) -> Result<(), R1CSError>
+    where
+        I: IntoIterator<Item = (RistrettoPoint, RistrettoPoint)>,
+    {
+        let statement = &claim.statement;
+        Self::check_generators(bp_gens, &claim.proof)?;
+        if statement.k_original > statement.input.len() {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        let verifier = Self::start_verifier(pc_gens, bp_gens, transcript, &claim.nonce, &statement.input);
+        let cs = Self::shuffle_cs(verifier, &statement.input, claim.output_commitment, statement.k_original);
+        cs.verify_streaming(&claim.proof, statement.k_original, ciphertexts, &statement.C)
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_with_external_challenge`] under the same
+    /// `nonce` and shuffle challenge `z`.
+    ///
+    /// The verifier derives `z` itself, from the outer protocol's
+    /// transcript after the statement and `output_commitment` were
+    /// committed there; a `z` supplied by the prover proves nothing.
+    pub fn verify_with_external_challenge<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        z: Scalar,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        Self::check_generators(bp_gens, &claim.proof)?;
+        statement.check()?;
+
+        Self::append_statement(transcript, &statement.input);
+        Self::append_nonce(transcript, &claim.nonce);
+        Self::append_external_challenge(transcript, &statement.input, &z)?;
+
+        let mut verifier = Verifier::new(bp_gens, pc_gens, transcript);
+        let output_vars = verifier.commit_vec(claim.output_commitment, statement.input.len());
+        let mut cs = verifier.finalize_inputs();
+        Self::gadget_with_challenge(&mut cs, &output_vars, &statement.input, statement.C1_prime.len(), z);
+        cs.verify(&claim.proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate())
+    }
+
+    /// Attempt to verify a proof produced by [`ShuffleProof::prove_with_key`]
+    /// under the same `nonce` and `key`.
+    pub fn verify_with_key<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        key: RistrettoPoint,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        Self::check_generators(bp_gens, &claim.proof)?;
+        let cs = Self::verifier_cs(
+            pc_gens, bp_gens, transcript, &claim.nonce, claim.output_commitment, statement, Some(key),
+        )?;
+        cs.verify(&claim.proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate())
+    }
+
+    /// Like [`ShuffleProof::verify_with_key`] for the ElGamal key
+    /// `secret_key * B`, then decrypts every original and shuffled
+    /// ciphertext as `C2 - secret_key * C1` and checks both hold the same
+    /// multiset of plaintexts.
+    ///
+    /// The proof ties the shuffled ciphertexts to the originals only
+    /// through their combination by the public `input`, so a prover that
+    /// knows `input` can change plaintexts by amounts that cancel in it.
+    /// A verifier holding the decryption key rules that out; a mismatch is
+    /// reported as [`R1CSError::DecryptionMismatch`].
+    pub fn verify_with_decryption<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        secret_key: &Scalar,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        Self::verify_with_key(pc_gens, bp_gens, transcript, pc_gens.B * secret_key, claim)?;
+
+        let plaintexts = |C1: &[RistrettoPoint], C2: &[RistrettoPoint]| {
+            let mut plaintexts: Vec<[u8; 32]> = C1
+                .iter()
+                .zip(C2.iter())
+                .map(|(C1_i, C2_i)| (C2_i - C1_i * secret_key).compress().to_bytes())
+                .collect();
+            plaintexts.sort();
+            plaintexts
+        };
+        let statement = &claim.statement;
+        if plaintexts(&statement.C1, &statement.C2)
+            != plaintexts(&statement.C1_prime, &statement.C2_prime)
+        {
+            return Err(R1CSError::DecryptionMismatch);
+        }
+        Ok(())
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_components`] under the same `nonce` and `keys`.
+    pub fn verify_components<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim<ComponentStatement>,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        statement.check()?;
+        Self::check_generators(bp_gens, &claim.proof)?;
+
+        let C = statement.aggregate();
+        let (folded, key, C_folded) =
+            Self::fold_components(pc_gens, transcript, statement, &C, &claim.output_commitment);
+
+        let k_original = statement.shuffled[0].len();
+        let mut verifier = Self::start_verifier(pc_gens, bp_gens, transcript, &claim.nonce, &statement.input);
+        verifier.set_reencryption_key(key);
+        let cs = Self::shuffle_cs(verifier, &statement.input, claim.output_commitment, k_original);
+        cs.verify(&claim.proof, &statement.shuffled[0], &folded, &[C[0], C_folded])
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_committed_input`] under the same `nonce`, for
+    /// the same mirrored statement.
+    pub fn verify_committed_input<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        Self::append_committed_input(transcript);
+        Self::verify(pc_gens, bp_gens, transcript, claim)
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_with_params`], with the same raw `input`.
+    ///
+    /// A proof whose shape doesn't match `params` is rejected with
+    /// [`R1CSError::FormatError`] before any transcript work.
+    pub fn verify_with_params<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        params: &ShuffleParams,
+        claim: &ShuffleClaim,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        if statement.input.len() != params.n_raw() {
+            return Err(R1CSError::InputLengthError);
+        }
+        if statement.C1_prime.len() != params.n_raw() {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        if !params.config().matches(&claim.proof) {
+            return Err(R1CSError::FormatError);
+        }
+        let mut input = statement.input.clone();
+        input.resize(params.n_padded(), Scalar::zero());
+        let statement = ShuffleStatement { input, ..statement.clone() };
+        Self::verify_statement(
+            pc_gens, bp_gens, transcript, &claim.nonce, &claim.proof, claim.output_commitment,
+            &statement,
+        )
+    }
+
+    /// Attempt to verify a proof produced by [`ShuffleProof::prove`] when
+    /// the verifier holds only the Merkle `root` of the original
+    /// ciphertexts, as built by [`CiphertextTree`](::r1cs::CiphertextTree).
+    ///
+    /// `inclusion[i]` must prove that `(statement.C1[i], statement.C2[i])`
+    /// is leaf `i` under `root`.  Every inclusion proof is checked before
+    /// the shuffle proof, and a missing or invalid one is reported as
+    /// [`R1CSError::InvalidInclusionProof`].
+    pub fn verify_with_merkle_root<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+        root: &[u8; 32],
+        inclusion: &[MerkleProof],
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        statement.check()?;
+        if inclusion.len() != statement.C1.len() {
+            return Err(R1CSError::InvalidInclusionProof);
+        }
+        for (i, ((C1, C2), path)) in statement.C1.iter().zip(statement.C2.iter()).zip(inclusion.iter()).enumerate() {
+            if path.index != i || !path.verify(root, C1, C2) {
+                return Err(R1CSError::InvalidInclusionProof);
+            }
+        }
+
+        Self::verify(pc_gens, bp_gens, transcript, claim)
+    }
+
+    /// Attempt to verify an [`AggregatedShuffleProof`] of the parties'
+    /// `statements`, on the transcript the parties started from.
+    ///
+    /// Party `i`'s proof is checked against `statements[i]` and
+    /// `bp_gens.share(i)`, replaying the parties' transcript in order.
+    /// Each party's verification terms are scaled by an independent random
+    /// weight and all of them are summed in one multiscalar
+    /// multiplication, so one party's invalid contribution cannot be
+    /// cancelled by another's.
+    pub fn verify_aggregated<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        proof: &AggregatedShuffleProof,
+        statements: &[ShuffleStatement],
+    ) -> Result<(), R1CSError> {
+        let parties = statements.len();
+        if parties == 0 || proof.proofs.len() != parties || proof.output_commitments.len() != parties {
+            return Err(R1CSError::InputLengthError);
+        }
+        if parties > bp_gens.party_capacity {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+
+        let mut rng = thread_rng();
+        let mut scalars = Vec::new();
+        let mut points = Vec::new();
+        for (party, statement) in statements.iter().enumerate() {
+            statement.check()?;
+            Self::append_party(transcript);
+            let mut verifier = Self::start_verifier(pc_gens, bp_gens, transcript, &[], &statement.input);
+            verifier.set_party(party);
+            let cs = Self::shuffle_cs(
+                verifier, &statement.input, proof.output_commitments[party], statement.C1_prime.len(),
+            );
+
+            let weight = Scalar::random(&mut rng);
+            let terms = cs.msm_terms(
+                &proof.proofs[party], &statement.C1_prime, &statement.C2_prime, &statement.aggregate(),
+            )?;
+            for (scalar, point) in terms {
+                scalars.push(weight * scalar);
+                points.push(point);
+            }
+        }
+
+        if !RistrettoPoint::vartime_multiscalar_mul(scalars, points).is_identity() {
+            return Err(R1CSError::VerificationError);
+        }
+        Ok(())
+    }
+
+    /// Verifies each of `claims` independently with
+    /// [`ShuffleProof::verify`], on a fresh transcript labelled `label`,
+    /// returning the result for `claims[i]` at index `i`.
+    ///
+    /// With the `parallel` feature the claims are spread over the rayon
+    /// thread pool, and otherwise checked in turn.  Every worker borrows
+    /// the same `pc_gens` and `bp_gens`, so the generators are derived
+    /// once by the caller and never copied.  Unlike
+    /// [`ShuffleProof::verify_aggregated`], one invalid proof only fails
+    /// its own entry.
+    pub fn verify_many_parallel(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        label: &'static [u8],
+        claims: &[ShuffleClaim],
+    ) -> Vec<Result<(), R1CSError>> {
+        maybe_par_iter!(claims)
+            .map(|claim| Self::verify(pc_gens, bp_gens, &mut Transcript::new(label), claim))
+            .collect()
+    }
+
+    /// Rejects a proof whose fold schedule implies a witness longer than
+    /// `bp_gens` has generators for, before any transcript work, instead
+    /// of failing with the same error part way through verification.
+    fn check_generators(bp_gens: &BulletproofGens, proof: &R1CSProof) -> Result<(), R1CSError> {
+        if proof.implied_circuit_size() > bp_gens.gens_capacity {
+            return Err(R1CSError::InvalidGeneratorsLength);
+        }
+        Ok(())
+    }
+
+    /// Replays the statement side of [`ShuffleProof::verify`], returning
+    /// the constraint system ready to check a proof; `key` is the
+    /// re-encryption key, if not `B_blinding`.
+    pub(crate) fn verifier_cs<'a, 'b, T: ProofTranscript>(
+        pc_gens: &'b PedersenGens,
+        bp_gens: &'b BulletproofGens,
+        transcript: &'a mut T,
+        nonce: &[u8],
+        output_commitment: CompressedRistretto,
+        statement: &ShuffleStatement,
+        key: Option<RistrettoPoint>,
+    ) -> Result<VerifierCS<'a, 'b, T>, R1CSError> {
+        statement.check()?;
+        let mut verifier = Self::start_verifier(pc_gens, bp_gens, transcript, nonce, &statement.input);
+        if let Some(key) = key {
+            verifier.set_reencryption_key(key);
+        }
+        Ok(Self::shuffle_cs(verifier, &statement.input, output_commitment, statement.C1_prime.len()))
+    }
+
+    /// Starts the transcript of a shuffle of `input` under `nonce`, as
+    /// [`ShuffleProof::start_prover`] does, and returns the verifier on it.
+    fn start_verifier<'a, 'b, T: ProofTranscript>(
+        pc_gens: &'b PedersenGens,
+        bp_gens: &'b BulletproofGens,
+        transcript: &'a mut T,
+        nonce: &[u8],
+        input: &[Scalar],
+    ) -> Verifier<'a, 'b, T> {
+        Self::append_statement(transcript, input);
+        Self::append_nonce(transcript, nonce);
+        Verifier::new(bp_gens, pc_gens, transcript)
+    }
+
+    /// Commits to the output on `verifier` and adds the shuffle
+    /// constraints for `k_original` ciphertexts.
+    fn shuffle_cs<'a, 'b, T: ProofTranscript>(
+        mut verifier: Verifier<'a, 'b, T>,
+        input: &[Scalar],
+        output_commitment: CompressedRistretto,
+        k_original: usize,
+    ) -> VerifierCS<'a, 'b, T> {
+        let output_vars = verifier.commit_vec(output_commitment, input.len());
+        let mut cs = verifier.finalize_inputs();
+        Self::gadget(&mut cs, &output_vars, input, k_original);
+        cs
+    }
+
+    /// Like [`ShuffleProof::prove`], additionally proving in the same proof
+    /// that every output lies in `[0, 2^n_bits)`.
+    ///
+    /// The shuffle and the range constraints share the single output
+    /// commitment returned.  `input` and `output` must be padded to at
+    /// least [`ShuffleProof::range_witness_len`]; an out-of-range output
+    /// yields a proof that fails [`ShuffleProof::verify_in_range`].
+    pub fn prove_in_range<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        witness: &ShuffleWitness,
+        n_bits: usize,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        Self::check_witness(bp_gens, witness, config)?;
+        let statement = &witness.statement;
+        let k_original = statement.C1_prime.len();
+        Self::check_range(statement.input.len(), k_original, n_bits)?;
+
+        Self::append_range(transcript, n_bits);
+        let mut prover = Self::start_prover(pc_gens, bp_gens, transcript, nonce, &statement.input);
+        let (output_commitment, output_vars) =
+            prover.commit_vec(&witness.output, witness.v_blinding, k_original);
+        let mut cs = prover.finalize_inputs();
+        Self::gadget(&mut cs, &output_vars, &statement.input, k_original);
+        Self::range_gadget(&mut cs, &output_vars, Some(&witness.output), k_original, n_bits)?;
+        let proof = cs.prove(
+            &statement.C1_prime, &statement.C2_prime, witness.r_prime, config.k, config.d,
+        )?;
+        Ok((proof, output_commitment))
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_in_range`] under the same `nonce` and
+    /// `n_bits`.
+    pub fn verify_in_range<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        claim: &ShuffleClaim,
+        n_bits: usize,
+    ) -> Result<(), R1CSError> {
+        let statement = &claim.statement;
+        statement.check()?;
+        let k_original = statement.C1_prime.len();
+        Self::check_range(statement.input.len(), k_original, n_bits)?;
+
+        Self::append_range(transcript, n_bits);
+        let mut verifier = Self::start_verifier(pc_gens, bp_gens, transcript, &claim.nonce, &statement.input);
+        let output_vars = verifier.commit_vec(claim.output_commitment, statement.input.len());
+        let mut cs = verifier.finalize_inputs();
+        Self::gadget(&mut cs, &output_vars, &statement.input, k_original);
+        Self::range_gadget(&mut cs, &output_vars, None, k_original, n_bits)?;
+        cs.verify(&claim.proof, &statement.C1_prime, &statement.C2_prime, &statement.aggregate())
+    }
+
+    /// Proves that the committed `committed_opening` is a permutation of
+    /// the public `public`, as multisets: repeated values are allowed, and
+    /// each must occur equally often in both.
+    ///
+    /// The shuffle gadget checks `prod(public_i - z) = prod(output_i - z)`
+    /// for a random `z`, and both sides are polynomials in `z` whose roots
+    /// are the elements with their multiplicities, so repeats need no
+    /// special handling.  There are no ciphertexts here: the consistency
+    /// check runs against a single fixed point standing in for every
+    /// ciphertext, which any reordering of the values satisfies.
+    ///
+    /// Returns the proof and the commitment to `committed_opening`.
+    pub fn prove_multiset_equal<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        public: &[Scalar],
+        committed_opening: &[Scalar],
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto), R1CSError> {
+        if committed_opening.len() != public.len() {
+            return Err(R1CSError::InputLengthError);
+        }
+        let statement = Self::multiset_statement(public);
+        let witness = ShuffleWitness::new(statement, committed_opening.to_vec(), Scalar::zero());
+
+        // The sub-proofs take the empty nonce; a caller needing replay
+        // protection appends its own nonce to the transcript beforehand.
+        transcript.append_message(b"dom-sep", b"MultisetEqualityProof");
+        Self::prove(pc_gens, bp_gens, transcript, &[], &witness, config)
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_multiset_equal`].
+    pub fn verify_multiset_equal<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        proof: &R1CSProof,
+        public: &[Scalar],
+        commitment: CompressedRistretto,
+    ) -> Result<(), R1CSError> {
+        let statement = Self::multiset_statement(public);
+        let (C1, C2) = Self::multiset_ciphertext();
+        let sum: Scalar = public.iter().sum();
+        let C = [C1 * sum, C2 * sum];
+
+        transcript.append_message(b"dom-sep", b"MultisetEqualityProof");
+        Self::check_generators(bp_gens, proof)?;
+        let cs = Self::verifier_cs(pc_gens, bp_gens, transcript, &[], commitment, &statement, None)?;
+        cs.verify(proof, &statement.C1_prime, &statement.C2_prime, &C)
+    }
+
+    /// Returns the fixed stand-in ciphertext used by
+    /// [`ShuffleProof::prove_multiset_equal`].
+    fn multiset_ciphertext() -> (RistrettoPoint, RistrettoPoint) {
+        let C1 = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"MultisetEqualityProof C1");
+        let C2 = RistrettoPoint::hash_from_bytes::<Sha3_512>(b"MultisetEqualityProof C2");
+        (C1, C2)
+    }
+
+    /// Returns the statement of [`ShuffleProof::prove_multiset_equal`]
+    /// over `public`, with the stand-in ciphertext as every original and
+    /// shuffled ciphertext.
+    fn multiset_statement(public: &[Scalar]) -> ShuffleStatement {
+        let n = public.len();
+        let (C1, C2) = Self::multiset_ciphertext();
+        ShuffleStatement {
+            input: public.to_vec(),
+            C1: vec![C1; n],
+            C2: vec![C2; n],
+            C1_prime: vec![C1; n],
+            C2_prime: vec![C2; n],
+        }
+    }
+
+    /// Encodes opaque 32-byte `payloads`, such as mixnet messages, as the
+    /// public input of a shuffle, zero-padded to `n_padded`.
+    ///
+    /// Each payload is encoded as the `Scalar` with those bytes, so every
+    /// payload must be a canonical scalar encoding (a little-endian integer
+    /// below the group order `l`, which holds whenever the top four bits
+    /// are clear); otherwise this returns
+    /// [`R1CSError::NonCanonicalPayload`] rather than silently reducing it
+    /// to a different payload.
+    pub fn encode_payloads(payloads: &[[u8; 32]], n_padded: usize) -> Result<Vec<Scalar>, R1CSError> {
+        if n_padded < payloads.len() {
+            return Err(R1CSError::InputLengthError);
+        }
+        let mut input = payloads
+            .iter()
+            .map(|p| Scalar::from_canonical_bytes(*p).ok_or(R1CSError::NonCanonicalPayload))
+            .collect::<Result<Vec<Scalar>, R1CSError>>()?;
+        input.resize(n_padded, Scalar::zero());
+        Ok(input)
+    }
+
+    /// Proves a shuffle of opaque 32-byte `payloads`, such as mixnet
+    /// messages, under `witness.permutation`.
+    ///
+    /// The payloads are encoded with [`ShuffleProof::encode_payloads`],
+    /// zero-padded to `config.n_padded`, and must be canonical scalar
+    /// encodings; see there.  The encoding replaces `witness.statement.input`,
+    /// which may be left empty.  There must be one payload per ciphertext.
+    ///
+    /// Returns the proof, the output commitment and the encoded input,
+    /// which the verifier puts in the [`ShuffleClaim`]'s statement.
+    /// Output position `i` holds `payloads[permutation[i]]`, recovered
+    /// with `Scalar::to_bytes`.
+    pub fn prove_bytes<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        nonce: &[u8],
+        payloads: &[[u8; 32]],
+        witness: &PermutationWitness,
+        config: &ProofConfig,
+    ) -> Result<(R1CSProof, CompressedRistretto, Vec<Scalar>), R1CSError> {
+        if payloads.len() != witness.statement.C1_prime.len() {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        let input = Self::encode_payloads(payloads, config.n_padded)?;
+        let witness = PermutationWitness {
+            statement: ShuffleStatement { input, ..witness.statement.clone() },
+            ..witness.clone()
+        }
+        .to_witness()?;
+        let (proof, output_commitment) =
+            Self::prove(pc_gens, bp_gens, transcript, nonce, &witness, config)?;
+        Ok((proof, output_commitment, witness.statement.input))
+    }
+
+    /// Rejects a `permutation` that repeats an index or points past its
+    /// own length.
+    fn check_permutation(permutation: &[usize]) -> Result<(), R1CSError> {
+        let mut seen = vec![false; permutation.len()];
+        for &j in permutation {
+            if j >= permutation.len() || seen[j] {
+                return Err(R1CSError::InvalidPermutation);
+            }
+            seen[j] = true;
+        }
+        Ok(())
+    }
+
+    /// Recovers the permutation `pi` with `output[i] = input[pi[i]]`, the
+    /// convention of [`ShuffleProof::prove_same_permutation`], or `None`
+    /// if `output` is not a rearrangement of `input`.
+    ///
+    /// Repeated values are matched in order: the first occurrence in
+    /// `output` takes the first unused occurrence in `input`.  This is a
+    /// prover-side audit of a witness both openings are known for; the
+    /// verifier never learns `output`.
+    pub fn recover_permutation(input: &[Scalar], output: &[Scalar]) -> Option<Vec<usize>> {
+        if input.len() != output.len() {
+            return None;
+        }
+        let mut positions: HashMap<[u8; 32], VecDeque<usize>> = HashMap::new();
+        for (j, x) in input.iter().enumerate() {
+            positions.entry(x.to_bytes()).or_default().push_back(j);
+        }
+        output
+            .iter()
+            .map(|y| positions.get_mut(y.as_bytes()).and_then(|js| js.pop_front()))
+            .collect()
+    }
+
+    /// Returns the permuted witness, zero-padded like `input`, and the
+    /// `r_prime` the verifier's consistency check expects for it.
+    fn permute(input: &[Scalar], permutation: &[usize], r: &[Scalar]) -> (Vec<Scalar>, Scalar) {
+        let mut output = vec![Scalar::zero(); input.len()];
+        let mut r_prime = Scalar::zero();
+        for (i, &j) in permutation.iter().enumerate() {
+            output[i] = input[j];
+            r_prime -= r[i] * input[j];
+        }
+        (output, r_prime)
+    }
+
+    /// Attempt to verify a proof produced by
+    /// [`ShuffleProof::prove_same_permutation`].
+    pub fn verify_same_permutation<T: ProofTranscript>(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        transcript: &mut T,
+        proof: &SamePermutationProof,
+        a: &ShuffleStatement,
+        b: &ShuffleStatement,
+    ) -> Result<(), R1CSError> {
+        if b.input.len() != a.input.len() {
+            return Err(R1CSError::InputLengthError);
+        }
+        a.check()?;
+        b.check()?;
+
+        transcript.append_message(b"dom-sep", b"SamePermutationProof");
+
+        Self::verify_statement(
+            pc_gens, bp_gens, transcript, &[], &proof.proof_a, proof.output_commitment_a, a,
+        )?;
+        Self::verify_statement(
+            pc_gens, bp_gens, transcript, &[], &proof.proof_b, proof.output_commitment_b, b,
+        )?;
+
+        let lambda = transcript.challenge_scalar(b"same-permutation lambda");
+        let combined = a.combine(b, lambda);
+        let V_a = proof.output_commitment_a.decompress().ok_or(R1CSError::FormatError)?;
+        let V_b = proof.output_commitment_b.decompress().ok_or(R1CSError::FormatError)?;
+        Self::verify_statement(
+            pc_gens, bp_gens, transcript, &[], &proof.proof_combined,
+            (V_a + V_b * lambda).compress(), &combined,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merlin::Transcript;
+
+    #[test]
+    fn shuffle_challenge_avoids_inputs() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(4, 1);
+        let mut transcript = Transcript::new(b"ShuffleChallengeTest");
+
+        // Draw the first `z` on a copy of the transcript, then make it an input.
+        let mut copy = transcript.clone();
+        let z_first = Verifier::new(&bp_gens, &pc_gens, &mut copy)
+            .finalize_inputs()
+            .challenge_scalar(b"k-scalar shuffle challenge");
+        let y = [Scalar::one(), z_first];
+
+        let mut cs = Verifier::new(&bp_gens, &pc_gens, &mut transcript).finalize_inputs();
+        let z = ShuffleProof::shuffle_challenge(&mut cs, &y);
+        assert_ne!(z, z_first);
+        assert!(!y.contains(&z));
+    }
+
+    #[test]
+    fn recover_permutation_matches_permute() {
+        let input: Vec<Scalar> = (1..6u64).map(Scalar::from).collect();
+        let permutation = [3, 0, 4, 2, 1];
+        let (output, _) = ShuffleProof::permute(&input, &permutation, &[Scalar::zero(); 5]);
+        assert_eq!(ShuffleProof::recover_permutation(&input, &output), Some(permutation.to_vec()));
+    }
+
+    #[test]
+    fn recover_permutation_matches_repeats_in_order() {
+        let (a, b) = (Scalar::from(7u64), Scalar::from(9u64));
+        let input = [a, b, a, b];
+        let output = [b, a, a, b];
+        assert_eq!(ShuffleProof::recover_permutation(&input, &output), Some(vec![1, 0, 2, 3]));
+    }
+
+    #[test]
+    fn recover_permutation_rejects_other_multisets() {
+        let input: Vec<Scalar> = (1..4u64).map(Scalar::from).collect();
+        let twice = [input[0], input[0], input[2]];
+        let foreign = [input[0], input[1], Scalar::from(4u64)];
+        assert_eq!(ShuffleProof::recover_permutation(&input, &twice), None);
+        assert_eq!(ShuffleProof::recover_permutation(&input, &foreign), None);
+        assert_eq!(ShuffleProof::recover_permutation(&input, &input[..2]), None);
+    }
+}
@@ -10,12 +10,124 @@ use merlin::Transcript;
 use super::{ConstraintSystem, LinearCombination, R1CSProof, Variable};
 
 use errors::R1CSError;
-use generators::{BulletproofGens, PedersenGens};
-use transcript::TranscriptProtocol;
-use curve25519_dalek::traits::IsIdentity;
+use generators::{BulletproofGens, PedersenGens, PrecomputedGens};
+use inner_product_proof::folded_len;
+use transcript::{ProofTranscript, TranscriptProtocol};
+use curve25519_dalek::traits::{Identity, IsIdentity};
+use std::iter;
+
+/// How many ciphertext pairs [`VerifierCS::verify_streaming`] reads
+/// before folding them into its running sum.
+const STREAM_CHUNK: usize = 1024;
+
+
+
+
+/// The stage of [`VerifierCS::verify_diagnostic`] that rejected a proof.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VerificationStage {
+    /// The proof exceeds the limits set with [`Verifier::with_limits`].
+    SizeLimits,
+    /// There are too few generators for the number of inputs.
+    Generators,
+    /// The K-ary inner-product proof has the wrong shape for its sizes.
+    IpaScalars,
+    /// The batched ElGamal consistency proof has the wrong shape for its sizes.
+    EcpScalars,
+    /// A point in the proof or statement is not a valid encoding.
+    Decompression,
+    /// The combined verification equation does not hold.
+    FinalCheck,
+}
+
+/// A structured description of a failed verification, for operators.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VerificationReport {
+    /// The stage at which verification failed.
+    pub stage: VerificationStage,
+    /// The padded number of inputs the verifier checked against.
+    pub n: usize,
+    /// The folding factor claimed by the proof.
+    pub k: usize,
+    /// The number of folding rounds claimed by the proof.
+    pub d: usize,
+}
+
+/// The scalars of [`VerifierCS`]'s final multiscalar multiplication,
+/// grouped by the points they multiply, for comparing against an
+/// independent verifier scalar by scalar.
+///
+/// Returned by [`VerifierCS::verification_scalars_dump`].  The scalars
+/// include the verifier's random weights, so two dumps of the same proof
+/// differ; compare against a reimplementation fed the same weights, or
+/// check that [`VerificationScalars::scalars`] against `points` sums to
+/// the identity.
+#[cfg(any(test, feature = "debug"))]
+#[derive(Clone, Debug)]
+pub struct VerificationScalars {
+    /// Scalars for `A_I`, `A_O`, `S`, `V`, `S_prime`, `B`, `B_blinding`,
+    /// the generator blinding `V` and the re-encryption key.
+    pub commitments: Vec<Scalar>,
+    /// Scalars for the `G` generators, including the consistency proof's
+    /// contribution.
+    pub g_scalars: Vec<Scalar>,
+    /// Scalars for the `H` generators.
+    pub h_scalars: Vec<Scalar>,
+    /// Scalars for the inner-product proof's `U` points, as they enter the
+    /// check (that is, negated).
+    pub s_U: Vec<Scalar>,
+    /// Scalars for `T_1_prime`, `T_2`, `T_1` to `T_6`, `S1_prime`,
+    /// `S2_prime`, `C[0]` and `C[1]`.
+    pub consistency: Vec<Scalar>,
+    /// Scalars for `C1_prime` followed by `C2_prime`, derived from the
+    /// consistency proof's final vector `z_s`.
+    pub z_s_vec: Vec<Scalar>,
+    /// Scalars for the consistency proof's `A_0` points followed by its
+    /// `A_1` points.
+    pub s_A: Vec<Scalar>,
+    /// The points of the multiscalar multiplication, in the order of
+    /// [`VerificationScalars::scalars`].
+    pub points: Vec<RistrettoPoint>,
+}
 
+#[cfg(any(test, feature = "debug"))]
+impl VerificationScalars {
+    /// Returns every scalar in the order of `points`.
+    pub fn scalars(&self) -> Vec<Scalar> {
+        self.commitments.iter()
+            .chain(self.g_scalars.iter())
+            .chain(self.h_scalars.iter())
+            .chain(self.s_U.iter())
+            .chain(self.consistency.iter())
+            .chain(self.z_s_vec.iter())
+            .chain(self.s_A.iter())
+            .cloned()
+            .collect()
+    }
+}
 
+/// The terms of a proof's final multiscalar multiplication, without the
+/// shuffled ciphertexts: pair `i` contributes
+/// `weights[i] * C1_prime[i] + weights[i] * c * C2_prime[i]`, and its
+/// terms belong at index `split` of `scalars` and `points`.
+struct MsmParts {
+    scalars: Vec<Scalar>,
+    points: Vec<RistrettoPoint>,
+    split: usize,
+    weights: Vec<Scalar>,
+    c: Scalar,
+    report: VerificationReport,
+}
 
+impl From<VerificationReport> for R1CSError {
+    fn from(report: VerificationReport) -> R1CSError {
+        match report.stage {
+            VerificationStage::SizeLimits => R1CSError::FormatError,
+            VerificationStage::Generators => R1CSError::InvalidGeneratorsLength,
+            _ => R1CSError::VerificationError,
+        }
+    }
+}
 
 /// An entry point for verifying a R1CS proof.
 ///
@@ -30,19 +142,19 @@ use curve25519_dalek::traits::IsIdentity;
 ///
 /// When all constraints are added, the verifying code calls `verify`
 /// on the instance of the constraint system to check the proof.
-pub struct Verifier<'a, 'b> {
+pub struct Verifier<'a, 'b, T: 'a = Transcript> {
     /// Number of high-level variables
     m: u64,
 
     /// Constraint system implementation
-    cs: VerifierCS<'a, 'b>,
+    cs: VerifierCS<'a, 'b, T>,
 }
 
 /// A [`ConstraintSystem`] implementation for use by the verifier.
-pub struct VerifierCS<'a, 'b> {
+pub struct VerifierCS<'a, 'b, T: 'a = Transcript> {
     bp_gens: &'b BulletproofGens,
     pc_gens: &'b PedersenGens,
-    transcript: &'a mut Transcript,
+    transcript: &'a mut T,
     constraints: Vec<LinearCombination>,
     /// Records the number of low-level variables allocated in the
     /// constraint system.
@@ -53,11 +165,25 @@ pub struct VerifierCS<'a, 'b> {
     /// variable assignments.
     num_vars: usize,
     V: Vec<CompressedRistretto>,
+    /// The generator blinding `V[0]` and `S_prime`.
+    v_blinding_gen: RistrettoPoint,
+    /// The public key `C2_prime` was re-randomized under.
+    reencryption_key: RistrettoPoint,
+    /// The party whose share of `bp_gens` the proof uses.
+    party: usize,
     num_inputs: usize,
-    
+    /// Largest number of committed inputs (after padding) accepted by `verify`.
+    n_max: usize,
+    /// Largest folding factor `k` a proof may claim.
+    k_max: usize,
+    /// Largest number of folding rounds `d` a proof may claim.
+    d_max: usize,
+    /// Set by `finalize_inputs`; challenges drawn before then would not
+    /// bind every committed input.
+    inputs_finalized: bool,
 }
 
-impl<'a, 'b> ConstraintSystem for VerifierCS<'a, 'b> {
+impl<'a, 'b, T: ProofTranscript> ConstraintSystem for VerifierCS<'a, 'b, T> {
     fn multiply(
         &mut self,
         mut left: LinearCombination,
@@ -103,11 +229,12 @@ impl<'a, 'b> ConstraintSystem for VerifierCS<'a, 'b> {
     }
 
     fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        assert!(self.inputs_finalized, "challenge_scalar called before finalize_inputs");
         self.transcript.challenge_scalar(label)
     }
 }
 
-impl<'a, 'b> Verifier<'a, 'b> {
+impl<'a, 'b, T: ProofTranscript> Verifier<'a, 'b, T> {
     /// Construct an empty constraint system with specified external
     /// input variables.
     ///
@@ -117,7 +244,10 @@ impl<'a, 'b> Verifier<'a, 'b> {
     /// and for the Pedersen commitments, respectively.  The
     /// [`BulletproofGens`] should have `gens_capacity` greater than
     /// the number of multiplication constraints that will eventually
-    /// be added into the constraint system.
+    /// be added into the constraint system.  Their
+    /// [`digest`](BulletproofGens::digest) is committed to the
+    /// transcript, so a prover and verifier using different generators
+    /// diverge from the first challenge.
     ///
     /// The `transcript` parameter is a Merlin proof transcript.  The
     /// `VerifierCS` holds onto the `&mut Transcript` until it consumes
@@ -142,9 +272,10 @@ impl<'a, 'b> Verifier<'a, 'b> {
     pub fn new(
         bp_gens: &'b BulletproofGens,
         pc_gens: &'b PedersenGens,
-        transcript: &'a mut Transcript,
+        transcript: &'a mut T,
     ) -> Self {
         transcript.r1cs_domain_sep();
+        transcript.append_message(b"generators", &bp_gens.digest(pc_gens));
 
         Verifier {
             m: 0,
@@ -154,13 +285,41 @@ impl<'a, 'b> Verifier<'a, 'b> {
                 transcript,
                 num_vars: 0,
                 V: Vec::new(),
+                v_blinding_gen: pc_gens.B_blinding,
+                reencryption_key: pc_gens.B_blinding,
+                party: 0,
                 constraints: Vec::new(),
                 num_inputs:0, // number of inputs + shuffled outputs
-                
+                n_max: usize::max_value(),
+                k_max: usize::max_value(),
+                d_max: usize::max_value(),
+                inputs_finalized: false,
             },
         }
     }
 
+    /// Construct a verifier like [`Verifier::new`], but one that rejects
+    /// any proof exceeding the given sizes with [`R1CSError::FormatError`].
+    ///
+    /// The checks run in [`VerifierCS::verify`] before any size-dependent
+    /// allocation, so a verifier configured for circuits up to `n_max`
+    /// inputs, folding factor `k_max` and `d_max` rounds cannot be made to
+    /// do more work than that by a proof claiming larger parameters.
+    pub fn with_limits(
+        bp_gens: &'b BulletproofGens,
+        pc_gens: &'b PedersenGens,
+        transcript: &'a mut T,
+        n_max: usize,
+        k_max: usize,
+        d_max: usize,
+    ) -> Self {
+        let mut verifier = Verifier::new(bp_gens, pc_gens, transcript);
+        verifier.cs.n_max = n_max;
+        verifier.cs.k_max = k_max;
+        verifier.cs.d_max = d_max;
+        verifier
+    }
+
     /// Creates commitment to a high-level variable and adds it to the transcript.
     ///
     /// # Inputs
@@ -180,6 +339,20 @@ impl<'a, 'b> Verifier<'a, 'b> {
         commitment: CompressedRistretto,
         n: usize,
     ) -> Vec<Variable> {
+        self.commit_vec_with_generator(commitment, n, None)
+    }
+
+    /// Like [`Verifier::commit_vec`], for a commitment the prover blinded
+    /// with `blinding_gen` instead of `B_blinding` (see
+    /// [`Prover::commit_vec_with_generator`](::r1cs::Prover::commit_vec_with_generator)).
+    /// `None` means `B_blinding`.
+    pub fn commit_vec_with_generator(
+        &mut self,
+        commitment: CompressedRistretto,
+        n: usize,
+        blinding_gen: Option<RistrettoPoint>,
+    ) -> Vec<Variable> {
+        self.cs.v_blinding_gen = blinding_gen.unwrap_or(self.cs.pc_gens.B_blinding);
         let start_index = self.m as usize;
 
         // Increase the commitment counter
@@ -195,57 +368,62 @@ impl<'a, 'b> Verifier<'a, 'b> {
             .collect()
     }
 
+    /// Sets the public key `C2_prime` was re-randomized under, in place of
+    /// `B_blinding`; see
+    /// [`Prover::set_reencryption_key`](::r1cs::Prover::set_reencryption_key).
+    pub fn set_reencryption_key(&mut self, key: RistrettoPoint) {
+        self.cs.reencryption_key = key;
+        self.cs.transcript.commit_point(b"reencryption key", &key.compress());
+    }
+
+    /// Checks the proof against party `j`'s share of the generators; see
+    /// [`Prover::set_party`](::r1cs::Prover::set_party).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `j` is not below the generators' `party_capacity`.
+    pub fn set_party(&mut self, j: usize) {
+        assert!(j < self.cs.bp_gens.party_capacity, "party index exceeds the generators' party capacity");
+        assert_eq!(self.m, 0, "set_party must be called before committing variables");
+        self.cs.party = j;
+        self.cs.transcript.commit_u64(b"party", j as u64);
+    }
+
     /// Consume the `Verifier`, provide the `ConstraintSystem` implementation to the closure,
     /// and verify the proof against the resulting constraint system.
-    pub fn finalize_inputs(self) -> VerifierCS<'a, 'b> {
+    pub fn finalize_inputs(mut self) -> VerifierCS<'a, 'b, T> {
         // Commit a length _suffix_ for the number of high-level variables.
         // We cannot do this in advance because user can commit variables one-by-one,
         // but this suffix provides safe disambiguation because each variable
         // is prefixed with a separate label.
         self.cs.transcript.commit_u64(b"m", self.m);
+        self.cs.inputs_finalized = true;
         self.cs
     }
 }
 
-/// Finds the smallest power of k (i.e., k^x)
-#[inline] 
-fn next_power_of_k(n: usize, k: usize) -> usize {
-    if n <= 1 {
-        return 1;
+/// Returns the smallest power of `k` that is at least `n`, `k^0 = 1` for
+/// `n <= 1`.
+///
+/// This multiplies in integers, so it is exact for every `n`; an `f64`
+/// logarithm cannot represent `n` near `2^53` and above.  Returns
+/// [`R1CSError::InvalidFoldParameters`] if `k < 2`, and
+/// [`R1CSError::FormatError`] if the power would overflow `usize`.
+#[allow(dead_code)]
+fn next_power_of_k(n: usize, k: usize) -> Result<usize, R1CSError> {
+    if k < 2 {
+        return Err(R1CSError::InvalidFoldParameters);
     }
-
-    match k {
-        0 => panic!("Cannot find a power of 0 >= {}", n),
-        1 => panic!("Cannot find a power of 1 >= {}", n), // 1^x is always 1
-        
-       
-        _ => {
-            let n_f = n as f64;
-            let k_f = k as f64;
-            let exponent = (n_f.ln() / k_f.ln()).ceil() as u32;
-
-            let mut power = k.checked_pow(exponent)
-                .unwrap_or_else(|| panic!("Overflow: k^x exceeds usize::MAX"));
-
-            if power < n {
-                power = power.checked_mul(k)
-                    .unwrap_or_else(|| panic!("Overflow: k^x * k exceeds usize::MAX"));
-            }
-            
-            if let Some(prev_power) = power.checked_div(k) {
-                if prev_power >= n {
-                    power = prev_power;
-                }
-            }
-            
-            power
-        }
+    let mut power: usize = 1;
+    while power < n {
+        power = power.checked_mul(k).ok_or(R1CSError::FormatError)?;
     }
+    Ok(power)
 }
 
 
 
-impl<'a, 'b> VerifierCS<'a, 'b> {
+impl<'a, 'b, T: ProofTranscript> VerifierCS<'a, 'b, T> {
     /// Use a challenge, `z`, to flatten the constraints in the
     /// constraint system into vectors used for proving and
     /// verification.
@@ -301,34 +479,328 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
         (wL, wR, wO, wV, wc)
     }
 
+  /// Checks that `proof` was made for the number of high-level variables
+  /// committed with [`Verifier::commit_vec`], before any transcript work.
+  ///
+  /// The proof does not carry that number `m`; it is bound only by the
+  /// `m` suffix in the transcript, so a prover and verifier disagreeing
+  /// on it would otherwise just see [`R1CSError::VerificationError`].
+  /// Each folding round shrinks the vectors to `ceil(len / k)`, so the
+  /// inner-product proof's final length pins `m` down to a block of
+  /// `k^d` values, and a mismatch that crosses a block boundary is
+  /// reported here as [`R1CSError::InputCountMismatch`].
+  pub fn check_input_count(&self, proof: &R1CSProof) -> Result<(), R1CSError> {
+    let k = proof.ipp_proof.k;
+    if k < 2 {
+        return Err(R1CSError::FormatError);
+    }
+
+    let mut folded = self.num_inputs;
+    let mut block: usize = 1;
+    for _ in 0..proof.ipp_proof.U_vecs.len() {
+        folded = folded_len(folded, k);
+        block = block.saturating_mul(k);
+    }
+
+    let final_len = proof.ipp_proof.a_final.len();
+    if folded != final_len {
+        return Err(R1CSError::InputCountMismatch {
+            verifier: self.num_inputs,
+            proof: final_len.saturating_mul(block),
+        });
+    }
+    Ok(())
+}
+
+  /// Checks that the number of high-level variables committed with
+  /// [`Verifier::commit_vec`] is exactly the witness length implied by
+  /// the proof's fold schedule, [`R1CSProof::implied_circuit_size`].
+  ///
+  /// [`VerifierCS::check_input_count`] accepts any count in the block
+  /// that folds down to the proof's final length; a count that isn't
+  /// itself `m * k^d` is not reachable by
+  /// [`ProofConfig::from_raw`](::r1cs::ProofConfig::from_raw) padding,
+  /// so the proof is malformed and is reported as
+  /// [`R1CSError::CircuitSizeMismatch`].
+  pub fn check_circuit_size(&self, proof: &R1CSProof) -> Result<(), R1CSError> {
+    self.check_input_count(proof)?;
+    let implied = proof.implied_circuit_size();
+    if implied != self.num_inputs {
+        return Err(R1CSError::CircuitSizeMismatch {
+            verifier: self.num_inputs,
+            implied,
+        });
+    }
+    Ok(())
+}
+
+  /// Verify `proof` against the shuffle statement `(C1_prime, C2_prime, C)`.
   pub fn verify(
-    mut self,
+    self,
+    proof: &R1CSProof,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    C: &[RistrettoPoint],
+) -> Result<(), R1CSError> {
+    self.check_circuit_size(proof)?;
+    self.verify_diagnostic(proof, C1_prime, C2_prime, C)
+        .map_err(R1CSError::from)
+}
+
+  /// Like [`VerifierCS::verify`], but on failure reports which stage of
+  /// verification rejected the proof, together with the proof's sizes.
+  pub fn verify_diagnostic(
+    self,
+    proof: &R1CSProof,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    C: &[RistrettoPoint],
+) -> Result<(), VerificationReport> {
+    let (scalars, points, report) = self.msm_diagnostic(proof, C1_prime, C2_prime, C)?;
+    let mega_check = RistrettoPoint::vartime_multiscalar_mul(scalars, points);
+
+    if !mega_check.is_identity() {
+        return Err(report);
+    }
+
+    Ok(())
+}
+
+  /// Like [`VerifierCS::verify`], with the `G` and `H` terms of the final
+  /// multiscalar multiplication evaluated from `table`.
+  ///
+  /// `table` must have been built from the same generators as this
+  /// verifier, with a capacity of at least the padded number of inputs;
+  /// the result is then the same as [`VerifierCS::verify`]'s.  A smaller
+  /// table is rejected with [`R1CSError::InvalidGeneratorsLength`].
+  /// Tables hold party 0's generators, so a verifier set to another
+  /// party with [`Verifier::set_party`] ignores `table`.
+  pub fn verify_precomputed(
+    self,
+    table: &PrecomputedGens,
+    proof: &R1CSProof,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    C: &[RistrettoPoint],
+) -> Result<(), R1CSError> {
+    if self.party != 0 {
+        // The table only covers party 0's generators.
+        return self.verify(proof, C1_prime, C2_prime, C);
+    }
+    self.check_circuit_size(proof)?;
+    let padded_n = self.num_inputs;
+    if table.capacity() < padded_n {
+        return Err(R1CSError::InvalidGeneratorsLength);
+    }
+    let (mut scalars, mut points, _) = self.msm_diagnostic(proof, C1_prime, C2_prime, C)?;
+
+    // Move the `G` and `H` terms, right after the 9 commitment terms, to
+    // the precomputed side.
+    let gh_scalars: Vec<Scalar> = scalars.drain(9..9 + 2 * padded_n).collect();
+    points.drain(9..9 + 2 * padded_n);
+    let (g_scalars, h_scalars) = gh_scalars.split_at(padded_n);
+
+    let mega_check = table.vartime_mixed_mul(g_scalars, h_scalars, &scalars, &points);
+    if !mega_check.is_identity() {
+        return Err(R1CSError::VerificationError);
+    }
+    Ok(())
+}
+
+  /// Like [`VerifierCS::verify`], but leaves every term whose scalar is
+  /// zero out of the final multiscalar multiplication.
+  ///
+  /// The result is the same as [`VerifierCS::verify`]'s.  Note that the
+  /// padded tail of a shuffle is *not* zero: the inner-product proof
+  /// weights every `G_i` and `H_i` up to the padded size, and each
+  /// `H_i` also carries the `-1` of the `y^n` term, so only terms that
+  /// actually cancel (such as zero entries of the statement) are skipped.
+  pub fn verify_sparse(
+    self,
     proof: &R1CSProof,
     C1_prime: &[RistrettoPoint],
     C2_prime: &[RistrettoPoint],
     C: &[RistrettoPoint],
 ) -> Result<(), R1CSError> {
+    self.check_circuit_size(proof)?;
+    let (scalars, points, _) = self.msm_diagnostic(proof, C1_prime, C2_prime, C)?;
+    let zero = Scalar::zero();
+    let (scalars, points): (Vec<Scalar>, Vec<RistrettoPoint>) = scalars
+        .into_iter()
+        .zip(points.into_iter())
+        .filter(|(scalar, _)| *scalar != zero)
+        .unzip();
+
+    let mega_check = RistrettoPoint::vartime_multiscalar_mul(scalars, points);
+    if !mega_check.is_identity() {
+        return Err(R1CSError::VerificationError);
+    }
+    Ok(())
+}
+
+  /// Returns the `(scalar, point)` terms [`VerifierCS::verify`] feeds to
+  /// its single multiscalar multiplication; the proof is valid exactly
+  /// when they sum to the identity.
+  ///
+  /// The terms are already weighted by verifier randomness drawn from the
+  /// transcript, but a caller batching several proofs into one
+  /// multiscalar multiplication must still scale each proof's terms by
+  /// its own random weight, so that errors in different proofs cannot
+  /// cancel out.
+  pub fn msm_terms(
+    self,
+    proof: &R1CSProof,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    C: &[RistrettoPoint],
+) -> Result<Vec<(Scalar, RistrettoPoint)>, R1CSError> {
+    self.check_circuit_size(proof)?;
+    let (scalars, points, _) = self.msm_diagnostic(proof, C1_prime, C2_prime, C)?;
+    Ok(scalars.into_iter().zip(points.into_iter()).collect())
+}
+
+  /// Like [`VerifierCS::msm_terms`], with the scalars split into the
+  /// groups of [`VerificationScalars`].
+  #[cfg(any(test, feature = "debug"))]
+  pub fn verification_scalars_dump(
+    self,
+    proof: &R1CSProof,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    C: &[RistrettoPoint],
+) -> Result<VerificationScalars, R1CSError> {
+    let padded_n = self.num_inputs;
+    let (scalars, points, _) = self.msm_diagnostic(proof, C1_prime, C2_prime, C)?;
+
+    let U_len: usize = proof.ipp_proof.U_vecs.iter().map(|U_round| U_round.len()).sum();
+    let A_len: usize = proof.ecp_batched.A_vecs.iter().map(|A_round| A_round.len()).sum();
+    let mut rest = scalars.into_iter();
+    let mut take = |len: usize| -> Vec<Scalar> { rest.by_ref().take(len).collect() };
+
+    Ok(VerificationScalars {
+        commitments: take(9),
+        g_scalars: take(padded_n),
+        h_scalars: take(padded_n),
+        s_U: take(U_len),
+        consistency: take(12),
+        z_s_vec: take(2 * C1_prime.len()),
+        s_A: take(2 * A_len),
+        points,
+    })
+}
+
+  /// Runs every verification stage up to the final check, returning the
+  /// terms of the final multiscalar multiplication and the report to
+  /// raise if they don't sum to the identity.
+  fn msm_diagnostic(
+    self,
+    proof: &R1CSProof,
+    C1_prime: &[RistrettoPoint],
+    C2_prime: &[RistrettoPoint],
+    C: &[RistrettoPoint],
+) -> Result<(Vec<Scalar>, Vec<RistrettoPoint>, VerificationReport), VerificationReport> {
+    let MsmParts { mut scalars, mut points, split, weights, c, report } =
+        self.msm_parts(proof, C1_prime.len(), C)?;
+
+    let ciphertext_scalars = weights.iter().cloned().chain(weights.iter().map(|w| w * c));
+    scalars.splice(split..split, ciphertext_scalars);
+    points.splice(split..split, C1_prime.iter().chain(C2_prime.iter()).cloned());
+    Ok((scalars, points, report))
+}
+
+  /// Like [`VerifierCS::verify`], with the shuffled ciphertexts read
+  /// one `(C1_prime[i], C2_prime[i])` pair at a time from `ciphertexts`,
+  /// which must yield exactly `k_original` pairs.
+  ///
+  /// The ciphertext terms are folded into a running sum a chunk at a
+  /// time, so a verifier reading millions of ciphertexts from disk holds
+  /// at most a fixed-size chunk of them at once.  The verifier's other
+  /// vectors (the public inputs, the generators and their scalars) still
+  /// grow with the padded size.  A short or long stream is rejected with
+  /// [`R1CSError::InvalidCiphertextLength`].
+  pub fn verify_streaming<I>(
+    self,
+    proof: &R1CSProof,
+    k_original: usize,
+    ciphertexts: I,
+    C: &[RistrettoPoint],
+) -> Result<(), R1CSError>
+  where
+    I: IntoIterator<Item = (RistrettoPoint, RistrettoPoint)>,
+{
+    self.check_circuit_size(proof)?;
+    if k_original > self.num_inputs {
+        return Err(R1CSError::InvalidCiphertextLength);
+    }
+    let MsmParts { scalars, points, weights, c, .. } = self.msm_parts(proof, k_original, C)?;
+
+    let mut pairs = ciphertexts.into_iter();
+    let mut folded = RistrettoPoint::identity();
+    for weights_chunk in weights.chunks(STREAM_CHUNK) {
+        let chunk: Vec<(RistrettoPoint, RistrettoPoint)> =
+            pairs.by_ref().take(weights_chunk.len()).collect();
+        if chunk.len() != weights_chunk.len() {
+            return Err(R1CSError::InvalidCiphertextLength);
+        }
+        folded += RistrettoPoint::vartime_multiscalar_mul(
+            weights_chunk.iter().cloned().chain(weights_chunk.iter().map(|w| w * c)),
+            chunk.iter().map(|(C1, _)| C1).chain(chunk.iter().map(|(_, C2)| C2)),
+        );
+    }
+    if pairs.next().is_some() {
+        return Err(R1CSError::InvalidCiphertextLength);
+    }
+
+    let mega_check = RistrettoPoint::vartime_multiscalar_mul(
+        scalars.iter().chain(iter::once(&Scalar::one())),
+        points.iter().chain(iter::once(&folded)),
+    );
+    if !mega_check.is_identity() {
+        return Err(R1CSError::VerificationError);
+    }
+    Ok(())
+}
+
+  /// Runs every verification stage up to the final check for
+  /// `k_original` ciphertexts, without the ciphertexts themselves.
+  fn msm_parts(
+    mut self,
+    proof: &R1CSProof,
+    k_original: usize,
+    C: &[RistrettoPoint],
+) -> Result<MsmParts, VerificationReport> {
     // Standard Imports
-    use curve25519_dalek::traits::IsIdentity;
     use inner_product_proof::inner_product;
-    use rand::thread_rng;
-    use std::iter;
     use util;
 
     // -----------------------------------------------------------------------------
     // 1. Checks & Padding 
     // -----------------------------------------------------------------------------
-    let n = self.num_vars;
     let padded_n = self.num_inputs;
     let k_fold = proof.ipp_proof.k;
-    let pad = padded_n - n;
-
+    let report = |stage| VerificationReport {
+        stage,
+        n: padded_n,
+        k: k_fold,
+        d: proof.ipp_proof.U_vecs.len(),
+    };
+
+    if padded_n > self.n_max
+        || k_fold > self.k_max
+        || proof.ecp_batched.k > self.k_max
+        || proof.ipp_proof.U_vecs.len() > self.d_max
+        || proof.ecp_batched.A_vecs.len() > self.d_max
+        || proof.ipp_proof.a_final.len() > self.n_max
+        || proof.ecp_batched.z.len() > self.n_max
+    {
+        return Err(report(VerificationStage::SizeLimits));
+    }
 
     if self.bp_gens.gens_capacity < padded_n {
-        return Err(R1CSError::InvalidGeneratorsLength);
+        return Err(report(VerificationStage::Generators));
     }
 
-    let gens = self.bp_gens.share(0);
+    let gens = self.bp_gens.share(self.party);
 
     // -----------------------------------------------------------------------------
     // 2. Transcript Interaction
@@ -371,6 +843,8 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
     self.transcript.commit_scalar(b"ec_blinding", &proof.ec_blinding);
     self.transcript.commit_scalar(b"r_blinding", &proof.r_blinding);
 
+    // Must mirror the prover: `t_cross` before `x_ipp`, both before the
+    // IPA and the ECP challenges.
     self.transcript.commit_scalar(b"t_cross", &proof.t_cross);
     let t_cross = proof.t_cross;
 
@@ -383,7 +857,7 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
     let (s_g_cir, s_h_cir, s_Q_cir, s_P_cir, s_U_cir) = proof
         .ipp_proof
         .verification_scalars(padded_n, self.transcript)
-        .map_err(|_| R1CSError::VerificationError)?;
+        .map_err(|_| report(VerificationStage::IpaScalars))?;
 
 
     let mut U_points_decompressed_cir: Vec<RistrettoPoint> =
@@ -393,27 +867,32 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
         for i_list in 0..(2 * k_fold - 2) {
             let U_ir_point_cir = proof.ipp_proof.U_vecs[r][i_list]
                 .decompress()
-                .ok_or(R1CSError::VerificationError)?;
+                .ok_or(report(VerificationStage::Decompression))?;
             U_points_decompressed_cir.push(U_ir_point_cir);
         }
     }
 
     let y_inv = y.invert();
-    let y_inv_vec: Vec<Scalar> = util::exp_iter(y_inv).take(padded_n).collect();
+    let y_inv_vec = util::PowerTable::up_to(y_inv, padded_n);
 
+    // The weights only cover the `n` constrained variables; past them
+    // `wL`, `wR` and `wO` are zero, so the padded tail's scalars are
+    // computed directly instead of from zero-filled vectors.
     let yneg_wR: Vec<Scalar> = wR
         .into_iter()
-        .zip(y_inv_vec.iter())
+        .zip(y_inv_vec.as_slice().iter())
         .map(|(wRi, exp_y_inv)| wRi * exp_y_inv)
-        .chain(iter::repeat(Scalar::zero()).take(pad))
         .collect();
 
-    let delta = inner_product(&yneg_wR[0..n], &wL);
+    let delta = inner_product(&yneg_wR, &wL);
 
     let g_scalars: Vec<Scalar> = s_g_cir
         .iter()
-        .zip(yneg_wR.iter())
-        .map(|(s_g_i, yneg_wR_i)| s_g_i - x * yneg_wR_i * s_P_cir) 
+        .enumerate()
+        .map(|(i, s_g_i)| match yneg_wR.get(i) {
+            Some(yneg_wR_i) => s_g_i - x * yneg_wR_i * s_P_cir,
+            None => *s_g_i,
+        })
         .collect();
 
 
@@ -421,14 +900,16 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
 
     let h_scalars: Vec<Scalar> = s_h_cir
         .iter()
-        .zip(y_inv_vec.iter())
-        .zip(wL.into_iter().chain(iter::repeat(Scalar::zero()).take(pad)))
-        .zip(wO.into_iter().chain(iter::repeat(Scalar::zero()).take(pad)))
+        .zip(y_inv_vec.as_slice().iter())
         .zip(rC.iter())
-        .map(|((((s_h_i, y_inv_i), wLi), wOi), rCi)| {
+        .enumerate()
+        .map(|(i, ((s_h_i, y_inv_i), rCi))| {
             //let term1 = y_inv_i * s_h_i * b_final;
             let term1 = y_inv_i * s_h_i;
-            let term2 = (y_inv_i * (x * wLi + wOi) - Scalar::one()) * (-s_P_cir);
+            let term2 = match (wL.get(i), wO.get(i)) {
+                (Some(wLi), Some(wOi)) => (y_inv_i * (x * wLi + wOi) - Scalar::one()) * (-s_P_cir),
+                _ => s_P_cir,
+            };
             let term3 = y_inv_i * x_ipp * (-s_P_cir) * rCi;
             term1 + term2 + term3
         })
@@ -437,7 +918,7 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
     // -----------------------------------------------------------------------------
     // 4. Verification Check Setup
     // -----------------------------------------------------------------------------
-    let mut rng = self.transcript.build_rng().finalize(&mut thread_rng());
+    let mut rng = self.transcript.witness_rng(&[]);
     let r = Scalar::random(&mut rng);
 
     let xx = x * x;
@@ -463,7 +944,7 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
         + r * (xx * (wc + delta) - proof.t_x)
         - r2 * proof.tc_x;
 
-    let B_blinding_scalar = x_ipp * proof.ec_blinding * s_P_cir + proof.e_blinding * s_P_cir
+    let B_blinding_scalar = proof.e_blinding * s_P_cir
         - r2 * proof.tc_x_blinding
         - r * proof.t_x_blinding;
 
@@ -475,7 +956,7 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
     let (z_s_vec, s_P, s_A_vec) = proof
         .ecp_batched
         .verification_scalars(padded_n, self.transcript)
-        .map_err(|_| R1CSError::VerificationError)?;
+        .map_err(|_| report(VerificationStage::EcpScalars))?;
 
     // Scalars 
     let s_V_checkS = r4 * (-s_P);
@@ -485,14 +966,26 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
     let s_B_checkS = s_P * r3 * proof.r_blinding;
     let s_C0 = r3 * (-s_P);
     let s_C1 = r3 * chall_batched_ecp * (-s_P);
-    let s_B_blinding_checkS =
-        s_P * (r4 * proof.ec_blinding + r3 * chall_batched_ecp * proof.r_blinding);
-
-    // Combine scalars for Shared Points
+    // `r_blinding` opens the re-randomization of `C2_prime` under the
+    // re-encryption key, `B_blinding` unless set.
+    let s_key_checkS = s_P * r3 * chall_batched_ecp * proof.r_blinding;
+    // `ec_blinding` opens the blinding of `V[0]` and `S_prime`, which may
+    // use a generator other than `B_blinding`.
+    let s_V_blinding_gen = (x_ipp * s_P_cir + r4 * s_P) * proof.ec_blinding;
+
+    // Combine scalars for Shared Points.
+    //
+    // The IPA's final `a_final`/`b_final` and the ECP's final `z` are not
+    // compared directly, and need not be: both sub-proofs open the same
+    // commitment `V + x_prime * S_prime` over the same `G` generators
+    // (the IPA through its `x_ipp`-aggregated vector, the ECP through
+    // `z`), so binding of the vector commitment forces them onto one
+    // witness.  Their equations enter with the independent weights `1`
+    // and `r^3`, `r^4` for a verifier-random `r`, so the combined check
+    // only passes if each of them does.
     let final_scalar_V = (-x_ipp * s_P_cir) + s_V_checkS;
     let final_scalar_S_prime = (-x_ipp * s_P_cir * x_prime) + s_S_prime_checkS;
     let final_scalar_B = B_scalar + s_B_checkS;
-    let final_scalar_B_blinding = B_blinding_scalar + s_B_blinding_checkS;
 
     let final_g_scalars: Vec<Scalar> = g_scalars
         .iter()
@@ -503,15 +996,15 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
     // -----------------------------------------------------------------------------
     // 5. Final MSM Construction
     // -----------------------------------------------------------------------------
-    let k_original = C1_prime.len();
-
-    let combined_scalars: Vec<Scalar> = iter::once(-x * s_P_cir) // A_I
+    let mut combined_scalars: Vec<Scalar> = iter::once(-x * s_P_cir) // A_I
         .chain(iter::once(-x * x * s_P_cir)) // A_O
         .chain(iter::once(-x * x * x * s_P_cir)) // S
         .chain(iter::once(final_scalar_V)) // V[0] (SHARED)
         .chain(iter::once(final_scalar_S_prime)) // S_prime (SHARED)
         .chain(iter::once(final_scalar_B)) // B (SHARED)
-        .chain(iter::once(final_scalar_B_blinding)) // B_blinding (SHARED)
+        .chain(iter::once(B_blinding_scalar)) // B_blinding
+        .chain(iter::once(s_V_blinding_gen)) // V blinding generator
+        .chain(iter::once(s_key_checkS)) // re-encryption key
         .chain(final_g_scalars.into_iter()) // G vec (SHARED)
         .chain(h_scalars.into_iter()) // H vec (Unique IPA)
         .chain(s_U_cir.iter().map(|s| -s)) // U vec (Unique IPA)
@@ -522,11 +1015,12 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
         .chain(iter::once(s_S2_prime)) // S2_prime
         .chain(iter::once(s_C0)) // C[0]
         .chain(iter::once(s_C1)) // C[1]
-        .chain(z_s_vec[0..k_original].iter().map(|z| z * r3)) // C1' vec only length k_original
-        .chain(z_s_vec[0..k_original].iter().map(|z| z * r3 * chall_batched_ecp)) // C2' vec only length k_original
-        .chain(s_A_vec.iter().map(|s_A| -s_A * r4)) // A0 vec
-        .chain(s_A_vec.iter().map(|s_A| -s_A * r3)) // A1 vec
         .collect();
+    // C1' and C2' go here, weighted by `weights` and `weights * chall_batched_ecp`.
+    let split = combined_scalars.len();
+    let weights: Vec<Scalar> = z_s_vec[0..k_original].iter().map(|z| z * r3).collect(); // only length k_original
+    combined_scalars.extend(s_A_vec.iter().map(|s_A| -s_A * r4)); // A0 vec
+    combined_scalars.extend(s_A_vec.iter().map(|s_A| -s_A * r3)); // A1 vec
 
     let combined_points_iter = iter::once(proof.A_I.decompress())
         .chain(iter::once(proof.A_O.decompress()))
@@ -535,6 +1029,8 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
         .chain(iter::once(proof.S_prime.decompress())) // S_prime
         .chain(iter::once(Some(self.pc_gens.B))) // B
         .chain(iter::once(Some(self.pc_gens.B_blinding))) // B_blinding
+        .chain(iter::once(Some(self.v_blinding_gen))) // V blinding generator
+        .chain(iter::once(Some(self.reencryption_key))) // re-encryption key
         .chain(gens.G(padded_n).cloned().map(Some)) // G vec
         .chain(gens.H(padded_n).cloned().map(Some)) // H vec
         .chain(U_points_decompressed_cir.into_iter().map(Some)) // U vec
@@ -545,8 +1041,6 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
         .chain(iter::once(proof.S2_prime.decompress())) // S2_prime
         .chain(iter::once(Some(C[0]))) // C[0]
         .chain(iter::once(Some(C[1]))) // C[1]
-        .chain(C1_prime.iter().map(|c| Some(*c))) // C1'
-        .chain(C2_prime.iter().map(|c| Some(*c))) // C2'
         .chain(
             proof
                 .ecp_batched
@@ -566,18 +1060,352 @@ impl<'a, 'b> VerifierCS<'a, 'b> {
 
     let combined_points: Vec<RistrettoPoint> = combined_points_iter
         .collect::<Option<Vec<_>>>()
-        .ok_or(R1CSError::VerificationError)?;
+        .ok_or(report(VerificationStage::Decompression))?;
+
+    Ok(MsmParts {
+        scalars: combined_scalars,
+        points: combined_points,
+        split,
+        weights,
+        c: chall_batched_ecp,
+        report: report(VerificationStage::FinalCheck),
+    })
+}
+  
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // -----------------------------------------------------------------------------
-    // 6. Final Execution
-    // -----------------------------------------------------------------------------
-    let mega_check = RistrettoPoint::vartime_multiscalar_mul(combined_scalars, combined_points);
+    use rand::thread_rng;
+    use r1cs::{ProofConfig, ShuffleProof, ShuffleStatement, ShuffleWitness};
+
+    struct Statement {
+        input: Vec<Scalar>,
+        output_commitment: CompressedRistretto,
+        C1: Vec<RistrettoPoint>,
+        C2: Vec<RistrettoPoint>,
+        C1_prime: Vec<RistrettoPoint>,
+        C2_prime: Vec<RistrettoPoint>,
+        C: Vec<RistrettoPoint>,
+    }
 
-    if !mega_check.is_identity() {
-        return Err(R1CSError::VerificationError);
+    impl Statement {
+        fn shuffle_statement(&self) -> ShuffleStatement {
+            ShuffleStatement {
+                input: self.input.clone(),
+                C1: self.C1.clone(),
+                C2: self.C2.clone(),
+                C1_prime: self.C1_prime.clone(),
+                C2_prime: self.C2_prime.clone(),
+            }
+        }
     }
 
-    Ok(())
+    /// Proves an identity shuffle of `n` re-randomized ciphertexts.
+    fn shuffle_proof(pc_gens: &PedersenGens, bp_gens: &BulletproofGens, n: usize) -> (R1CSProof, Statement) {
+        let mut rng = thread_rng();
+        let input: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let C1: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+        let C2: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+
+        let mut C1_prime = Vec::with_capacity(n);
+        let mut C2_prime = Vec::with_capacity(n);
+        let mut C = vec![RistrettoPoint::default(); 2];
+        let mut r_prime = Scalar::zero();
+        for i in 0..n {
+            let r_i = Scalar::random(&mut rng);
+            C1_prime.push(C1[i] + pc_gens.B * r_i);
+            C2_prime.push(C2[i] + pc_gens.B_blinding * r_i);
+            r_prime -= r_i * input[i];
+            C[0] += C1[i] * input[i];
+            C[1] += C2[i] * input[i];
+        }
+
+        let mut statement = Statement {
+            input, output_commitment: CompressedRistretto::default(), C1, C2, C1_prime, C2_prime, C,
+        };
+        let witness = ShuffleWitness::new(statement.shuffle_statement(), statement.input.clone(), r_prime);
+        let mut transcript = Transcript::new(b"VerificationReportTest");
+        let (proof, output_commitment) = ShuffleProof::prove(
+            pc_gens, bp_gens, &mut transcript, &[], &witness, &ProofConfig::new(n, 2, 3),
+        ).unwrap();
+        statement.output_commitment = output_commitment;
+
+        (proof, statement)
+    }
+
+    fn diagnose(
+        pc_gens: &PedersenGens,
+        bp_gens: &BulletproofGens,
+        proof: &R1CSProof,
+        statement: &Statement,
+        d_max: usize,
+    ) -> Result<(), VerificationReport> {
+        let n = statement.input.len();
+        let mut transcript = Transcript::new(b"VerificationReportTest");
+        ShuffleProof::append_statement(&mut transcript, &statement.input);
+        ShuffleProof::append_nonce(&mut transcript, &[]);
+
+        let max = usize::max_value();
+        let mut verifier = Verifier::with_limits(bp_gens, pc_gens, &mut transcript, max, max, d_max);
+        let output_vars = verifier.commit_vec(statement.output_commitment, n);
+        let mut cs = verifier.finalize_inputs();
+        ShuffleProof::gadget(&mut cs, &output_vars, &statement.input, n);
+
+        cs.verify_diagnostic(proof, &statement.C1_prime, &statement.C2_prime, &statement.C)
+    }
+
+    #[test]
+    fn dumped_scalars_reproduce_final_msm() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(8, 1);
+        let (proof, statement) = shuffle_proof(&pc_gens, &bp_gens, 8);
+
+        let mut transcript = Transcript::new(b"VerificationReportTest");
+        let cs = ShuffleProof::verifier_cs(
+            &pc_gens, &bp_gens, &mut transcript, &[], statement.output_commitment,
+            &statement.shuffle_statement(), None,
+        ).unwrap();
+        let dump = cs
+            .verification_scalars_dump(&proof, &statement.C1_prime, &statement.C2_prime, &statement.C)
+            .unwrap();
+
+        assert_eq!(dump.g_scalars.len(), 8);
+        assert_eq!(dump.h_scalars.len(), 8);
+        assert_eq!(dump.z_s_vec.len(), 16);
+        let scalars = dump.scalars();
+        assert_eq!(scalars.len(), dump.points.len());
+        assert!(RistrettoPoint::vartime_multiscalar_mul(&scalars, &dump.points).is_identity());
+    }
+
+    fn failing_stage(
+        bp_gens: &BulletproofGens,
+        d_max: usize,
+        tamper: fn(&mut R1CSProof),
+    ) -> VerificationStage {
+        let pc_gens = PedersenGens::default();
+        let (mut proof, statement) = shuffle_proof(&pc_gens, &BulletproofGens::new(8, 1), 8);
+        tamper(&mut proof);
+        diagnose(&pc_gens, bp_gens, &proof, &statement, d_max).unwrap_err().stage
+    }
+
+    #[test]
+    fn valid_proof_has_no_report() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(8, 1);
+        let (proof, statement) = shuffle_proof(&pc_gens, &bp_gens, 8);
+        assert_eq!(diagnose(&pc_gens, &bp_gens, &proof, &statement, 3), Ok(()));
+    }
+
+    #[test]
+    fn report_carries_proof_sizes() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(8, 1);
+        let (proof, statement) = shuffle_proof(&pc_gens, &bp_gens, 8);
+        assert_eq!(
+            diagnose(&pc_gens, &bp_gens, &proof, &statement, 2),
+            Err(VerificationReport { stage: VerificationStage::SizeLimits, n: 8, k: 2, d: 3 })
+        );
+    }
+
+    #[test]
+    fn report_stage_per_failure_mode() {
+        let bp_gens = BulletproofGens::new(8, 1);
+        assert_eq!(
+            failing_stage(&BulletproofGens::new(4, 1), 3, |_| {}),
+            VerificationStage::Generators
+        );
+        assert_eq!(
+            failing_stage(&bp_gens, 3, |p| p.ipp_proof.a_final.push(Scalar::zero())),
+            VerificationStage::IpaScalars
+        );
+        assert_eq!(
+            failing_stage(&bp_gens, 3, |p| p.ecp_batched.z.push(Scalar::zero())),
+            VerificationStage::EcpScalars
+        );
+        assert_eq!(
+            failing_stage(&bp_gens, 3, |p| p.A_I = CompressedRistretto([0xff; 32])),
+            VerificationStage::Decompression
+        );
+        assert_eq!(
+            failing_stage(&bp_gens, 3, |p| p.t_x += Scalar::one()),
+            VerificationStage::FinalCheck
+        );
+    }
+
+    #[test]
+    fn mismatched_ecp_and_ipp_final_vectors_are_rejected() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(8, 1);
+        let (proof, statement) = shuffle_proof(&pc_gens, &bp_gens, 8);
+        let (other, _) = shuffle_proof(&pc_gens, &bp_gens, 8);
+        assert_eq!(proof.ecp_batched.z.len(), proof.ipp_proof.a_final.len());
+
+        // `z` taken from another valid proof of the same shape.
+        let mut transplanted = proof.clone();
+        transplanted.ecp_batched.z = other.ecp_batched.z.clone();
+        assert_eq!(
+            diagnose(&pc_gens, &bp_gens, &transplanted, &statement, 3).unwrap_err().stage,
+            VerificationStage::FinalCheck
+        );
+
+        // `z` shifted away from the witness `a_final` folds.
+        let mut shifted = proof.clone();
+        shifted.ecp_batched.z[0] += Scalar::one();
+        assert_eq!(
+            diagnose(&pc_gens, &bp_gens, &shifted, &statement, 3).unwrap_err().stage,
+            VerificationStage::FinalCheck
+        );
+
+        // And `a_final` shifted under an untouched `z`.
+        let mut shifted = proof.clone();
+        shifted.ipp_proof.a_final[0] += Scalar::one();
+        assert_eq!(
+            diagnose(&pc_gens, &bp_gens, &shifted, &statement, 3).unwrap_err().stage,
+            VerificationStage::FinalCheck
+        );
+    }
+
+    /// Draws `(x_ipp, chall_batched_ecp)` with `t_cross` committed either
+    /// before the aggregation challenge (the protocol order) or only after
+    /// the ECP challenge.
+    fn aggregation_challenges(t_cross: &Scalar, t_cross_first: bool) -> (Scalar, Scalar) {
+        let mut transcript = Transcript::new(b"AggregationOrderTest");
+        if t_cross_first {
+            transcript.commit_scalar(b"t_cross", t_cross);
+        }
+        let x_ipp = transcript.challenge_scalar(b"x_ipp");
+        let chall_batched_ecp = transcript.challenge_scalar(b"chall_batched_ecp");
+        if !t_cross_first {
+            transcript.commit_scalar(b"t_cross", t_cross);
+        }
+        (x_ipp, chall_batched_ecp)
+    }
+
+    #[test]
+    fn x_ipp_binds_t_cross_only_when_committed_first() {
+        let t_cross = Scalar::from(7u64);
+        let other = Scalar::from(8u64);
+
+        let (x_ipp, chall_ecp) = aggregation_challenges(&t_cross, true);
+        let (x_ipp_other, chall_ecp_other) = aggregation_challenges(&other, true);
+        assert_ne!(x_ipp, x_ipp_other);
+        assert_ne!(chall_ecp, chall_ecp_other);
+
+        // Committed after the ECP challenge, `t_cross` no longer affects
+        // either challenge.
+        let (late, late_ecp) = aggregation_challenges(&t_cross, false);
+        assert_eq!((late, late_ecp), aggregation_challenges(&other, false));
+        assert_ne!(late, x_ipp);
+    }
+
+    #[test]
+    fn tampered_t_cross_fails_verification() {
+        assert_eq!(
+            failing_stage(&BulletproofGens::new(8, 1), 3, |p| p.t_cross += Scalar::one()),
+            VerificationStage::FinalCheck
+        );
+    }
+
+    #[test]
+    fn input_count_mismatch_is_reported() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(16, 1);
+        let (proof, statement) = shuffle_proof(&pc_gens, &bp_gens, 8);
+
+        // k = 2 and d = 3 fold 8 inputs down to 1, but 16 down to 2.
+        let mut transcript = Transcript::new(b"VerificationReportTest");
+        ShuffleProof::append_statement(&mut transcript, &statement.input);
+        ShuffleProof::append_nonce(&mut transcript, &[]);
+        let mut verifier = Verifier::new(&bp_gens, &pc_gens, &mut transcript);
+        verifier.commit_vec(statement.output_commitment, 16);
+        let cs = verifier.finalize_inputs();
+
+        assert_eq!(
+            cs.check_input_count(&proof),
+            Err(R1CSError::InputCountMismatch { verifier: 16, proof: 8 })
+        );
+        assert_eq!(
+            cs.verify(&proof, &statement.C1_prime, &statement.C2_prime, &statement.C),
+            Err(R1CSError::InputCountMismatch { verifier: 16, proof: 8 })
+        );
+    }
+
+    #[test]
+    fn circuit_size_mismatch_is_reported() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(8, 1);
+        let (proof, statement) = shuffle_proof(&pc_gens, &bp_gens, 8);
+        assert_eq!(proof.implied_circuit_size(), 8);
+
+        // 7 inputs fold down to 1 like 8 do, but no padded witness has 7.
+        let mut transcript = Transcript::new(b"VerificationReportTest");
+        ShuffleProof::append_statement(&mut transcript, &statement.input);
+        ShuffleProof::append_nonce(&mut transcript, &[]);
+        let mut verifier = Verifier::new(&bp_gens, &pc_gens, &mut transcript);
+        verifier.commit_vec(statement.output_commitment, 7);
+        let cs = verifier.finalize_inputs();
+
+        assert_eq!(cs.check_input_count(&proof), Ok(()));
+        assert_eq!(
+            cs.check_circuit_size(&proof),
+            Err(R1CSError::CircuitSizeMismatch { verifier: 7, implied: 8 })
+        );
+        assert_eq!(
+            cs.verify(&proof, &statement.C1_prime, &statement.C2_prime, &statement.C),
+            Err(R1CSError::CircuitSizeMismatch { verifier: 7, implied: 8 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "challenge_scalar called before finalize_inputs")]
+    fn early_challenge_is_caught() {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(4, 1);
+        let mut transcript = Transcript::new(b"EarlyChallengeTest");
+        let mut verifier = Verifier::new(&bp_gens, &pc_gens, &mut transcript);
+        verifier.commit_vec(pc_gens.B.compress(), 4);
+        verifier.cs.challenge_scalar(b"k-scalar shuffle challenge");
+    }
+
+    #[test]
+    fn next_power_of_k_is_exact_at_boundaries() {
+        let max = usize::max_value();
+        for &k in &[2usize, 3, 10, 1 << 20] {
+            assert_eq!(next_power_of_k(0, k), Ok(1));
+            assert_eq!(next_power_of_k(1, k), Ok(1));
+
+            // Every power k^x that fits, and its neighbours.
+            let mut power = k;
+            let mut prev = 1;
+            loop {
+                assert_eq!(next_power_of_k(power, k), Ok(power));
+                assert_eq!(next_power_of_k(prev + 1, k), Ok(power));
+                if power - 1 > prev {
+                    assert_eq!(next_power_of_k(power - 1, k), Ok(power));
+                }
+                match power.checked_mul(k) {
+                    Some(next) => {
+                        prev = power;
+                        power = next;
+                    }
+                    None => break,
+                }
+            }
+
+            // Past the largest power that fits, no power exists.
+            assert_eq!(next_power_of_k(power + 1, k), Err(R1CSError::FormatError));
+            assert_eq!(next_power_of_k(max, k).is_ok(), max == power);
+        }
+
+        // Near `2^62` and just below `usize::MAX / k`, beyond `f64` precision.
+        let n = (1usize << 62) + 1;
+        assert_eq!(next_power_of_k(n, 2), Ok(1 << 63));
+        assert_eq!(next_power_of_k(max / 2 - 1, 2), Ok(1 << 63));
+        assert_eq!(next_power_of_k(max / 3 - 1, 3), Ok(3usize.pow(40)));
+
+        assert_eq!(next_power_of_k(8, 1), Err(R1CSError::InvalidFoldParameters));
+        assert_eq!(next_power_of_k(8, 0), Err(R1CSError::InvalidFoldParameters));
+    }
 }
-  
-}
\ No newline at end of file
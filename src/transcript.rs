@@ -1,9 +1,291 @@
-//! Defines a `TranscriptProtocol` trait for using a Merlin transcript.
+//! Defines a `TranscriptProtocol` trait for using a Merlin transcript,
+//! or any other Fiat-Shamir oracle implementing `ProofTranscript`.
 
 use byteorder::{ByteOrder, LittleEndian};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
-use merlin::Transcript;
+use merlin::{Transcript, TranscriptRng};
+use rand::rngs::StdRng;
+use rand::{thread_rng, CryptoRng, RngCore, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use errors::ProofError;
+
+/// The Fiat-Shamir operations the k-ary inner-product, consistency and
+/// shuffle proofs need from a transcript.
+///
+/// It is implemented for Merlin's `Transcript`, which is what the rest of
+/// the crate uses; a protocol built on another hash or transcript
+/// abstraction can implement it to drive
+/// [`K_BulletProof`](::prelude::K_BulletProof) and the shuffle proof with
+/// its own oracle.  The prover and the verifier must of course use the
+/// same implementation.
+pub trait ProofTranscript {
+    /// The RNG returned by [`ProofTranscript::witness_rng`].
+    type Rng: RngCore + CryptoRng;
+
+    /// Absorbs `message` under `label`.
+    fn append_message(&mut self, label: &'static [u8], message: &[u8]);
+
+    /// Fills `dest` with challenge bytes bound to everything absorbed so
+    /// far, and absorbs the challenge itself.
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]);
+
+    /// Returns an RNG seeded from the transcript state, the secret
+    /// `witness` entries and fresh system randomness, for the prover's
+    /// blinding factors and the verifier's random weights.
+    fn witness_rng(&self, witness: &[(&'static [u8], &[u8])]) -> Self::Rng;
+}
+
+impl ProofTranscript for Transcript {
+    type Rng = TranscriptRng;
+
+    fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        Transcript::append_message(self, label, message);
+    }
+
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        Transcript::challenge_bytes(self, label, dest);
+    }
+
+    fn witness_rng(&self, witness: &[(&'static [u8], &[u8])]) -> TranscriptRng {
+        build_witness_rng(self, witness, &mut thread_rng())
+    }
+}
+
+/// Seeds a Merlin RNG from `transcript`, the `witness` entries and `rng`.
+fn build_witness_rng<R: RngCore + CryptoRng>(
+    transcript: &Transcript,
+    witness: &[(&'static [u8], &[u8])],
+    rng: &mut R,
+) -> TranscriptRng {
+    let mut builder = transcript.build_rng();
+    for &(label, bytes) in witness {
+        builder = builder.commit_witness_bytes(label, bytes);
+    }
+    builder.finalize(rng)
+}
+
+/// A Merlin transcript that records every operation, so that its state
+/// can be exported from one machine and imported on another, for proofs
+/// whose steps run on different machines.
+///
+/// Merlin's `Transcript` has no serializable state, so the state is the
+/// log of operations instead: [`TranscriptState::from_bytes`] replays it
+/// onto a fresh transcript with the same label, which then produces the
+/// same challenges as the original.  A proof finished on the imported
+/// state is exactly what a single-machine run over a plain `Transcript`
+/// would produce, and verifies against one.
+///
+/// The state holds only what was appended to the transcript, all of it
+/// public.  The witness, such as the assignments of a
+/// [`ProverCS`](::r1cs::ProverCS), must reach the other machine
+/// separately.
+pub struct TranscriptState {
+    transcript: Transcript,
+    label: &'static [u8],
+    log: Vec<TranscriptOp>,
+    witness_seed: Option<[u8; 32]>,
+}
+
+/// One operation recorded by a [`TranscriptState`].
+enum TranscriptOp {
+    Append(&'static [u8], Vec<u8>),
+    Challenge(&'static [u8], usize),
+}
+
+impl TranscriptState {
+    /// Creates an empty recording transcript, as `Transcript::new(label)`.
+    pub fn new(label: &'static [u8]) -> Self {
+        TranscriptState {
+            transcript: Transcript::new(label),
+            label,
+            log: Vec::new(),
+            witness_seed: None,
+        }
+    }
+
+    /// Replaces the system randomness in [`ProofTranscript::witness_rng`]
+    /// with a fixed `seed`, so that proving on this state is
+    /// deterministic, for tests comparing the proof bytes of a split run
+    /// with a single-machine one.
+    ///
+    /// The seed is not part of the exported state and must be set again
+    /// after [`TranscriptState::from_bytes`].  Blinding factors drawn this
+    /// way depend only on the transcript and the witness, so never use a
+    /// fixed seed outside of tests.
+    pub fn with_witness_seed(mut self, seed: [u8; 32]) -> Self {
+        self.witness_seed = Some(seed);
+        self
+    }
+
+    /// Exports the state as the transcript label followed by every
+    /// operation so far, each tagged `0` for a message (label, message)
+    /// or `1` for a challenge (label, length), with `u64` little-endian
+    /// lengths.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_bytes(&mut buf, self.label);
+        for op in self.log.iter() {
+            match op {
+                TranscriptOp::Append(label, message) => {
+                    buf.push(0);
+                    write_bytes(&mut buf, label);
+                    write_bytes(&mut buf, message);
+                }
+                TranscriptOp::Challenge(label, len) => {
+                    buf.push(1);
+                    write_bytes(&mut buf, label);
+                    buf.extend_from_slice(&le_u64(*len as u64));
+                }
+            }
+        }
+        buf
+    }
+
+    /// Imports a state exported with [`TranscriptState::to_bytes`],
+    /// replaying it onto a fresh transcript.
+    ///
+    /// Merlin takes `'static` labels, so each distinct label is kept for
+    /// the lifetime of the thread; only import states from the machines
+    /// taking part in the proof.
+    pub fn from_bytes(slice: &[u8]) -> Result<TranscriptState, ProofError> {
+        let mut reader = slice;
+        let mut state = TranscriptState::new(intern_label(read_bytes(&mut reader)?));
+        while let Some((&tag, rest)) = reader.split_first() {
+            reader = rest;
+            let label = intern_label(read_bytes(&mut reader)?);
+            match tag {
+                0 => {
+                    let message = read_bytes(&mut reader)?;
+                    state.append_message(label, message);
+                }
+                1 => {
+                    let len = read_u64(&mut reader)? as usize;
+                    if len > 64 {
+                        return Err(ProofError::FormatError);
+                    }
+                    state.challenge_bytes(label, &mut [0u8; 64][..len]);
+                }
+                _ => return Err(ProofError::FormatError),
+            }
+        }
+        Ok(state)
+    }
+}
+
+impl ProofTranscript for TranscriptState {
+    type Rng = TranscriptRng;
+
+    fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        self.transcript.append_message(label, message);
+        self.log.push(TranscriptOp::Append(label, message.to_vec()));
+    }
+
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        self.transcript.challenge_bytes(label, dest);
+        self.log.push(TranscriptOp::Challenge(label, dest.len()));
+    }
+
+    fn witness_rng(&self, witness: &[(&'static [u8], &[u8])]) -> TranscriptRng {
+        match self.witness_seed {
+            Some(seed) => build_witness_rng(&self.transcript, witness, &mut StdRng::from_seed(seed)),
+            None => self.transcript.witness_rng(witness),
+        }
+    }
+}
+
+/// A Merlin transcript that logs the label and length of every message
+/// and challenge, for checking the crate's transcript schedule against a
+/// specification or another implementation.
+///
+/// Message contents are not logged, since commitments and blinded values
+/// differ from run to run; the log of a proof is determined by the proof's
+/// sizes alone, and can be compared against a golden file.
+#[cfg(feature = "debug")]
+pub struct RecordingTranscript {
+    transcript: Transcript,
+    log: String,
+}
+
+#[cfg(feature = "debug")]
+impl RecordingTranscript {
+    /// Creates an empty recording transcript, as `Transcript::new(label)`.
+    pub fn new(label: &'static [u8]) -> Self {
+        RecordingTranscript {
+            transcript: Transcript::new(label),
+            log: format!("transcript {}\n", String::from_utf8_lossy(label)),
+        }
+    }
+
+    /// Returns the log so far, one line per operation: `append <label>
+    /// <length>` for a message and `challenge <label> <length>` for a
+    /// challenge.
+    pub fn log(&self) -> &str {
+        &self.log
+    }
+}
+
+#[cfg(feature = "debug")]
+impl ProofTranscript for RecordingTranscript {
+    type Rng = TranscriptRng;
+
+    fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        self.transcript.append_message(label, message);
+        self.log += &format!("append {} {}\n", String::from_utf8_lossy(label), message.len());
+    }
+
+    fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        self.transcript.challenge_bytes(label, dest);
+        self.log += &format!("challenge {} {}\n", String::from_utf8_lossy(label), dest.len());
+    }
+
+    fn witness_rng(&self, witness: &[(&'static [u8], &[u8])]) -> TranscriptRng {
+        self.transcript.witness_rng(witness)
+    }
+}
+
+thread_local! {
+    static LABELS: RefCell<HashSet<&'static [u8]>> = RefCell::new(HashSet::new());
+}
+
+/// Returns a `'static` copy of `label`, allocating it once per thread.
+fn intern_label(label: &[u8]) -> &'static [u8] {
+    LABELS.with(|labels| {
+        let mut labels = labels.borrow_mut();
+        if let Some(interned) = labels.get(label) {
+            return *interned;
+        }
+        let interned: &'static [u8] = Box::leak(label.to_vec().into_boxed_slice());
+        labels.insert(interned);
+        interned
+    })
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&le_u64(bytes.len() as u64));
+    buf.extend_from_slice(bytes);
+}
+
+fn read_u64(reader: &mut &[u8]) -> Result<u64, ProofError> {
+    if reader.len() < 8 {
+        return Err(ProofError::FormatError);
+    }
+    let (value, rest) = reader.split_at(8);
+    *reader = rest;
+    Ok(LittleEndian::read_u64(value))
+}
+
+fn read_bytes<'s>(reader: &mut &'s [u8]) -> Result<&'s [u8], ProofError> {
+    let len = read_u64(reader)?;
+    if (reader.len() as u64) < len {
+        return Err(ProofError::FormatError);
+    }
+    let (bytes, rest) = reader.split_at(len as usize);
+    *reader = rest;
+    Ok(bytes)
+}
 
 pub trait TranscriptProtocol {
     /// Commit a domain separator for an `n`-bit, `m`-party range proof.
@@ -28,32 +310,32 @@ fn le_u64(value: u64) -> [u8; 8] {
     value_bytes
 }
 
-impl TranscriptProtocol for Transcript {
+impl<T: ProofTranscript> TranscriptProtocol for T {
     fn rangeproof_domain_sep(&mut self, n: u64, m: u64) {
-        self.commit_bytes(b"dom-sep", b"rangeproof v1");
-        self.commit_bytes(b"n", &le_u64(n));
-        self.commit_bytes(b"m", &le_u64(m));
+        self.append_message(b"dom-sep", b"rangeproof v1");
+        self.append_message(b"n", &le_u64(n));
+        self.append_message(b"m", &le_u64(m));
     }
 
     fn innerproduct_domain_sep(&mut self, n: u64) {
-        self.commit_bytes(b"dom-sep", b"ipp v1");
-        self.commit_bytes(b"n", &le_u64(n));
+        self.append_message(b"dom-sep", b"ipp v1");
+        self.append_message(b"n", &le_u64(n));
     }
 
     fn r1cs_domain_sep(&mut self) {
-        self.commit_bytes(b"dom-sep", b"r1cs v1");
+        self.append_message(b"dom-sep", b"r1cs v1");
     }
 
     fn commit_u64(&mut self, label: &'static [u8], n: u64) {
-        self.commit_bytes(label, &le_u64(n));
+        self.append_message(label, &le_u64(n));
     }
 
     fn commit_scalar(&mut self, label: &'static [u8], scalar: &Scalar) {
-        self.commit_bytes(label, scalar.as_bytes());
+        self.append_message(label, scalar.as_bytes());
     }
 
     fn commit_point(&mut self, label: &'static [u8], point: &CompressedRistretto) {
-        self.commit_bytes(label, point.as_bytes());
+        self.append_message(label, point.as_bytes());
     }
 
     fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
@@ -3,7 +3,57 @@
 
 use clear_on_drop::clear::Clear;
 use curve25519_dalek::scalar::Scalar;
+use errors::ProofError;
 use inner_product_proof::inner_product;
+#[cfg(feature = "yoloproofs")]
+use std::ops::Index;
+
+#[cfg(feature = "parallel")]
+pub use rayon::iter::ParallelIterator as MaybeParallelIterator;
+#[cfg(not(feature = "parallel"))]
+pub use core::iter::Iterator as MaybeParallelIterator;
+#[cfg(feature = "parallel")]
+pub use rayon::iter::IndexedParallelIterator as MaybeIndexedParallelIterator;
+#[cfg(not(feature = "parallel"))]
+pub use core::iter::Iterator as MaybeIndexedParallelIterator;
+
+/// Iterates over `$e` on the rayon thread pool when the `parallel` feature
+/// is enabled, and sequentially otherwise.
+///
+/// This and `MaybeParallelIterator`, which call sites import to get the
+/// adaptor methods in either build, are the only places the
+/// parallel/sequential split should live: call sites write
+/// `maybe_par_iter!(0..n).map(..).collect()` and get the same ordered
+/// result in both builds.
+macro_rules! maybe_par_iter {
+    ($e:expr) => {{
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::iter::IntoParallelIterator;
+            $e.into_par_iter()
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            $e.into_iter()
+        }
+    }};
+}
+
+/// Like `maybe_par_iter!`, but mutably borrows the elements of the slice
+/// or `Vec` `$e`, so results can be written into a reused buffer.
+macro_rules! maybe_par_iter_mut {
+    ($e:expr) => {{
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::iter::IntoParallelRefMutIterator;
+            $e.par_iter_mut()
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            $e.iter_mut()
+        }
+    }};
+}
 
 /// Represents a degree-1 vector polynomial \\(\mathbf{a} + \mathbf{b} \cdot x\\).
 pub struct VecPoly1(pub Vec<Scalar>, pub Vec<Scalar>);
@@ -61,6 +111,42 @@ pub fn exp_iter(x: Scalar) -> ScalarExp {
     ScalarExp { x, next_exp_x }
 }
 
+/// The powers \\(1, x, x^2, \ldots, x^{n-1}\\) of a scalar, computed
+/// once so that every use with the same base shares them.
+///
+/// The prover reads \\(y^i\\) and \\(y^{-i}\\) both while building
+/// its polynomials and again when aggregating, so it builds a table for
+/// each instead of re-running `exp_iter`.
+#[cfg(feature = "yoloproofs")]
+pub struct PowerTable {
+    powers: Vec<Scalar>,
+}
+
+#[cfg(feature = "yoloproofs")]
+impl PowerTable {
+    /// Computes the first `n` powers of `x`, the same values as
+    /// `exp_iter(x).take(n)`.
+    pub fn up_to(x: Scalar, n: usize) -> PowerTable {
+        PowerTable {
+            powers: exp_iter(x).take(n).collect(),
+        }
+    }
+
+    /// Returns every power in the table, \\(x^0\\) first.
+    pub fn as_slice(&self) -> &[Scalar] {
+        &self.powers
+    }
+}
+
+#[cfg(feature = "yoloproofs")]
+impl Index<usize> for PowerTable {
+    type Output = Scalar;
+
+    fn index(&self, i: usize) -> &Scalar {
+        &self.powers[i]
+    }
+}
+
 pub fn add_vec(a: &[Scalar], b: &[Scalar]) -> Vec<Scalar> {
     let mut out = Vec::new();
     if a.len() != b.len() {
@@ -255,6 +341,19 @@ fn sum_of_powers_slow(x: &Scalar, n: usize) -> Scalar {
     exp_iter(*x).take(n).sum()
 }
 
+/// Inverts every element of `inputs` in place, like `Scalar::batch_invert`,
+/// and returns the inverse of their product.
+///
+/// `batch_invert` cannot invert zero and silently corrupts the whole batch
+/// if one is present, so this returns a `VerificationError` instead,
+/// leaving `inputs` untouched.
+pub fn checked_batch_invert(inputs: &mut [Scalar]) -> Result<Scalar, ProofError> {
+    if inputs.iter().any(|x| *x == Scalar::zero()) {
+        return Err(ProofError::VerificationError);
+    }
+    Ok(Scalar::batch_invert(inputs))
+}
+
 /// Given `data` with `len >= 32`, return the first 32 bytes.
 pub fn read32(data: &[u8]) -> [u8; 32] {
     let mut buf32 = [0u8; 32];
@@ -276,6 +375,19 @@ mod tests {
         assert_eq!(exp_2[3], Scalar::from(8u64));
     }
 
+    #[test]
+    #[cfg(feature = "yoloproofs")]
+    fn power_table_matches_exp_iter() {
+        let base = Scalar::from(7u64).invert();
+        for &n in &[0, 1, 2, 13] {
+            let table = PowerTable::up_to(base, n);
+            let expected: Vec<Scalar> = exp_iter(base).take(n).collect();
+            assert_eq!(table.as_slice(), &expected[..]);
+        }
+        let table = PowerTable::up_to(base, 4);
+        assert_eq!(table[3], base * base * base);
+    }
+
     #[test]
     fn test_inner_product() {
         let a = vec![
@@ -389,4 +501,40 @@ mod tests {
         assert_eq!(v.1, Scalar::zero());
         assert_eq!(v.2, Scalar::zero());
     }
+
+    #[test]
+    fn checked_batch_invert_rejects_zero() {
+        let mut xs = vec![Scalar::from(2u64), Scalar::from(3u64), Scalar::from(5u64)];
+        let allinv = checked_batch_invert(&mut xs).unwrap();
+        assert_eq!(allinv, Scalar::from(30u64).invert());
+        assert_eq!(xs[1], Scalar::from(3u64).invert());
+
+        let mut with_zero = vec![Scalar::from(2u64), Scalar::zero()];
+        assert_eq!(checked_batch_invert(&mut with_zero), Err(ProofError::VerificationError));
+        assert_eq!(with_zero, vec![Scalar::from(2u64), Scalar::zero()]);
+    }
+
+    #[cfg(feature = "yoloproofs")]
+    #[test]
+    fn special_inner_product_matches_evaluated_vectors() {
+        use rand::thread_rng;
+
+        // Shaped like the prover's l(X) and r(X): l.0 and r.2 are zero.
+        let mut rng = thread_rng();
+        let n = 5;
+        let mut l = VecPoly3::zero(n);
+        let mut r = VecPoly3::zero(n);
+        for i in 0..n {
+            l.1[i] = Scalar::random(&mut rng);
+            l.2[i] = Scalar::random(&mut rng);
+            l.3[i] = Scalar::random(&mut rng);
+            r.0[i] = Scalar::random(&mut rng);
+            r.1[i] = Scalar::random(&mut rng);
+            r.3[i] = Scalar::random(&mut rng);
+        }
+
+        let t = VecPoly3::special_inner_product(&l, &r);
+        let x = Scalar::random(&mut rng);
+        assert_eq!(inner_product(&l.eval(x), &r.eval(x)), t.eval(x));
+    }
 }
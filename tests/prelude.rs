@@ -0,0 +1,112 @@
+#![allow(non_snake_case)]
+extern crate bulletproofs;
+extern crate curve25519_dalek;
+extern crate merlin;
+extern crate rand;
+
+use bulletproofs::prelude::*;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+
+#[allow(dead_code)]
+fn proof_types_are_nameable(
+    _: &InnerProductProof,
+    _: &K_BulletProof,
+    _: &RoundVerifier,
+    _: &batched_eCP,
+    _: &RangeProof,
+    _: &R1CSProof,
+    _: Option<ProofError>,
+    _: Option<R1CSError>,
+    _: Option<PermutationWitness>,
+    _: Option<ShuffleWitness<ComponentStatement>>,
+    _: Option<ShuffleClaim<StreamedStatement>>,
+) {
+}
+
+#[allow(dead_code)]
+fn gadget_is_generic<CS: ConstraintSystem>(cs: &mut CS, x: &[Variable], y: &[Scalar]) {
+    ShuffleProof::gadget(cs, x, y, x.len());
+    cs.constrain(LinearCombination::from(x[0]) - x[0]);
+}
+
+#[test]
+fn prelude_covers_a_shuffle_proof() {
+    let pc_gens = PedersenGens::default();
+    let bp_gens = BulletproofGens::new(4, 1);
+    let input: Vec<Scalar> = (1..5u64).map(Scalar::from).collect();
+    let C: Vec<RistrettoPoint> = (0..4).map(|i| pc_gens.commit(Scalar::from(i as u64 + 1), Scalar::one())).collect();
+
+    let statement = ShuffleStatement {
+        input: input.clone(), C1: C.clone(), C2: C.clone(), C1_prime: C.clone(), C2_prime: C,
+    };
+    let witness = ShuffleWitness::new(statement, input, Scalar::zero());
+
+    let config = ProofConfig::new(4, 2, 1);
+    let mut transcript = Transcript::new(b"PreludeTest");
+    let (proof, _) = ShuffleProof::prove(&pc_gens, &bp_gens, &mut transcript, &[], &witness, &config).unwrap();
+
+    assert!(config.matches(&proof));
+
+    let mut transcript = Transcript::new(b"PreludeTest");
+    let _ = Prover::new(&bp_gens, &pc_gens, &mut transcript);
+    let mut transcript = Transcript::new(b"PreludeTest");
+    let _ = Verifier::new(&bp_gens, &pc_gens, &mut transcript);
+}
+
+#[test]
+fn prelude_covers_a_proof_over_scaled_generators() {
+    let n = 4;
+    let mut rng = rand::thread_rng();
+    let G: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+    let H: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+    let Q = RistrettoPoint::random(&mut rng);
+    let a: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+    let b: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+    let factors: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+
+    // The proof is made against `H'_i = factors_i * H_i`, and checked
+    // against `H` alone.
+    let H_prime: Vec<RistrettoPoint> = H.iter().zip(factors.iter()).map(|(H_i, f_i)| H_i * f_i).collect();
+    let c: Scalar = a.iter().zip(b.iter()).map(|(a_i, b_i)| a_i * b_i).sum();
+    let P = G.iter().zip(a.iter()).map(|(G_i, a_i)| G_i * a_i).sum::<RistrettoPoint>()
+        + H_prime.iter().zip(b.iter()).map(|(H_i, b_i)| H_i * b_i).sum::<RistrettoPoint>()
+        + Q * c;
+
+    let mut transcript = Transcript::new(b"PreludeTest");
+    let proof = K_BulletProof::create(&mut transcript, 2, &G, &H_prime, Q, &a, &b, 2);
+
+    let mut transcript = Transcript::new(b"PreludeTest");
+    assert!(proof.verify_with_factors(&mut transcript, &factors, &G, &H, &Q, &P).is_ok());
+}
+
+#[test]
+fn prelude_covers_a_compressed_commitment_check() {
+    let n = 4;
+    let mut rng = rand::thread_rng();
+    let G: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+    let H: Vec<RistrettoPoint> = (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+    let Q = RistrettoPoint::random(&mut rng);
+    let a: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+    let b: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+    let ones = vec![Scalar::one(); n];
+
+    let c: Scalar = a.iter().zip(b.iter()).map(|(a_i, b_i)| a_i * b_i).sum();
+    let P = G.iter().zip(a.iter()).map(|(G_i, a_i)| G_i * a_i).sum::<RistrettoPoint>()
+        + H.iter().zip(b.iter()).map(|(H_i, b_i)| H_i * b_i).sum::<RistrettoPoint>()
+        + Q * c;
+
+    let mut transcript = Transcript::new(b"PreludeTest");
+    let proof = InnerProductProof::create(&mut transcript, &Q, &ones, G.clone(), H.clone(), a, b);
+
+    let mut transcript = Transcript::new(b"PreludeTest");
+    assert!(proof.verify_compressed_p(n, &mut transcript, &ones, &P.compress(), &Q, &G, &H).is_ok());
+
+    // A commitment that does not decompress is a format error.
+    let mut transcript = Transcript::new(b"PreludeTest");
+    assert_eq!(
+        proof.verify_compressed_p(n, &mut transcript, &ones, &CompressedRistretto([0xff; 32]), &Q, &G, &H),
+        Err(ProofError::FormatError)
+    );
+}